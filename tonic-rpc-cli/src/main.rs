@@ -0,0 +1,238 @@
+//! Call `tonic-rpc` services from the command line, like `grpcurl` for `tonic-rpc`'s
+//! `serde` based encodings.
+//!
+//! Requests are given as JSON and transcoded to the service's encoding, and responses are
+//! decoded into JSON. This only works for self-describing encodings (`json`, `cbor` and
+//! `messagepack`), since `bincode` can't be decoded without knowing the types involved.
+
+use std::{process, str::FromStr};
+
+use serde_json::Value;
+use tonic::{
+    client::Grpc,
+    codegen::http::uri::PathAndQuery,
+    metadata::{MetadataKey, MetadataValue},
+    transport::{Channel, Endpoint},
+    Request, Status,
+};
+use tonic_rpc::codec::{CborSerdeCodec, Codec, JsonSerdeCodec, MessagePackSerdeCodec, SerdeCodec};
+
+const USAGE: &str = "\
+Usage: tonic-rpc-cli [OPTIONS] <ADDRESS> <PATH> [PAYLOAD]
+
+Call the method at PATH (e.g. /Increment/Increment) of the tonic-rpc service at ADDRESS
+(e.g. http://[::1]:8080) with a JSON PAYLOAD, which defaults to `null`.
+
+Options:
+  --codec <CODEC>       json, cbor or messagepack [default: json]
+  --client-streaming    PAYLOAD is a JSON array of the messages to send
+  --server-streaming    Print each message in the response stream
+  -H, --header <K:V>    Add metadata to the request
+  -h, --help            Print this message";
+
+#[derive(Debug, Clone, Copy)]
+enum CodecName {
+    Json,
+    Cbor,
+    MessagePack,
+}
+
+impl FromStr for CodecName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(CodecName::Json),
+            "cbor" => Ok(CodecName::Cbor),
+            "messagepack" => Ok(CodecName::MessagePack),
+            "bincode" => Err(
+                "bincode is not self-describing, so it can't be transcoded from JSON".to_string(),
+            ),
+            other => Err(format!("Unrecognized codec {}", other)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Args {
+    codec: CodecName,
+    client_streaming: bool,
+    server_streaming: bool,
+    headers: Vec<(String, String)>,
+    address: String,
+    path: String,
+    payload: Value,
+}
+
+/// Parse the command line arguments, or `None` if help was requested.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut codec = CodecName::Json;
+    let mut client_streaming = false;
+    let mut server_streaming = false;
+    let mut headers = Vec::new();
+    let mut positional = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--codec" => {
+                codec = args
+                    .next()
+                    .ok_or("--codec requires a value")?
+                    .parse::<CodecName>()?
+            }
+            "--client-streaming" => client_streaming = true,
+            "--server-streaming" => server_streaming = true,
+            "-H" | "--header" => {
+                let header = args.next().ok_or("--header requires a value")?;
+                let (key, value) = header.split_once(':').ok_or_else(|| {
+                    format!("Expected a header of the form key:value, found {}", header)
+                })?;
+                headers.push((key.trim().to_string(), value.trim().to_string()));
+            }
+            other if other.starts_with('-') => {
+                return Err(format!("Unrecognized option {}", other))
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let (address, path) = match (positional.next(), positional.next()) {
+        (Some(address), Some(path)) => (address, path),
+        _ => return Err(USAGE.to_string()),
+    };
+    let payload = match positional.next() {
+        Some(payload) => serde_json::from_str(&payload)
+            .map_err(|err| format!("Payload is not valid JSON: {}", err))?,
+        None => Value::Null,
+    };
+    if let Some(extra) = positional.next() {
+        return Err(format!("Unexpected argument {}", extra));
+    }
+    if client_streaming && !payload.is_array() {
+        return Err("With --client-streaming the payload must be a JSON array".to_string());
+    }
+
+    Ok(Some(Args {
+        codec,
+        client_streaming,
+        server_streaming,
+        headers,
+        address,
+        path,
+        payload,
+    }))
+}
+
+/// Build the request, attaching the metadata given on the command line.
+fn request<T>(message: T, headers: &[(String, String)]) -> Result<Request<T>, String> {
+    let mut request = Request::new(message);
+    for (key, value) in headers {
+        let key =
+            MetadataKey::from_str(key).map_err(|err| format!("Invalid header {}: {}", key, err))?;
+        let value: MetadataValue<_> = value
+            .parse()
+            .map_err(|err| format!("Invalid header value {}: {}", value, err))?;
+        request.metadata_mut().insert(key, value);
+    }
+    Ok(request)
+}
+
+fn print_status(status: Status) -> String {
+    format!("{:?}: {}", status.code(), status.message())
+}
+
+fn print(value: &Value) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).expect("JSON values can always be printed")
+    );
+}
+
+async fn call<C>(args: Args, channel: Channel, codec: C) -> Result<(), String>
+where
+    C: SerdeCodec + Clone + Send + Sync + 'static,
+{
+    let path = PathAndQuery::from_str(&args.path)
+        .map_err(|err| format!("Invalid path {}: {}", args.path, err))?;
+    let mut client = Grpc::new(channel);
+    client
+        .ready()
+        .await
+        .map_err(|err| format!("Service was not ready: {}", err))?;
+    let codec = Codec::<C, Value, Value>::new(codec);
+
+    match (args.client_streaming, args.server_streaming) {
+        (false, false) => {
+            let request = request(args.payload, &args.headers)?;
+            let response = client
+                .unary(request, path, codec)
+                .await
+                .map_err(print_status)?;
+            print(response.get_ref());
+        }
+        (false, true) => {
+            let request = request(args.payload, &args.headers)?;
+            let mut responses = client
+                .server_streaming(request, path, codec)
+                .await
+                .map_err(print_status)?
+                .into_inner();
+            while let Some(response) = responses.message().await.map_err(print_status)? {
+                print(&response);
+            }
+        }
+        (true, false) => {
+            let messages = tokio_stream::iter(args.payload.as_array().cloned().unwrap_or_default());
+            let request = request(messages, &args.headers)?;
+            let response = client
+                .client_streaming(request, path, codec)
+                .await
+                .map_err(print_status)?;
+            print(response.get_ref());
+        }
+        (true, true) => {
+            let messages = tokio_stream::iter(args.payload.as_array().cloned().unwrap_or_default());
+            let request = request(messages, &args.headers)?;
+            let mut responses = client
+                .streaming(request, path, codec)
+                .await
+                .map_err(print_status)?
+                .into_inner();
+            while let Some(response) = responses.message().await.map_err(print_status)? {
+                print(&response);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run(args: Args) -> Result<(), String> {
+    let channel = Endpoint::from_shared(args.address.clone())
+        .map_err(|err| format!("Invalid address {}: {}", args.address, err))?
+        .connect()
+        .await
+        .map_err(|err| format!("Failed to connect to {}: {}", args.address, err))?;
+    match args.codec {
+        CodecName::Json => call(args, channel, JsonSerdeCodec).await,
+        CodecName::Cbor => call(args, channel, CborSerdeCodec).await,
+        CodecName::MessagePack => call(args, channel, MessagePackSerdeCodec).await,
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let result = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => run(args).await,
+        Ok(None) => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use tokio::{net::TcpListener, process::Command};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::transport::Server;
+use tonic_rpc::tonic_rpc;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[tonic_rpc(cbor)]
+trait Geometry {
+    fn translate(point: Point, by: i32) -> Point;
+
+    #[server_streaming]
+    fn corners(size: i32) -> Point;
+
+    #[client_streaming]
+    fn sum(value: i32) -> i32;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl geometry_server::Geometry for State {
+    type CornersStream = ReceiverStream<Result<Point, tonic::Status>>;
+
+    async fn translate(
+        &self,
+        request: tonic::Request<(Point, i32)>,
+    ) -> Result<tonic::Response<Point>, tonic::Status> {
+        let (Point { x, y }, by) = request.into_inner();
+        Ok(tonic::Response::new(Point {
+            x: x + by,
+            y: y + by,
+        }))
+    }
+
+    async fn corners(
+        &self,
+        request: tonic::Request<i32>,
+    ) -> Result<tonic::Response<Self::CornersStream>, tonic::Status> {
+        let size = request.into_inner();
+        let (tx, rx) = tokio::sync::mpsc::channel(2);
+        tokio::spawn(async move {
+            tx.send(Ok(Point { x: 0, y: 0 })).await.unwrap();
+            tx.send(Ok(Point { x: size, y: size })).await.unwrap();
+        });
+        Ok(tonic::Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn sum(
+        &self,
+        request: tonic::Request<tonic::Streaming<i32>>,
+    ) -> Result<tonic::Response<i32>, tonic::Status> {
+        let mut values = request.into_inner();
+        let mut sum = 0;
+        while let Some(value) = values.message().await? {
+            sum += value;
+        }
+        Ok(tonic::Response::new(sum))
+    }
+}
+
+async fn run_server() -> String {
+    let listener = TcpListener::bind("[::1]:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        Server::builder()
+            .add_service(geometry_server::GeometryServer::new(State))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+    format!("http://{}", addr)
+}
+
+/// Returns whether the command succeeded along with its stdout and stderr.
+async fn cli(args: &[&str]) -> (bool, String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_tonic-rpc-cli"))
+        .args(args)
+        .output()
+        .await
+        .expect("Failed to run tonic-rpc-cli");
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+fn compact(json: &str) -> String {
+    json.lines()
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join("")
+        .replace(": ", ":")
+}
+
+#[tokio::test]
+async fn test_unary() {
+    let addr = run_server().await;
+    let (success, stdout, _) = cli(&[
+        "--codec",
+        "cbor",
+        &addr,
+        "/Geometry/Translate",
+        r#"[{"x": 1, "y": 2}, 10]"#,
+    ])
+    .await;
+    assert!(success);
+    assert_eq!(r#"{"x":11,"y":12}"#, compact(&stdout));
+}
+
+#[tokio::test]
+async fn test_streaming() {
+    let addr = run_server().await;
+    let (success, stdout, _) = cli(&[
+        "--codec",
+        "cbor",
+        "--server-streaming",
+        &addr,
+        "/Geometry/Corners",
+        "3",
+    ])
+    .await;
+    assert!(success);
+    assert_eq!(r#"{"x":0,"y":0}{"x":3,"y":3}"#, compact(&stdout));
+
+    let (success, stdout, _) = cli(&[
+        "--codec",
+        "cbor",
+        "--client-streaming",
+        &addr,
+        "/Geometry/Sum",
+        "[1, 2, 3]",
+    ])
+    .await;
+    assert!(success);
+    assert_eq!("6", compact(&stdout));
+}
+
+#[tokio::test]
+async fn test_errors() {
+    let addr = run_server().await;
+    let (success, _, stderr) = cli(&["--codec", "cbor", &addr, "/Geometry/Missing", "3"]).await;
+    assert!(!success);
+    assert!(stderr.starts_with("Unimplemented"), "{}", stderr);
+
+    let (success, _, stderr) = cli(&["--codec", "bincode", &addr, "/Geometry/Sum", "3"]).await;
+    assert!(!success);
+    assert!(stderr.contains("not self-describing"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn test_help() {
+    for flag in ["-h", "--help"] {
+        let (success, stdout, stderr) = cli(&[flag]).await;
+        assert!(success);
+        assert!(stdout.starts_with("Usage: tonic-rpc-cli"), "{}", stdout);
+        assert!(stderr.is_empty(), "{}", stderr);
+    }
+
+    let (success, _, stderr) = cli(&[]).await;
+    assert!(!success);
+    assert!(stderr.starts_with("Usage: tonic-rpc-cli"), "{}", stderr);
+}
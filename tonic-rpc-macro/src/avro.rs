@@ -0,0 +1,347 @@
+//! Generation of the Avro schemas of services using the `avro` codec, and of the
+//! `tonic_rpc::avro::AvroSchema` implementations of their messages.
+
+use quote::{quote, ToTokens};
+use syn::{Attribute, Data, DeriveInput, Fields, Lit, Meta, NestedMeta};
+
+use crate::RustDefService;
+
+/// Generate a function returning the Avro schemas of the methods of the service.
+pub fn generate(service: &RustDefService) -> proc_macro2::TokenStream {
+    let function = quote::format_ident!(
+        "{}_avro_schemas",
+        heck::ToSnakeCase::to_snake_case(service.name.as_str())
+    );
+    let doc = format!(
+        " The Avro schemas of the methods of the `{}` service.",
+        service.identifier
+    );
+    let methods = service.methods.iter().map(|method| {
+        let path = service.method_path(method);
+        let request = &method.request;
+        let response = &method.response;
+        quote! {
+            ::tonic_rpc::avro::MethodSchemas::new(
+                #path,
+                <#request as ::tonic_rpc::avro::AvroSchema>::schema(),
+                <#response as ::tonic_rpc::avro::AvroSchema>::schema(),
+            )
+        }
+    });
+    quote! {
+        #[doc = #doc]
+        pub fn #function() -> ::tonic_rpc::avro::ServiceSchemas {
+            ::tonic_rpc::avro::ServiceSchemas::new(vec![#( #methods ),*])
+        }
+    }
+}
+
+/// The `#[serde(..)]` options of a container, variant or field which change its schema.
+#[derive(Default)]
+struct SerdeOptions {
+    rename: Option<String>,
+    rename_all: Option<String>,
+    transparent: bool,
+    skip: bool,
+}
+
+/// `serde` options which don't change the schema of a type.
+const IGNORED_OPTIONS: &[&str] = &[
+    "alias",
+    "bound",
+    "borrow",
+    "crate",
+    "default",
+    "deny_unknown_fields",
+    "expecting",
+];
+
+/// The string value of `meta`, which must be either `name = ".."` or
+/// `name(serialize = "..", deserialize = "..")` with the same value for both.
+fn string_value(meta: &Meta) -> syn::Result<String> {
+    let string = |lit: &Lit| match lit {
+        Lit::Str(lit) => Ok(lit.value()),
+        other => Err(syn::Error::new_spanned(other, "Expected a string literal")),
+    };
+    match meta {
+        Meta::NameValue(name_value) => string(&name_value.lit),
+        Meta::List(list) => {
+            let values = list
+                .nested
+                .iter()
+                .map(|nested| match nested {
+                    NestedMeta::Meta(Meta::NameValue(name_value)) => string(&name_value.lit),
+                    other => Err(syn::Error::new_spanned(other, "Expected a string option")),
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            match values.as_slice() {
+                [value] => Ok(value.clone()),
+                [first, second] if first == second => Ok(first.clone()),
+                _ => Err(syn::Error::new_spanned(
+                    meta,
+                    "AvroSchema cannot describe types with different serialized and \
+                     deserialized names",
+                )),
+            }
+        }
+        Meta::Path(_) => Err(syn::Error::new_spanned(meta, "Expected a string option")),
+    }
+}
+
+fn parse_serde_options(attrs: &[Attribute]) -> syn::Result<SerdeOptions> {
+    let mut options = SerdeOptions::default();
+    let mut skip_serializing = false;
+    let mut skip_deserializing = false;
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("serde")) {
+        let nested = match attr.parse_meta()? {
+            Meta::List(list) => list.nested,
+            other => return Err(syn::Error::new_spanned(other, "Expected #[serde(..)]")),
+        };
+        for nested in &nested {
+            let meta = match nested {
+                NestedMeta::Meta(meta) => meta,
+                other => return Err(syn::Error::new_spanned(other, "Expected a serde option")),
+            };
+            let name = meta.path().to_token_stream().to_string();
+            match name.as_str() {
+                "rename" => options.rename = Some(string_value(meta)?),
+                "rename_all" => options.rename_all = Some(string_value(meta)?),
+                "transparent" => options.transparent = true,
+                "skip" => options.skip = true,
+                "skip_serializing" => skip_serializing = true,
+                "skip_deserializing" => skip_deserializing = true,
+                name if IGNORED_OPTIONS.contains(&name) => {}
+                name => {
+                    return Err(syn::Error::new_spanned(
+                        meta,
+                        format!("AvroSchema cannot describe types using #[serde({})]", name),
+                    ))
+                }
+            }
+        }
+    }
+    match (skip_serializing, skip_deserializing) {
+        (true, true) => options.skip = true,
+        (false, false) => {}
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &attrs[0],
+                "AvroSchema cannot describe fields which are only skipped when serializing \
+                 or deserializing",
+            ))
+        }
+    }
+    Ok(options)
+}
+
+/// Apply the `serde` `rename_all` `rule` to the snake case name of a field, as `serde` does.
+fn rename_field(rule: &str, field: &str) -> Option<String> {
+    Some(match rule {
+        "lowercase" | "snake_case" => field.to_string(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => field.to_ascii_uppercase(),
+        "PascalCase" | "camelCase" => {
+            let mut pascal = String::new();
+            let mut capitalize = rule == "PascalCase";
+            for c in field.chars() {
+                if c == '_' {
+                    capitalize = true;
+                } else if capitalize {
+                    pascal.push(c.to_ascii_uppercase());
+                    capitalize = false;
+                } else {
+                    pascal.push(c);
+                }
+            }
+            pascal
+        }
+        "kebab-case" => field.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => field.to_ascii_uppercase().replace('_', "-"),
+        _ => return None,
+    })
+}
+
+/// Apply the `serde` `rename_all` `rule` to the Pascal case name of a variant, as `serde` does.
+fn rename_variant(rule: &str, variant: &str) -> Option<String> {
+    let snake = || {
+        let mut snake = String::new();
+        for (i, c) in variant.char_indices() {
+            if i > 0 && c.is_uppercase() {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        }
+        snake
+    };
+    Some(match rule {
+        "lowercase" => variant.to_ascii_lowercase(),
+        "UPPERCASE" => variant.to_ascii_uppercase(),
+        "PascalCase" => variant.to_string(),
+        "camelCase" => variant[..1].to_ascii_lowercase() + &variant[1..],
+        "snake_case" => snake(),
+        "SCREAMING_SNAKE_CASE" => snake().to_ascii_uppercase(),
+        "kebab-case" => snake().replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => snake().to_ascii_uppercase().replace('_', "-"),
+        _ => return None,
+    })
+}
+
+/// The serialized name of an item named `ident`, following its own `rename` option or the
+/// `rename_all` option of its parent, and checked to be a valid Avro name.
+fn serialized_name(
+    ident: &dyn ToTokens,
+    options: &SerdeOptions,
+    rename_all: Option<&str>,
+    rename: fn(&str, &str) -> Option<String>,
+) -> syn::Result<String> {
+    let name = ident.to_token_stream().to_string();
+    let name = name.trim_start_matches("r#");
+    let name = match (&options.rename, rename_all) {
+        (Some(renamed), _) => renamed.clone(),
+        (None, Some(rule)) => rename(rule, name).ok_or_else(|| {
+            syn::Error::new_spanned(ident, format!("Unknown serde rename rule {}", rule))
+        })?,
+        (None, None) => name.to_string(),
+    };
+    let mut chars = name.chars();
+    let valid = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(syn::Error::new_spanned(
+            ident,
+            format!("{} is not a valid Avro name", name),
+        ));
+    }
+    Ok(name)
+}
+
+/// The schemas of the fields of a struct or enum variant which aren't skipped, with their
+/// serialized names. Tuple fields are named `field0`, `field1`, ...
+fn avro_fields(
+    fields: &Fields,
+    rename_all: Option<&str>,
+) -> syn::Result<Vec<proc_macro2::TokenStream>> {
+    let mut schemas = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let options = parse_serde_options(&field.attrs)?;
+        if options.skip {
+            continue;
+        }
+        let name = match &field.ident {
+            Some(ident) => serialized_name(ident, &options, rename_all, rename_field)?,
+            None => format!("field{}", i),
+        };
+        let ty = &field.ty;
+        schemas.push(quote! {
+            (#name.to_string(), <#ty as ::tonic_rpc::avro::AvroSchema>::schema())
+        });
+    }
+    Ok(schemas)
+}
+
+fn avro_record(name: &str, fields: &[proc_macro2::TokenStream]) -> proc_macro2::TokenStream {
+    quote! {
+        ::tonic_rpc::avro::Schema::Record {
+            name: #name.to_string(),
+            fields: vec![#( #fields ),*],
+        }
+    }
+}
+
+/// The schema of a struct.
+fn struct_schema(
+    name: &str,
+    fields: &Fields,
+    options: &SerdeOptions,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let rename_all = options.rename_all.as_deref();
+    if options.transparent {
+        let mut unskipped = Vec::new();
+        for field in fields {
+            if !parse_serde_options(&field.attrs)?.skip {
+                unskipped.push(&field.ty);
+            }
+        }
+        return match unskipped.as_slice() {
+            [ty] => Ok(quote! { <#ty as ::tonic_rpc::avro::AvroSchema>::schema() }),
+            _ => Err(syn::Error::new_spanned(
+                fields,
+                "#[serde(transparent)] structs must have exactly one field which isn't skipped",
+            )),
+        };
+    }
+    Ok(match fields {
+        Fields::Unit => quote! { ::tonic_rpc::avro::Schema::Null },
+        // Newtype structs are serialized as the type they wrap.
+        Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+            let ty = &unnamed.unnamed[0].ty;
+            quote! { <#ty as ::tonic_rpc::avro::AvroSchema>::schema() }
+        }
+        fields => avro_record(name, &avro_fields(fields, rename_all)?),
+    })
+}
+
+/// Derive `tonic_rpc::avro::AvroSchema` for a struct or enum, matching its `serde`
+/// representation.
+pub fn derive(mut input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let options = parse_serde_options(&input.attrs)?;
+    let name = serialized_name(&input.ident, &options, None, rename_variant)?;
+    let schema = match &input.data {
+        Data::Struct(data) => struct_schema(&name, &data.fields, &options)?,
+        Data::Enum(data) => {
+            let rename_all = options.rename_all.as_deref();
+            let mut symbols = Vec::new();
+            let mut variants = Vec::new();
+            for variant in &data.variants {
+                let variant_options = parse_serde_options(&variant.attrs)?;
+                if variant_options.skip {
+                    return Err(syn::Error::new_spanned(
+                        variant,
+                        "AvroSchema cannot describe enums with skipped variants",
+                    ));
+                }
+                let variant_name =
+                    serialized_name(&variant.ident, &variant_options, rename_all, rename_variant)?;
+                let fields = avro_fields(&variant.fields, variant_options.rename_all.as_deref())?;
+                variants.push(avro_record(&format!("{}{}", name, variant_name), &fields));
+                symbols.push(variant_name);
+            }
+            if data.variants.iter().all(|v| v.fields.is_empty()) {
+                quote! {
+                    ::tonic_rpc::avro::Schema::Enum {
+                        name: #name.to_string(),
+                        symbols: vec![#( #symbols.to_string() ),*],
+                    }
+                }
+            } else {
+                quote! { ::tonic_rpc::avro::Schema::Union(vec![#( #variants ),*]) }
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "AvroSchema cannot be derived for unions",
+            ))
+        }
+    };
+
+    let type_params: Vec<_> = input
+        .generics
+        .type_params()
+        .map(|p| p.ident.clone())
+        .collect();
+    let where_clause = input.generics.make_where_clause();
+    for param in type_params {
+        where_clause
+            .predicates
+            .push(syn::parse_quote! { #param: ::tonic_rpc::avro::AvroSchema });
+    }
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::tonic_rpc::avro::AvroSchema for #ident #ty_generics #where_clause {
+            fn schema() -> ::tonic_rpc::avro::Schema {
+                #schema
+            }
+        }
+    })
+}
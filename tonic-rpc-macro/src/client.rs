@@ -0,0 +1,210 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+use crate::{doc_comments, naive_snake_case, RustDefMethod, RustDefService};
+
+/// Generate a module containing the client of the service.
+///
+/// The client holds a codec of type `C`, which defaults to the codec named in
+/// `#[tonic_rpc(..)]` and can be set with `with_codec`.
+pub(crate) fn generate(service: &RustDefService) -> TokenStream {
+    let client_ident = format_ident!("{}Client", service.name);
+    let client_mod = format_ident!("{}_client", naive_snake_case(&service.name));
+    let default_codec = &service.codec;
+    let methods = service
+        .methods
+        .iter()
+        .map(|method| generate_method(service, method));
+
+    quote! {
+        /// Generated client implementations.
+        pub mod #client_mod {
+            #![allow(
+                unused_variables,
+                dead_code,
+                missing_docs,
+                // will trigger if compression is disabled
+                clippy::let_unit_value,
+            )]
+            use tonic::codegen::*;
+            use tonic::codegen::http::Uri;
+
+            #[derive(Debug, Clone)]
+            pub struct #client_ident<T, C = #default_codec> {
+                inner: tonic::client::Grpc<::tonic_rpc::instrument::Instrumented<T>>,
+                codec: C,
+            }
+
+            impl #client_ident<tonic::transport::Channel> {
+                /// Attempt to create a new client by connecting to a given endpoint.
+                pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+                where
+                    D: std::convert::TryInto<tonic::transport::Endpoint>,
+                    D::Error: Into<StdError>,
+                {
+                    let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+                    Ok(Self::new(conn))
+                }
+
+                ::tonic_rpc::__unix! {
+                    /// Attempt to create a new client by connecting to the Unix domain socket at `path`.
+                    pub async fn connect_unix<P>(path: P) -> Result<Self, tonic::transport::Error>
+                    where
+                        P: AsRef<std::path::Path>,
+                    {
+                        let conn = ::tonic_rpc::transport::unix::connect(path).await?;
+                        Ok(Self::new(conn))
+                    }
+                }
+            }
+
+            impl<T> #client_ident<T>
+            where
+                T: tonic::client::GrpcService<tonic::body::BoxBody>,
+                T::Error: Into<StdError>,
+                T::ResponseBody: Body<Data = Bytes> + Send + 'static,
+                <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+            {
+                pub fn new(inner: T) -> Self {
+                    Self::with_codec(inner, Default::default())
+                }
+
+                pub fn with_origin(inner: T, origin: Uri) -> Self {
+                    let inner = ::tonic_rpc::instrument::Instrumented::new(inner);
+                    let inner = tonic::client::Grpc::with_origin(inner, origin);
+                    Self {
+                        inner,
+                        codec: Default::default(),
+                    }
+                }
+
+                pub fn with_interceptor<F>(inner: T, interceptor: F) -> #client_ident<InterceptedService<T, F>>
+                where
+                    F: tonic::service::Interceptor,
+                    T::ResponseBody: Default,
+                    T: tonic::codegen::Service<
+                        http::Request<tonic::body::BoxBody>,
+                        Response = http::Response<<T as tonic::client::GrpcService<tonic::body::BoxBody>>::ResponseBody>
+                    >,
+                    <T as tonic::codegen::Service<http::Request<tonic::body::BoxBody>>>::Error: Into<StdError> + Send + Sync,
+                {
+                    #client_ident::new(InterceptedService::new(inner, interceptor))
+                }
+            }
+
+            impl<T, C> #client_ident<T, C>
+            where
+                T: tonic::client::GrpcService<tonic::body::BoxBody>,
+                T::Error: Into<StdError>,
+                T::ResponseBody: Body<Data = Bytes> + Send + 'static,
+                <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+            {
+                /// Create a client encoding messages with `codec`, e.g. a codec configured
+                /// with a key or options.
+                pub fn with_codec(inner: T, codec: C) -> Self {
+                    let inner = tonic::client::Grpc::new(::tonic_rpc::instrument::Instrumented::new(inner));
+                    Self { inner, codec }
+                }
+
+                /// Compress requests with the given encoding.
+                ///
+                /// This requires the server to support it otherwise it might respond with an
+                /// error.
+                #[must_use]
+                pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+                    self.inner = self.inner.send_compressed(encoding);
+                    self
+                }
+
+                /// Enable decompressing responses.
+                #[must_use]
+                pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+                    self.inner = self.inner.accept_compressed(encoding);
+                    self
+                }
+
+                #( #methods )*
+            }
+        }
+    }
+}
+
+fn generate_method(service: &RustDefService, method: &RustDefMethod) -> TokenStream {
+    let ident = format_ident!("{}", method.name);
+    let path = service.method_path(method);
+    let doc = doc_comments(&method.doc_comments);
+    let (request, response) = method.client_request_response();
+    let codec = method.make_codec(quote! { self.codec }, &path, false);
+    let codec_bound = method.codec_bound(false);
+    let span = method.span(service, false);
+    let metrics = method.metrics(service, false);
+
+    let request_type = if method.client_streaming {
+        quote! { impl tonic::IntoStreamingRequest<Message = #request> }
+    } else if method.client_encodes_request() {
+        let borrowed_request = &method.generated_borrowed_request;
+        quote! { impl tonic::IntoRequest<super::#borrowed_request<'_>> }
+    } else {
+        quote! { impl tonic::IntoRequest<#request> }
+    };
+    // Borrowed arguments are serialized before the call, as `tonic` only sends `'static`
+    // messages.
+    let encode_request = if method.client_encodes_request() {
+        let codec = method.codec_value(quote! { self.codec });
+        Some(quote! {
+            let (metadata, extensions, message) = request.into_request().into_parts();
+            // The request is the first message sent by the call.
+            let position = ::tonic_rpc::codec::MessagePosition {
+                path: #path,
+                direction: ::tonic_rpc::codec::Direction::Request,
+                sequence: 0,
+            };
+            let message = ::tonic_rpc::codec::Encoded::<#request, _>::new_at(#codec, &message, &position)?;
+            let request = tonic::Request::from_parts(metadata, extensions, message);
+        })
+    } else {
+        None
+    };
+    let request_value = if method.client_streaming {
+        quote! { request.into_streaming_request() }
+    } else if encode_request.is_some() {
+        quote! { request }
+    } else {
+        quote! { request.into_request() }
+    };
+    let response_type = if method.server_streaming {
+        quote! { tonic::codec::Streaming<#response> }
+    } else {
+        quote! { #response }
+    };
+    let call = match (method.client_streaming, method.server_streaming) {
+        (false, false) => quote! { self.inner.unary(request, path, codec) },
+        (false, true) => quote! { self.inner.server_streaming(request, path, codec) },
+        (true, false) => quote! { self.inner.client_streaming(request, path, codec) },
+        (true, true) => quote! { self.inner.streaming(request, path, codec) },
+    };
+
+    quote! {
+        #doc
+        pub async fn #ident(
+            &mut self,
+            request: #request_type,
+        ) -> Result<tonic::Response<#response_type>, tonic::Status>
+        where
+            #codec_bound
+        {
+            ::tonic_rpc::instrument::client(#span, #metrics, |end| async move {
+                self.inner.ready().await.map_err(|e| {
+                    tonic::Status::new(tonic::Code::Unknown, format!("Service was not ready: {}", e.into()))
+                })?;
+                #encode_request
+                let mut request = ::tonic_rpc::instrument::inject(#request_value);
+                request.extensions_mut().insert(end);
+                let codec = #codec;
+                let path = http::uri::PathAndQuery::from_static(#path);
+                #call.await
+            })
+            .await
+        }
+    }
+}
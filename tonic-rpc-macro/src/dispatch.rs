@@ -0,0 +1,95 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+use crate::{doc_comments, naive_snake_case, RustDefService};
+
+/// Generate a module containing an enum of the calls to the service, an enum of their
+/// replies and a `dispatch` function handling a call with an implementation of the server
+/// trait, so that requests can be routed over other channels than gRPC.
+///
+/// Only unary methods are included, as streams can't be serialized, and methods sending
+/// `Encoded` messages are left out as their replies can't be deserialized.
+pub(crate) fn generate(service: &RustDefService) -> TokenStream {
+    let dispatch_mod = format_ident!("{}_dispatch", naive_snake_case(&service.name));
+    let server_mod = format_ident!("{}_server", naive_snake_case(&service.name));
+    let server_trait = format_ident!("{}", service.name);
+    let call_ident = format_ident!("{}Call", service.name);
+    let reply_ident = format_ident!("{}Reply", service.name);
+    let call_doc = format!(" A call to a method of the `{}` service.", service.name);
+    let reply_doc = format!(
+        " The reply to a `{}Call`, with a variant for each variant of the call.",
+        service.name
+    );
+    let methods: Vec<_> = service
+        .methods
+        .iter()
+        .filter(|method| !method.client_streaming && !method.server_streaming && !method.encoded)
+        .collect();
+
+    let variants: Vec<_> = methods
+        .iter()
+        .map(|method| {
+            format_ident!(
+                "{}",
+                heck::ToUpperCamelCase::to_upper_camel_case(method.name.as_str())
+            )
+        })
+        .collect();
+    let call_variants = methods.iter().zip(&variants).map(|(method, variant)| {
+        let doc = doc_comments(&method.doc_comments);
+        let request = &method.generated_request;
+        quote! {
+            #doc
+            #variant(super::#request)
+        }
+    });
+    let reply_variants = methods.iter().zip(&variants).map(|(method, variant)| {
+        let response = &method.generated_response;
+        quote! { #variant(super::#response) }
+    });
+    let arms = methods.iter().zip(&variants).map(|(method, variant)| {
+        let name = format_ident!("{}", method.name);
+        quote! {
+            #call_ident::#variant(request) => {
+                super::#server_mod::#server_trait::#name(server, tonic::Request::new(request))
+                    .await
+                    .map(|response| #reply_ident::#variant(response.into_inner()))
+            }
+        }
+    });
+
+    quote! {
+        /// Generated call and reply enums, and a dispatcher handling calls with a server implementation.
+        pub mod #dispatch_mod {
+            #![allow(
+                unused_variables,
+                dead_code,
+                missing_docs,
+            )]
+
+            #[doc = #call_doc]
+            #[derive(::tonic_rpc::serde::Serialize, ::tonic_rpc::serde::Deserialize)]
+            #[serde(crate = "::tonic_rpc::serde")]
+            pub enum #call_ident {
+                #( #call_variants, )*
+            }
+
+            #[doc = #reply_doc]
+            #[derive(::tonic_rpc::serde::Serialize, ::tonic_rpc::serde::Deserialize)]
+            #[serde(crate = "::tonic_rpc::serde")]
+            pub enum #reply_ident {
+                #( #reply_variants, )*
+            }
+
+            /// Handle `call` with the method of `server` it names.
+            pub async fn dispatch<T: super::#server_mod::#server_trait>(
+                server: &T,
+                call: #call_ident,
+            ) -> Result<#reply_ident, tonic::Status> {
+                match call {
+                    #( #arms )*
+                }
+            }
+        }
+    }
+}
@@ -1,187 +1,556 @@
 use proc_macro::TokenStream;
 use quote::{quote, ToTokens};
 use syn::{
-    parse_macro_input, punctuated::Pair, FnArg, ItemTrait, ReturnType, TraitItem, TraitItemMethod,
-    Type,
+    parse_macro_input, punctuated::Pair, AttributeArgs, DeriveInput, FnArg, ItemTrait, Lit, Meta,
+    NestedMeta, Pat, ReturnType, TraitItem, TraitItemMethod, Type,
 };
-use tonic_build::{Method, Service};
+
+mod avro;
+mod client;
+mod dispatch;
+mod mirror;
+mod proto;
+mod server;
 
 struct RustDefMethod {
     pub name: String,
     pub identifier: String,
+    /// The `tonic_rpc::codec::Compression` applied to the messages of this method, if any.
+    pub compression: Option<proc_macro2::TokenStream>,
+    /// Whether messages are encoded with a `serde` based codec, which can also send
+    /// `tonic_rpc::codec::Encoded` messages.
+    pub serde: bool,
     pub client_streaming: bool,
     pub server_streaming: bool,
+    /// The arguments of the method, with borrowed arguments replaced by their owned types.
+    pub args: Vec<(String, proc_macro2::TokenStream)>,
+    /// The arguments of the method as they are declared.
+    pub declared_args: Vec<(String, Type)>,
+    /// Whether the request or response is declared as a `tonic_rpc::codec::Encoded` message,
+    /// which the client and server see as different types.
+    pub encoded: bool,
     pub request: proc_macro2::TokenStream,
+    /// The request type declared by the method if any of its arguments are borrowed, with
+    /// the lifetime `'a`. Clients accept this type and serialize it before sending it.
+    pub borrowed_request: Option<proc_macro2::TokenStream>,
     pub response: proc_macro2::TokenStream,
     pub generated_request: syn::Ident,
+    pub generated_borrowed_request: syn::Ident,
     pub generated_response: syn::Ident,
     pub doc_comments: Vec<String>,
 }
 
 impl RustDefMethod {
-    fn name(&self) -> &str {
-        &self.name
+    /// The Rust name of the method in `UpperCamelCase`, used to name the generated items of
+    /// the method. Unlike the wire name, this is always a valid identifier.
+    fn camel_case_name(&self) -> String {
+        heck::ToUpperCamelCase::to_upper_camel_case(self.name.as_str())
     }
-    fn identifier(&self) -> &str {
-        &self.identifier
+
+    /// The associated type holding the response stream of a `#[server_streaming]` method.
+    fn stream_type(&self) -> syn::Ident {
+        quote::format_ident!("{}Stream", self.camel_case_name())
     }
-    fn comment(&self) -> &[String] {
-        &self.doc_comments
+
+    /// The request and response types, as seen from the generated client and server modules.
+    fn request_response_name(&self) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        let request = &self.generated_request;
+        let response = &self.generated_response;
+        (quote! {super::#request}, quote! {super::#response})
     }
-    fn client_streaming(&self) -> bool {
-        self.client_streaming
+
+    /// The type received for a message declared as `ty`, which differs from `ty` for
+    /// `Encoded` messages.
+    fn decoded(&self, ty: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        if self.serde {
+            quote! { <#ty as ::tonic_rpc::codec::EncodeMessage>::Decoded }
+        } else {
+            ty
+        }
     }
-    fn server_streaming(&self) -> bool {
-        self.server_streaming
+
+    /// The request and response types received by the server.
+    fn server_request_response(&self) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        let (request, response) = self.request_response_name();
+        (self.decoded(request), response)
     }
-    fn request_response_name(
-        &self,
-        _: &str,
-    ) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
-        let request = self.generated_request.clone();
-        let response = self.generated_response.clone();
-        (quote! {super::#request}, quote! {super::#response})
+
+    /// The request and response types sent and received by the client.
+    fn client_request_response(&self) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        let (request, response) = self.request_response_name();
+        (request, self.decoded(response))
     }
-}
 
-trait RequestResponseTypes {
-    fn generated_request(&self) -> &proc_macro2::Ident;
-    fn generated_response(&self) -> &proc_macro2::Ident;
-    fn request(&self) -> &proc_macro2::TokenStream;
-    fn response(&self) -> &proc_macro2::TokenStream;
-}
+    /// Whether the generated client accepts borrowed arguments, which it serializes into an
+    /// `Encoded` request since `tonic` only sends `'static` messages. Streamed requests are
+    /// always sent as owned values.
+    fn client_encodes_request(&self) -> bool {
+        self.borrowed_request.is_some() && !self.client_streaming
+    }
 
-macro_rules! method_impl {
-    ($name:ident, $codec:expr) => {
-        struct $name(RustDefMethod);
+    /// The types encoded and decoded by the codec of this method: clients encode
+    /// requests and decode responses, servers do the opposite.
+    fn codec_messages(&self, server: bool) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        if server {
+            let (request, response) = self.server_request_response();
+            (response, request)
+        } else if self.client_encodes_request() {
+            let (request, response) = self.client_request_response();
+            let codec_type = self.codec_type();
+            (
+                quote! { ::tonic_rpc::codec::Encoded<#request, #codec_type> },
+                response,
+            )
+        } else {
+            self.client_request_response()
+        }
+    }
 
-        impl From<RustDefMethod> for $name {
-            fn from(inner: RustDefMethod) -> Self {
-                $name(inner)
-            }
+    /// The type of the codec used by this method when the generated client or server holds
+    /// a codec of type `C`.
+    fn codec_type(&self) -> proc_macro2::TokenStream {
+        match &self.compression {
+            Some(compression) => quote! { ::tonic_rpc::codec::Compressed<C, #compression> },
+            None => quote! { C },
         }
+    }
 
-        impl RequestResponseTypes for $name {
-            fn generated_request(&self) -> &proc_macro2::Ident {
-                &self.0.generated_request
-            }
-            fn generated_response(&self) -> &proc_macro2::Ident {
-                &self.0.generated_response
-            }
-            fn request(&self) -> &proc_macro2::TokenStream {
-                &self.0.request
-            }
-            fn response(&self) -> &proc_macro2::TokenStream {
-                &self.0.response
-            }
+    /// A reference to the codec used by this method, built from `codec`, the codec held by
+    /// a generated client or server.
+    fn codec_value(&self, codec: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        match &self.compression {
+            Some(compression) => quote! {
+                &::tonic_rpc::codec::Compressed::new(
+                    ::std::clone::Clone::clone(&#codec),
+                    <#compression as ::std::default::Default>::default(),
+                )
+            },
+            None => quote! { &#codec },
         }
+    }
 
-        impl Method for $name {
-            type Comment = String;
+    /// Build the `tonic` codec of this method, whose path is `path`, from `codec`, the codec
+    /// held by a generated client or server.
+    fn make_codec(
+        &self,
+        codec: proc_macro2::TokenStream,
+        path: &str,
+        server: bool,
+    ) -> proc_macro2::TokenStream {
+        let (encode, decode) = self.codec_messages(server);
+        let codec = self.codec_value(codec);
+        quote! {
+            ::tonic_rpc::codec::MakeCodec::<#encode, #decode>::make_method_codec(#codec, #path, #server)
+        }
+    }
 
-            fn codec_path(&self) -> &str {
-                $codec
-            }
-            fn name(&self) -> &str {
-                self.0.name()
-            }
-            fn identifier(&self) -> &str {
-                self.0.identifier()
-            }
-            fn comment(&self) -> &[Self::Comment] {
-                self.0.comment()
-            }
-            fn client_streaming(&self) -> bool {
-                self.0.client_streaming()
-            }
-            fn server_streaming(&self) -> bool {
-                self.0.server_streaming()
-            }
-            fn request_response_name(
-                &self,
-                s: &str,
-                _compile_well_known_types: bool,
-            ) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
-                self.0.request_response_name(s)
-            }
+    /// The span instrumenting the calls to this method of `service`, on the generated server
+    /// if `server` and on the generated client otherwise.
+    fn span(&self, service: &RustDefService, server: bool) -> proc_macro2::TokenStream {
+        let name = format!("{}/{}", service.name, self.identifier);
+        let kind = match (self.client_streaming, self.server_streaming) {
+            (false, false) => "unary",
+            (false, true) => "server_streaming",
+            (true, false) => "client_streaming",
+            (true, true) => "bidi_streaming",
+        };
+        let codec_type = self.codec_type();
+        let span = if server {
+            quote! { ::tonic_rpc::__server_span }
+        } else {
+            quote! { ::tonic_rpc::__client_span }
+        };
+        quote! { #span!(#name, #kind, ::std::any::type_name::<#codec_type>()) }
+    }
+
+    /// The metrics of the calls of this method by a generated client or server.
+    fn metrics(&self, service: &RustDefService, server: bool) -> proc_macro2::TokenStream {
+        let service_name = &service.name;
+        let method_name = &self.identifier;
+        let codec_type = self.codec_type();
+        let side = if server {
+            quote! { server }
+        } else {
+            quote! { client }
+        };
+        quote! {
+            ::tonic_rpc::instrument::Metrics::#side(
+                #service_name,
+                #method_name,
+                ::std::any::type_name::<#codec_type>(),
+            )
         }
-    };
-}
+    }
 
-method_impl!(JsonMethod, "::tonic_rpc::codec::JsonCodec");
-method_impl!(BincodeMethod, "::tonic_rpc::codec::BincodeCodec");
-method_impl!(CborMethod, "::tonic_rpc::codec::CborCodec");
-method_impl!(MessagePackMethod, "::tonic_rpc::codec::MessagePackCodec");
+    /// The bound on the codec type `C` of a generated client or server required by `make_codec`.
+    fn codec_bound(&self, server: bool) -> proc_macro2::TokenStream {
+        let (encode, decode) = self.codec_messages(server);
+        let codec_type = self.codec_type();
+        let clone = self
+            .compression
+            .as_ref()
+            .map(|_| quote! { C: ::std::clone::Clone, });
+        let serde = if !server && self.client_encodes_request() {
+            Some(quote! { C: 'static, #codec_type: ::tonic_rpc::codec::SerdeCodec, })
+        } else {
+            None
+        };
+        quote! {
+            #clone
+            #serde
+            #codec_type: ::tonic_rpc::codec::MakeCodec<#encode, #decode>,
+        }
+    }
+}
 
-struct RustDefService<T> {
+struct RustDefService {
     pub name: String,
     pub package: String,
     pub identifier: String,
-    pub methods: Vec<T>,
+    /// The codec used by the generated client and server unless another one is given
+    /// to `with_codec`.
+    pub codec: proc_macro2::TokenStream,
+    pub methods: Vec<RustDefMethod>,
 }
 
-macro_rules! service_impl {
-    ($name:ident, $codec:expr) => {
-        impl Service for RustDefService<$name> {
-            type Comment = String;
-            type Method = $name;
+impl RustDefService {
+    /// The name of the service including its package, e.g. `package.Service`.
+    fn path(&self) -> String {
+        if self.package.is_empty() {
+            self.identifier.clone()
+        } else {
+            format!("{}.{}", self.package, self.identifier)
+        }
+    }
 
-            fn name(&self) -> &str {
-                &self.name
-            }
-            fn package(&self) -> &str {
-                &self.package
+    /// The path of the gRPC endpoint of `method`, e.g. `/package.Service/Method`.
+    fn method_path(&self, method: &RustDefMethod) -> String {
+        format!("/{}/{}", self.path(), method.identifier)
+    }
+}
+
+/// The text of a `#[doc = ".."]` attribute.
+fn doc_comment(attr: &syn::Attribute) -> Option<String> {
+    if !attr.path.is_ident("doc") {
+        return None;
+    }
+    match attr.parse_meta() {
+        Ok(Meta::NameValue(syn::MetaNameValue {
+            lit: Lit::Str(lit), ..
+        })) => Some(lit.value()),
+        _ => None,
+    }
+}
+
+fn doc_comments(comments: &[String]) -> proc_macro2::TokenStream {
+    quote! { #( #[doc = #comments] )* }
+}
+
+/// The snake case name used for the generated client and server modules, inserting an
+/// underscore before each uppercase letter, as `tonic-build` does.
+fn naive_snake_case(name: &str) -> String {
+    let mut s = String::new();
+    let mut it = name.chars().peekable();
+    while let Some(x) = it.next() {
+        s.push(x.to_ascii_lowercase());
+        if let Some(y) = it.peek() {
+            if y.is_uppercase() {
+                s.push('_');
             }
-            fn identifier(&self) -> &str {
-                &self.identifier
+        }
+    }
+    s
+}
+
+/// Options given to the `#[tonic_rpc(..)]` attribute.
+struct ServiceOptions {
+    codec: String,
+    package: String,
+    name: Option<String>,
+    compress: Option<syn::LitStr>,
+}
+
+fn string_option(name_value: syn::MetaNameValue) -> String {
+    lit_str_option(name_value).value()
+}
+
+fn lit_str_option(name_value: syn::MetaNameValue) -> syn::LitStr {
+    match name_value.lit {
+        Lit::Str(lit) => lit,
+        other => panic!(
+            "The tonic_rpc option {} must be a string literal, found {}",
+            name_value.path.to_token_stream(),
+            other.to_token_stream()
+        ),
+    }
+}
+
+fn parse_service_options(args: AttributeArgs) -> ServiceOptions {
+    let mut args = args.into_iter();
+    let codec = match args.next() {
+        Some(NestedMeta::Meta(Meta::Path(path))) => path.to_token_stream().to_string(),
+        Some(other) => panic!(
+            "Expected a codec as the first tonic_rpc option, found {}",
+            other.to_token_stream()
+        ),
+        None => panic!("No tonic_rpc codec given"),
+    };
+    let mut package = String::new();
+    let mut name = None;
+    let mut compress = None;
+
+    for arg in args {
+        match arg {
+            NestedMeta::Meta(Meta::NameValue(name_value))
+                if name_value.path.is_ident("package") =>
+            {
+                package = string_option(name_value)
             }
-            fn comment(&self) -> &[String] {
-                &[]
+            NestedMeta::Meta(Meta::NameValue(name_value)) if name_value.path.is_ident("name") => {
+                name = Some(string_option(name_value))
             }
-            fn methods(&self) -> &[Self::Method] {
-                &self.methods
+            NestedMeta::Meta(Meta::NameValue(name_value))
+                if name_value.path.is_ident("compress") =>
+            {
+                compress = Some(lit_str_option(name_value))
             }
+            other => panic!("Unrecognized tonic_rpc option {}", other.to_token_stream()),
         }
-    };
+    }
+
+    ServiceOptions {
+        codec,
+        package,
+        name,
+        compress,
+    }
 }
 
-service_impl!(JsonMethod, "::tonic_rpc::codec::JsonCodec");
-service_impl!(BincodeMethod, "::tonic_rpc::codec::BincodeCodec");
-service_impl!(CborMethod, "::tonic_rpc::codec::CborCodec");
-service_impl!(MessagePackMethod, "::tonic_rpc::codec::MessagePackCodec");
+/// Options given by the attributes on a method of the service trait.
+#[derive(Default)]
+struct MethodOptions {
+    server_streaming: bool,
+    client_streaming: bool,
+    doc_comments: Vec<String>,
+    name: Option<String>,
+    compress: Option<syn::LitStr>,
+}
 
-/// Return value is `(server_streaming, client_streaming, doc_comments)`.
-fn parse_attributes(attributes: Vec<syn::Attribute>) -> (bool, bool, Vec<String>) {
-    let mut server_streaming = false;
-    let mut client_streaming = false;
-    let mut doc_comments = Vec::new();
+fn parse_attributes(attributes: Vec<syn::Attribute>) -> MethodOptions {
+    let mut options = MethodOptions::default();
 
     for attr in attributes {
         if attr.path.is_ident("server_streaming") {
-            server_streaming = true;
+            options.server_streaming = true;
         } else if attr.path.is_ident("client_streaming") {
-            client_streaming = true;
+            options.client_streaming = true;
         } else if attr.path.is_ident("doc") {
-            if let Some(comment) = attr
-                .tokens
-                .to_string()
-                .strip_prefix("= \"")
-                .and_then(|c| c.strip_suffix('\"'))
-            {
-                doc_comments.push(comment.to_string())
+            options.doc_comments.extend(doc_comment(&attr));
+        } else if attr.path.is_ident("rpc") {
+            let nested = match attr.parse_meta() {
+                Ok(Meta::List(list)) => list.nested,
+                _ => panic!("Expected #[rpc(..)], found {}", attr.to_token_stream()),
+            };
+            for meta in nested {
+                match meta {
+                    NestedMeta::Meta(Meta::NameValue(name_value))
+                        if name_value.path.is_ident("name") =>
+                    {
+                        options.name = Some(string_option(name_value))
+                    }
+                    NestedMeta::Meta(Meta::NameValue(name_value))
+                        if name_value.path.is_ident("compress") =>
+                    {
+                        options.compress = Some(lit_str_option(name_value))
+                    }
+                    other => panic!("Unrecognized rpc option {}", other.to_token_stream()),
+                }
             }
         } else {
             panic!("Attribute {:?} is not supported on tonic-rpc methods", attr)
         }
     }
 
-    (server_streaming, client_streaming, doc_comments)
+    options
+}
+
+/// The type of the codec named by the first `#[tonic_rpc(..)]` option.
+fn codec_type(codec: &str) -> proc_macro2::TokenStream {
+    match codec {
+        "avro" => quote! { ::tonic_rpc::codec::Avro },
+        "bincode" => quote! { ::tonic_rpc::codec::BincodeSerdeCodec },
+        "bson" => quote! { ::tonic_rpc::codec::BsonSerdeCodec },
+        "cbor" => quote! { ::tonic_rpc::codec::CborSerdeCodec },
+        "cbor_canonical" => quote! { ::tonic_rpc::codec::CanonicalCborSerdeCodec },
+        "json" => quote! { ::tonic_rpc::codec::JsonSerdeCodec },
+        "messagepack" => quote! { ::tonic_rpc::codec::MessagePackSerdeCodec },
+        "postcard" => quote! { ::tonic_rpc::codec::PostcardSerdeCodec },
+        "prost" => quote! { ::tonic_rpc::codec::Prost },
+        "rkyv" => quote! { ::tonic_rpc::codec::Rkyv },
+        "ron" => quote! { ::tonic_rpc::codec::RonSerdeCodec },
+        other => panic!("Unrecognized tonic_rpc codec {}", other),
+    }
+}
+
+/// Whether `codec` is a `tonic_rpc::codec::SerdeCodec`, which can compress messages and
+/// send `tonic_rpc::codec::Encoded` messages. The other codecs build their own `tonic`
+/// codec for each method.
+fn is_serde_codec(codec: &str) -> bool {
+    !["avro", "prost", "rkyv"].contains(&codec)
+}
+
+/// The type compressing the messages of the `serde` based `codec` with `compression`,
+/// which is either `zstd`, `lz4`, `snappy` or the path to a type implementing
+/// `tonic_rpc::codec::Compression`.
+fn compression_type(
+    codec: &str,
+    compression: &syn::LitStr,
+) -> syn::Result<Option<proc_macro2::TokenStream>> {
+    let value = compression.value();
+    if value == "none" {
+        return Ok(None);
+    }
+    if !is_serde_codec(codec) {
+        return Err(syn::Error::new_spanned(
+            compression,
+            format!("Messages encoded with {} cannot be compressed", codec),
+        ));
+    }
+    let unknown = || {
+        syn::Error::new_spanned(
+            compression,
+            format!(
+                "Unknown compression {}: expected zstd, lz4, snappy, none or the path to a type \
+                 implementing tonic_rpc::codec::Compression",
+                value
+            ),
+        )
+    };
+    Ok(Some(match value.as_str() {
+        "zstd" => quote! { ::tonic_rpc::codec::Zstd },
+        "lz4" => quote! { ::tonic_rpc::codec::Lz4 },
+        "snappy" => quote! { ::tonic_rpc::codec::Snappy },
+        path => {
+            let ty = compression.parse::<Type>().map_err(|_| unknown())?;
+            // A lowercase name, such as a misspelled or unsupported algorithm, isn't a type.
+            if path.starts_with(|c: char| c.is_ascii_lowercase()) && !path.contains("::") {
+                return Err(unknown());
+            }
+            ty.to_token_stream()
+        }
+    }))
+}
+
+/// The request type of a method taking arguments of types `args`: the type of its argument
+/// if it has one, or a tuple of the types of its arguments otherwise.
+fn request_type(
+    mut args: impl ExactSizeIterator<Item = proc_macro2::TokenStream>,
+) -> proc_macro2::TokenStream {
+    if args.len() == 1 {
+        return args.next().unwrap();
+    }
+    let tuple_fields: proc_macro2::TokenStream =
+        itertools::Itertools::intersperse(args, quote! {,}).collect();
+    quote! { ( #tuple_fields )}
 }
 
-fn make_method<T: From<RustDefMethod>>(method: TraitItemMethod, trait_name: &str) -> T {
-    fn extract_arg<P>(arg: Pair<FnArg, P>) -> Box<Type> {
+/// The owned type sent for an argument of type `ty`: `&str` is sent as a `String`, `&[T]`
+/// as a `Vec<T>` and any other `&T` as a `T`. Borrows nested in the type, e.g. `Option<&str>`,
+/// are rejected since they can't be converted to owned values.
+fn owned_type(ty: &Type) -> syn::Result<Type> {
+    let owned = match ty {
+        Type::Reference(reference) => match &*reference.elem {
+            Type::Path(path) if path.qself.is_none() && path.path.is_ident("str") => {
+                syn::parse_quote!(::std::string::String)
+            }
+            Type::Slice(slice) => {
+                let elem = &slice.elem;
+                syn::parse_quote!(::std::vec::Vec<#elem>)
+            }
+            elem => elem.clone(),
+        },
+        ty => ty.clone(),
+    };
+    if is_borrowed(&owned) {
+        return Err(syn::Error::new_spanned(
+            ty,
+            "tonic_rpc only supports borrowed arguments of the form `&T`, \
+             where `T` doesn't borrow, e.g. `&str` or `&[u8]`; use an owned type instead",
+        ));
+    }
+    Ok(owned)
+}
+
+/// Whether `ty` contains a reference or a lifetime.
+fn is_borrowed(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(_) => true,
+        Type::Array(array) => is_borrowed(&array.elem),
+        Type::Slice(slice) => is_borrowed(&slice.elem),
+        Type::Paren(paren) => is_borrowed(&paren.elem),
+        Type::Group(group) => is_borrowed(&group.elem),
+        Type::Ptr(ptr) => is_borrowed(&ptr.elem),
+        Type::Tuple(tuple) => tuple.elems.iter().any(is_borrowed),
+        Type::Path(path) => {
+            path.qself
+                .as_ref()
+                .is_some_and(|qself| is_borrowed(&qself.ty))
+                || path
+                    .path
+                    .segments
+                    .iter()
+                    .any(|segment| match &segment.arguments {
+                        syn::PathArguments::AngleBracketed(args) => {
+                            args.args.iter().any(|arg| match arg {
+                                syn::GenericArgument::Lifetime(_) => true,
+                                syn::GenericArgument::Type(ty) => is_borrowed(ty),
+                                _ => false,
+                            })
+                        }
+                        _ => false,
+                    })
+        }
+        Type::TraitObject(_) | Type::ImplTrait(_) => true,
+        _ => false,
+    }
+}
+
+/// Whether `ty` names a `tonic_rpc::codec::Encoded` message. The type must be written as
+/// `tonic_rpc::codec::Encoded` or `codec::Encoded`, so that a message type of the service
+/// named `Encoded` isn't mistaken for it.
+fn is_encoded(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => {
+            let segments: Vec<_> = path
+                .path
+                .segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect();
+            let segments: Vec<_> = segments.iter().map(String::as_str).collect();
+            matches!(
+                segments.as_slice(),
+                ["codec", "Encoded"] | ["tonic_rpc", "codec", "Encoded"]
+            )
+        }
+        _ => false,
+    }
+}
+
+fn make_method(
+    method: TraitItemMethod,
+    trait_name: &str,
+    service: &ServiceOptions,
+) -> syn::Result<RustDefMethod> {
+    fn extract_arg<P>(arg: Pair<FnArg, P>) -> (String, Box<Type>) {
         match arg {
-            Pair::Punctuated(FnArg::Typed(pat), _) | Pair::End(FnArg::Typed(pat)) => pat.ty,
+            Pair::Punctuated(FnArg::Typed(pat), _) | Pair::End(FnArg::Typed(pat)) => {
+                let name = match *pat.pat {
+                    Pat::Ident(ident) => ident.ident.to_string(),
+                    other => other.to_token_stream().to_string(),
+                };
+                (name, pat.ty)
+            }
             Pair::Punctuated(FnArg::Receiver(rec), _) | Pair::End(FnArg::Receiver(rec)) => panic!(
                 "Invalid RPC argument. 'self' arguments are not allowed: {}",
                 rec.to_token_stream()
@@ -190,99 +559,184 @@ fn make_method<T: From<RustDefMethod>>(method: TraitItemMethod, trait_name: &str
     }
 
     let name = method.sig.ident.to_string();
-    let (server_streaming, client_streaming, doc_comments) = parse_attributes(method.attrs);
-
-    let args: Vec<_> = method.sig.inputs.into_pairs().map(extract_arg).collect();
-    let request = match args.len() {
-        1 => args[0].to_token_stream(),
-        _ => {
-            let tuple_fields: proc_macro2::TokenStream = itertools::Itertools::intersperse(
-                args.into_iter().map(|t| t.to_token_stream()),
-                quote! {,},
-            )
-            .collect();
-            quote! { ( #tuple_fields )}
+    let options = parse_attributes(method.attrs);
+    let compression = options
+        .compress
+        .as_ref()
+        .or(service.compress.as_ref())
+        .map(|compression| compression_type(&service.codec, compression))
+        .transpose()?
+        .flatten();
+
+    let declared_args: Vec<_> = method.sig.inputs.into_pairs().map(extract_arg).collect();
+    let borrowed_request = if declared_args
+        .iter()
+        .any(|(_, ty)| matches!(**ty, Type::Reference(_)))
+    {
+        if !is_serde_codec(&service.codec) {
+            panic!(
+                "Invalid RPC argument. Borrowed arguments are only supported by serde codecs, not {}: {}",
+                service.codec, name
+            );
         }
+        let lifetime: syn::Lifetime = syn::parse_quote!('a);
+        Some(request_type(declared_args.iter().map(|(_, ty)| {
+            let mut ty = (**ty).clone();
+            if let Type::Reference(reference) = &mut ty {
+                reference.lifetime = Some(lifetime.clone());
+            }
+            ty.to_token_stream()
+        })))
+    } else {
+        None
     };
+    let args = declared_args
+        .iter()
+        .map(|(name, ty)| Ok((name.clone(), owned_type(ty)?.to_token_stream())))
+        .collect::<syn::Result<Vec<_>>>()?;
+    let request = request_type(args.iter().map(|(_, ty)| ty.clone()));
+    let encoded = match (declared_args.as_slice(), &method.sig.output) {
+        ([(_, ty)], _) if is_encoded(ty) => true,
+        (_, ReturnType::Type(_arrow, ty)) => is_encoded(ty),
+        _ => false,
+    };
+    let declared_args = declared_args
+        .into_iter()
+        .map(|(name, ty)| (name, *ty))
+        .collect();
     let response = match method.sig.output {
         ReturnType::Default => quote! { () },
         ReturnType::Type(_arrow, ty) => ty.to_token_stream(),
     };
     let generated_request =
         quote::format_ident!("__tonic_generated_{}_{}_request", trait_name, name);
+    let generated_borrowed_request =
+        quote::format_ident!("__tonic_generated_{}_{}_borrowed_request", trait_name, name);
     let generated_response =
         quote::format_ident!("__tonic_generated_{}_{}_response", trait_name, name);
 
-    RustDefMethod {
-        identifier: heck::ToUpperCamelCase::to_upper_camel_case(name.as_str()),
+    Ok(RustDefMethod {
+        identifier: options
+            .name
+            .unwrap_or_else(|| heck::ToUpperCamelCase::to_upper_camel_case(name.as_str())),
         name,
-        client_streaming,
-        server_streaming,
+        compression,
+        serde: is_serde_codec(&service.codec),
+        client_streaming: options.client_streaming,
+        server_streaming: options.server_streaming,
+        args,
+        declared_args,
+        encoded,
         request,
+        borrowed_request,
         response,
         generated_request,
+        generated_borrowed_request,
         generated_response,
-        doc_comments,
-    }
-    .into()
+        doc_comments: options.doc_comments,
+    })
 }
 
-fn make_rpc<T>(item: TokenStream) -> TokenStream
-where
-    T: From<RustDefMethod> + RequestResponseTypes,
-    RustDefService<T>: Service,
-{
+fn make_rpc(options: ServiceOptions, item: TokenStream) -> TokenStream {
     let trait_ = parse_macro_input!(item as ItemTrait);
     let name = trait_.ident.to_string();
-    let methods: Vec<_> = trait_
+    let methods = trait_
         .items
         .into_iter()
         .filter_map(|item| match item {
-            TraitItem::Method(method) => Some(make_method::<T>(method, &name)),
+            TraitItem::Method(method) => Some(make_method(method, &name, &options)),
             _ => None,
         })
-        .collect();
+        .collect::<syn::Result<Vec<_>>>();
+    let methods = match methods {
+        Ok(methods) => methods,
+        Err(err) => return err.into_compile_error().into(),
+    };
+    let codec = options.codec;
     let service = RustDefService {
-        package: "".to_string(),
-        identifier: name.clone(),
+        package: options.package,
+        identifier: options.name.unwrap_or_else(|| name.clone()),
+        codec: codec_type(&codec),
         name,
         methods,
     };
-    let client = tonic_build::CodeGenBuilder::new()
-        .compile_well_known_types(false)
-        .emit_package(false)
-        .generate_client(&service, "");
-    let server = tonic_build::CodeGenBuilder::new()
-        .compile_well_known_types(false)
-        .emit_package(false)
-        .generate_server(&service, "");
+    let client = client::generate(&service);
+    let server = server::generate(&service);
+    let mirror = if codec == "rkyv" {
+        // The arguments of `rkyv` methods are only sent and received as a single archived
+        // message.
+        quote! {}
+    } else {
+        mirror::generate(&service)
+    };
+    let dispatch = if codec == "prost" || codec == "rkyv" {
+        quote! {}
+    } else {
+        dispatch::generate(&service)
+    };
     let types = service.methods.iter().map(|m| {
-        let request_name = m.generated_request();
-        let response_name = m.generated_response();
-        let request_type = m.request();
-        let response_type = m.response();
-        quote! {
-            type #request_name = #request_type;
-            type #response_name = #response_type;
+        let request_name = &m.generated_request;
+        let response_name = &m.generated_response;
+        let request_type = &m.request;
+        let response_type = &m.response;
+        let borrowed_request = m.borrowed_request.as_ref().map(|borrowed_type| {
+            let borrowed_name = &m.generated_borrowed_request;
+            quote! { type #borrowed_name<'a> = #borrowed_type; }
+        });
+        if codec == "rkyv" {
+            // `rkyv` messages are sent and received in their archived form.
+            quote! {
+                type #request_name = ::tonic_rpc::codec::Archived<#request_type>;
+                type #response_name = ::tonic_rpc::codec::Archived<#response_type>;
+            }
+        } else {
+            quote! {
+                type #request_name = #request_type;
+                #borrowed_request
+                type #response_name = #response_type;
+            }
         }
     });
     let types = quote! { #( #types )*};
+    let proto = if codec == "prost" {
+        proto::generate(&service)
+    } else if codec == "avro" {
+        avro::generate(&service)
+    } else {
+        quote! {}
+    };
     (quote! {
         #types
         #client
         #server
+        #mirror
+        #dispatch
+        #proto
     })
     .into()
 }
 
+/// Derive `tonic_rpc::avro::AvroSchema` for a struct or enum, matching its `serde` representation.
+#[proc_macro_derive(AvroSchema, attributes(serde))]
+pub fn derive_avro_schema(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    avro::derive(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive `tonic_rpc::proto::ProtoMessage` for a struct deriving `prost::Message`, from its
+/// `#[prost(..)]` field attributes.
+#[proc_macro_derive(ProtoMessage)]
+pub fn derive_proto_message(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    proto::derive(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
 #[proc_macro_attribute]
 pub fn tonic_rpc(attributes: TokenStream, item: TokenStream) -> TokenStream {
-    match attributes.to_string().as_str() {
-        "json" => make_rpc::<JsonMethod>(item),
-        "bincode" => make_rpc::<BincodeMethod>(item),
-        "cbor" => make_rpc::<CborMethod>(item),
-        "messagepack" => make_rpc::<MessagePackMethod>(item),
-        "" => panic!("No tonic_rpc codec given"),
-        other => panic!("Unrecognized tonic_rpc codec {}", other),
-    }
+    let options = parse_service_options(parse_macro_input!(attributes as AttributeArgs));
+    make_rpc(options, item)
 }
@@ -0,0 +1,218 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+use crate::{doc_comments, naive_snake_case, RustDefMethod, RustDefService};
+
+/// Generate a module containing an async version of the declared trait, implemented by
+/// the generated client and by every implementation of the generated server trait, so
+/// that code generic over it runs against either a remote or a local service.
+///
+/// `#[client_streaming]` methods and methods sending `Encoded` messages are left out, as
+/// the client and the server don't see the same types for them.
+pub(crate) fn generate(service: &RustDefService) -> TokenStream {
+    let async_trait = format_ident!("{}Async", service.name);
+    let async_mod = format_ident!("{}_async", naive_snake_case(&service.name));
+    let client_mod = format_ident!("{}_client", naive_snake_case(&service.name));
+    let client_ident = format_ident!("{}Client", service.name);
+    let server_mod = format_ident!("{}_server", naive_snake_case(&service.name));
+    let server_trait = format_ident!("{}", service.name);
+    let trait_doc = format!(
+        " An async version of the `{0}` trait, implemented by `{0}Client` and by implementations of `{0}`.",
+        service.name
+    );
+    let methods: Vec<_> = service
+        .methods
+        .iter()
+        .filter(|method| !method.client_streaming && !method.encoded)
+        .collect();
+    let trait_methods = methods.iter().map(|method| generate_trait_method(method));
+    let codec_bounds = methods.iter().map(|method| method.codec_bound(false));
+    let client = quote! { super::#client_mod::#client_ident<T, C> };
+    let client_methods = methods
+        .iter()
+        .map(|method| generate_client_method(method, &client));
+    let server_methods = methods
+        .iter()
+        .map(|method| generate_server_method(method, &server_mod, &server_trait));
+
+    quote! {
+        /// Generated async trait implemented by the client and the server.
+        pub mod #async_mod {
+            #![allow(
+                unused_variables,
+                dead_code,
+                missing_docs,
+                clippy::let_unit_value,
+            )]
+            use super::*;
+
+            #[doc = #trait_doc]
+            #[tonic::async_trait]
+            pub trait #async_trait: Send + Sync {
+                #( #trait_methods )*
+            }
+
+            #[tonic::async_trait]
+            impl<T, C> #async_trait for #client
+            where
+                T: tonic::client::GrpcService<tonic::body::BoxBody>
+                    + ::std::clone::Clone
+                    + Send
+                    + Sync
+                    + 'static,
+                <T as tonic::client::GrpcService<tonic::body::BoxBody>>::Future: Send,
+                T::Error: Into<tonic::codegen::StdError>,
+                T::ResponseBody: tonic::codegen::Body<Data = tonic::codegen::Bytes> + Send + 'static,
+                <T::ResponseBody as tonic::codegen::Body>::Error: Into<tonic::codegen::StdError> + Send,
+                C: ::std::clone::Clone + Send + Sync + 'static,
+                #( #codec_bounds )*
+            {
+                #( #client_methods )*
+            }
+
+            #[tonic::async_trait]
+            impl<T: super::#server_mod::#server_trait> #async_trait for T {
+                #( #server_methods )*
+            }
+        }
+    }
+}
+
+/// The parameters of the async version of `method`, as they are declared.
+fn params(method: &RustDefMethod) -> TokenStream {
+    let params = method
+        .declared_args
+        .iter()
+        .enumerate()
+        .map(|(i, (name, ty))| {
+            let name = arg_ident(i, name);
+            quote! { #name: #ty }
+        });
+    quote! { #( #params ),* }
+}
+
+/// The name of the parameter at position `i` named `name`, which may be `_`.
+fn arg_ident(i: usize, name: &str) -> syn::Ident {
+    if name == "_" {
+        format_ident!("arg{}", i)
+    } else {
+        format_ident!("{}", name)
+    }
+}
+
+/// The request built from the parameters of the async version of `method`, converting
+/// borrowed arguments to their owned types if `owned`.
+fn request(method: &RustDefMethod, owned: bool) -> TokenStream {
+    let args: Vec<_> = method
+        .declared_args
+        .iter()
+        .enumerate()
+        .map(|(i, (name, ty))| {
+            let name = arg_ident(i, name);
+            if owned && matches!(ty, syn::Type::Reference(_)) {
+                quote! { ::std::borrow::ToOwned::to_owned(#name) }
+            } else {
+                quote! { #name }
+            }
+        })
+        .collect();
+    match args.as_slice() {
+        [arg] => arg.clone(),
+        args => quote! { ( #( #args ),* ) },
+    }
+}
+
+fn generate_trait_method(method: &RustDefMethod) -> TokenStream {
+    let name = format_ident!("{}", method.name);
+    let doc = doc_comments(&method.doc_comments);
+    let params = params(method);
+    let response = &method.generated_response;
+
+    if method.server_streaming {
+        let stream = method.stream_type();
+        let stream_doc = format!(
+            " Server streaming response type for the {} method.",
+            method.name
+        );
+        quote! {
+            #[doc = #stream_doc]
+            type #stream: tonic::codegen::futures_core::Stream<Item = Result<super::#response, tonic::Status>>
+                + Send
+                + 'static;
+
+            #doc
+            async fn #name(&self, #params) -> Result<Self::#stream, tonic::Status>;
+        }
+    } else {
+        quote! {
+            #doc
+            async fn #name(&self, #params) -> Result<super::#response, tonic::Status>;
+        }
+    }
+}
+
+fn generate_client_method(method: &RustDefMethod, client: &TokenStream) -> TokenStream {
+    let name = format_ident!("{}", method.name);
+    let params = params(method);
+    let request = request(method, false);
+    let (_, response) = method.client_request_response();
+    let call = quote! {
+        // Clients are cheap to clone, and calls need a mutable client. The inherent method
+        // is named explicitly, as method calls would resolve to this one.
+        let mut client = ::std::clone::Clone::clone(self);
+        <#client>::#name(&mut client, #request)
+            .await
+            .map(tonic::Response::into_inner)
+    };
+
+    if method.server_streaming {
+        let stream = method.stream_type();
+        quote! {
+            type #stream = tonic::codec::Streaming<#response>;
+
+            async fn #name(&self, #params) -> Result<Self::#stream, tonic::Status> {
+                #call
+            }
+        }
+    } else {
+        quote! {
+            async fn #name(&self, #params) -> Result<#response, tonic::Status> {
+                #call
+            }
+        }
+    }
+}
+
+fn generate_server_method(
+    method: &RustDefMethod,
+    server_mod: &syn::Ident,
+    server_trait: &syn::Ident,
+) -> TokenStream {
+    let name = format_ident!("{}", method.name);
+    let params = params(method);
+    let request = request(method, true);
+    let (_, response) = method.server_request_response();
+    let call = quote! {
+        let request = tonic::Request::new(#request);
+        super::#server_mod::#server_trait::#name(self, request)
+            .await
+            .map(tonic::Response::into_inner)
+    };
+
+    if method.server_streaming {
+        let stream = method.stream_type();
+        quote! {
+            type #stream = <T as super::#server_mod::#server_trait>::#stream;
+
+            async fn #name(&self, #params) -> Result<Self::#stream, tonic::Status> {
+                #call
+            }
+        }
+    } else {
+        quote! {
+            async fn #name(&self, #params) -> Result<#response, tonic::Status> {
+                #call
+            }
+        }
+    }
+}
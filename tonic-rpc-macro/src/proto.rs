@@ -0,0 +1,234 @@
+//! Generation of the `proto` files of services using the `prost` codec, and of the
+//! `tonic_rpc::proto::ProtoMessage` implementations of their messages.
+
+use quote::{quote, ToTokens};
+use syn::{Data, DeriveInput, Fields, Lit, Meta, NestedMeta, Type};
+
+use crate::{doc_comment, RustDefService};
+
+/// `comments` as `proto` comments indented by `indent`, one for each of their lines.
+fn proto_comments(indent: &str, comments: &[String]) -> String {
+    comments
+        .iter()
+        .flat_map(|comment| comment.split('\n'))
+        .map(|line| format!("{}//{}\n", indent, line.trim_end()))
+        .collect()
+}
+
+/// Generate a function returning the `proto` file declaring the service and its messages,
+/// so that clients in other languages can be generated for services using the `prost` codec.
+pub fn generate(service: &RustDefService) -> proc_macro2::TokenStream {
+    let function = quote::format_ident!(
+        "{}_proto",
+        heck::ToSnakeCase::to_snake_case(service.name.as_str())
+    );
+    let doc = format!(
+        " The `proto` definition of the `{}` service.",
+        service.identifier
+    );
+    let package = &service.package;
+    let header = format!("service {} {{\n", service.identifier);
+    let rpcs = service.methods.iter().map(|method| {
+        let comments = proto_comments("  ", &method.doc_comments);
+        let identifier = &method.identifier;
+        let stream = |streaming| if streaming { "stream " } else { "" };
+        let request_stream = stream(method.client_streaming);
+        let response_stream = stream(method.server_streaming);
+        let request = &method.request;
+        let response = &method.response;
+        quote! {
+            service.push_str(#comments);
+            let request = file.message::<#request>();
+            let response = file.message::<#response>();
+            service.push_str(&format!(
+                "  rpc {}({}{}) returns ({}{});\n",
+                #identifier, #request_stream, request, #response_stream, response,
+            ));
+        }
+    });
+    quote! {
+        #[doc = #doc]
+        pub fn #function() -> ::std::string::String {
+            let mut file = ::tonic_rpc::proto::ProtoFile::new();
+            let mut service = ::std::string::String::from(#header);
+            #( #rpcs )*
+            service.push_str("}\n");
+            file.finish(#package, &service)
+        }
+    }
+}
+
+/// The scalar `prost` field types, which have the same name in `proto` files.
+const SCALARS: &[&str] = &[
+    "double", "float", "int32", "int64", "uint32", "uint64", "sint32", "sint64", "fixed32",
+    "fixed64", "sfixed32", "sfixed64", "bool", "string", "bytes",
+];
+
+/// The message type contained in the Rust type of a `message` field, e.g. `T` for
+/// `Option<Box<T>>` or `Vec<T>`.
+fn message_type(ty: &Type) -> &Type {
+    if let Type::Path(path) = ty {
+        if let Some(segment) = path.path.segments.last() {
+            if ["Option", "Vec", "Box"].contains(&segment.ident.to_string().as_str()) {
+                if let syn::PathArguments::AngleBracketed(args) = &segment.arguments {
+                    if let Some(syn::GenericArgument::Type(inner)) = args.args.first() {
+                        return message_type(inner);
+                    }
+                }
+            }
+        }
+    }
+    ty
+}
+
+/// The statements adding the definition of `field` to the `fields` of a message, defining
+/// the message it contains in `file`.
+fn field_definition(field: &syn::Field) -> syn::Result<proc_macro2::TokenStream> {
+    let ident = field.ident.as_ref().unwrap();
+    let name = ident.to_string().trim_start_matches("r#").to_string();
+    let attr = field
+        .attrs
+        .iter()
+        .find(|attr| attr.path.is_ident("prost"))
+        .ok_or_else(|| syn::Error::new_spanned(ident, "Expected a #[prost(..)] attribute"))?;
+    let nested = match attr.parse_meta()? {
+        Meta::List(list) => list.nested,
+        other => return Err(syn::Error::new_spanned(other, "Expected #[prost(..)]")),
+    };
+
+    let mut ty = None;
+    let mut label = "";
+    let mut tag = None;
+    let mut packed = "";
+    for meta in &nested {
+        match meta {
+            NestedMeta::Meta(Meta::Path(path)) => {
+                let word = path.to_token_stream().to_string();
+                match word.as_str() {
+                    "optional" => label = "optional ",
+                    "repeated" => label = "repeated ",
+                    word if SCALARS.contains(&word) || word == "message" => {
+                        ty = Some(word.to_string())
+                    }
+                    _ => return Err(unsupported(meta)),
+                }
+            }
+            NestedMeta::Meta(Meta::NameValue(name_value)) => {
+                let value = match &name_value.lit {
+                    Lit::Str(lit) => lit.value(),
+                    other => {
+                        return Err(syn::Error::new_spanned(other, "Expected a string literal"))
+                    }
+                };
+                if name_value.path.is_ident("tag") {
+                    tag = Some(value);
+                } else if name_value.path.is_ident("bytes") {
+                    ty = Some("bytes".to_string());
+                } else if name_value.path.is_ident("packed") && value == "false" {
+                    packed = " [packed = false]";
+                } else {
+                    return Err(unsupported(meta));
+                }
+            }
+            other => return Err(unsupported(other)),
+        }
+    }
+    let (ty, tag) = match (ty, tag) {
+        (Some(ty), Some(tag)) => (ty, tag),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                attr,
+                "Expected a field type and a tag in #[prost(..)]",
+            ))
+        }
+    };
+    let comments = proto_comments("  ", &field_docs(&field.attrs));
+
+    Ok(if ty == "message" {
+        // Message fields are always optional in proto3.
+        let label = if label == "repeated " { label } else { "" };
+        let message = message_type(&field.ty);
+        quote! {
+            fields.push_str(#comments);
+            let message = file.message::<#message>();
+            fields.push_str(&format!("  {}{} {} = {};\n", #label, message, #name, #tag));
+        }
+    } else {
+        let definition = format!(
+            "{}  {}{} {} = {}{};\n",
+            comments, label, ty, name, tag, packed
+        );
+        quote! { fields.push_str(#definition); }
+    })
+}
+
+/// The error for a `prost` field option which can't be described in a `proto` file.
+fn unsupported(meta: &impl ToTokens) -> syn::Error {
+    syn::Error::new_spanned(
+        meta,
+        format!(
+            "ProtoMessage cannot describe the prost field option {}: only scalar, string, bytes \
+             and message fields are supported",
+            meta.to_token_stream()
+        ),
+    )
+}
+
+fn field_docs(attrs: &[syn::Attribute]) -> Vec<String> {
+    attrs.iter().filter_map(doc_comment).collect()
+}
+
+/// Derive `tonic_rpc::proto::ProtoMessage` for a struct deriving `prost::Message`.
+pub fn derive(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let ident = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "ProtoMessage cannot be derived for generic types",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().collect(),
+            Fields::Unit => Vec::new(),
+            Fields::Unnamed(_) => {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "ProtoMessage can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "ProtoMessage can only be derived for structs",
+            ))
+        }
+    };
+    let fields = fields
+        .into_iter()
+        .map(field_definition)
+        .collect::<syn::Result<Vec<_>>>()?;
+    let name = ident.to_string();
+    let header = format!(
+        "{}message {} {{\n",
+        proto_comments("", &field_docs(&input.attrs)),
+        name
+    );
+
+    Ok(quote! {
+        impl ::tonic_rpc::proto::ProtoMessage for #ident {
+            const NAME: &'static str = #name;
+
+            fn define(file: &mut ::tonic_rpc::proto::ProtoFile) {
+                if !file.declare(Self::NAME) {
+                    return;
+                }
+                let mut fields = ::std::string::String::from(#header);
+                #( #fields )*
+                fields.push_str("}\n");
+                file.define(Self::NAME, fields);
+            }
+        }
+    })
+}
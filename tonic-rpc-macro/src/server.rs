@@ -0,0 +1,333 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+use crate::{doc_comments, naive_snake_case, RustDefMethod, RustDefService};
+
+/// Generate a module containing the trait implemented by the service and the server
+/// routing requests to an implementation of it.
+///
+/// Like the client, the server holds a codec of type `C`, which defaults to the codec
+/// named in `#[tonic_rpc(..)]` and can be set with `with_codec`.
+pub(crate) fn generate(service: &RustDefService) -> TokenStream {
+    let server_ident = format_ident!("{}Server", service.name);
+    let server_trait = format_ident!("{}", service.name);
+    let server_mod = format_ident!("{}_server", naive_snake_case(&service.name));
+    let default_codec = &service.codec;
+    let service_name = service.path();
+    let trait_doc = format!(
+        " Generated trait containing gRPC methods that should be implemented for use with {}Server.",
+        service.name
+    );
+    let trait_methods = service.methods.iter().map(generate_trait_method);
+    let codec_bounds = service
+        .methods
+        .iter()
+        .map(|method| method.codec_bound(true));
+    let methods = service
+        .methods
+        .iter()
+        .map(|method| generate_method(service, method, &server_trait));
+    let descriptors = service.methods.iter().map(|method| {
+        let name = &method.name;
+        let path = service.method_path(method);
+        let args = method.args.iter().map(|(arg, _)| arg);
+        let client_streaming = method.client_streaming;
+        let server_streaming = method.server_streaming;
+        quote! {
+            ::tonic_rpc::descriptor::MethodDescriptor {
+                name: #name,
+                path: #path,
+                args: &[#( #args ),*],
+                client_streaming: #client_streaming,
+                server_streaming: #server_streaming,
+            }
+        }
+    });
+
+    // Adapters serving messages as JSON need the messages of every method to be plain JSON.
+    let json_codec = service
+        .methods
+        .iter()
+        .all(|method| method.serde && method.compression.is_none())
+        .then(|| {
+            quote! {
+                impl<T: #server_trait, C: ::tonic_rpc::codec::JsonEncoding> ::tonic_rpc::descriptor::JsonCodec for #server_ident<T, C> {}
+            }
+        });
+
+    quote! {
+        /// Generated server implementations.
+        pub mod #server_mod {
+            #![allow(
+                unused_variables,
+                dead_code,
+                missing_docs,
+                // will trigger if compression is disabled
+                clippy::let_unit_value,
+            )]
+            use tonic::codegen::*;
+
+            #[doc = #trait_doc]
+            #[async_trait]
+            pub trait #server_trait : Send + Sync + 'static {
+                #( #trait_methods )*
+            }
+
+            #[derive(Debug)]
+            pub struct #server_ident<T: #server_trait, C = #default_codec> {
+                inner: _Inner<T>,
+                codec: C,
+                accept_compression_encodings: EnabledCompressionEncodings,
+                send_compression_encodings: EnabledCompressionEncodings,
+            }
+
+            struct _Inner<T>(Arc<T>);
+
+            impl<T: #server_trait> #server_ident<T> {
+                pub fn new(inner: T) -> Self {
+                    Self::from_arc(Arc::new(inner))
+                }
+
+                pub fn from_arc(inner: Arc<T>) -> Self {
+                    Self::from_arc_with_codec(inner, Default::default())
+                }
+
+                pub fn with_interceptor<F>(inner: T, interceptor: F) -> InterceptedService<Self, F>
+                where
+                    F: tonic::service::Interceptor,
+                {
+                    InterceptedService::new(Self::new(inner), interceptor)
+                }
+            }
+
+            impl<T: #server_trait, C> #server_ident<T, C> {
+                /// Create a server encoding messages with `codec`, e.g. a codec configured
+                /// with a key or options.
+                pub fn with_codec(inner: T, codec: C) -> Self {
+                    Self::from_arc_with_codec(Arc::new(inner), codec)
+                }
+
+                pub fn from_arc_with_codec(inner: Arc<T>, codec: C) -> Self {
+                    let inner = _Inner(inner);
+                    Self {
+                        inner,
+                        codec,
+                        accept_compression_encodings: Default::default(),
+                        send_compression_encodings: Default::default(),
+                    }
+                }
+
+                /// Enable decompressing requests with the given encoding.
+                #[must_use]
+                pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+                    self.accept_compression_encodings.enable(encoding);
+                    self
+                }
+
+                /// Compress responses with the given encoding, if the client supports it.
+                #[must_use]
+                pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+                    self.send_compression_encodings.enable(encoding);
+                    self
+                }
+            }
+
+            impl<T, C, B> tonic::codegen::Service<http::Request<B>> for #server_ident<T, C>
+                where
+                    T: #server_trait,
+                    #( #codec_bounds )*
+                    B: Body + Send + 'static,
+                    B::Error: Into<StdError> + Send + 'static,
+            {
+                type Response = http::Response<tonic::body::BoxBody>;
+                type Error = std::convert::Infallible;
+                type Future = BoxFuture<Self::Response, Self::Error>;
+
+                fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+                    Poll::Ready(Ok(()))
+                }
+
+                fn call(&mut self, req: http::Request<B>) -> Self::Future {
+                    let inner = self.inner.clone();
+
+                    match req.uri().path() {
+                        #( #methods )*
+
+                        _ => Box::pin(async move {
+                            Ok(http::Response::builder()
+                               .status(200)
+                               .header("grpc-status", "12")
+                               .header("content-type", "application/grpc")
+                               .body(empty_body())
+                               .unwrap())
+                        }),
+                    }
+                }
+            }
+
+            impl<T: #server_trait, C: Clone> Clone for #server_ident<T, C> {
+                fn clone(&self) -> Self {
+                    let inner = self.inner.clone();
+                    Self {
+                        inner,
+                        codec: self.codec.clone(),
+                        accept_compression_encodings: self.accept_compression_encodings,
+                        send_compression_encodings: self.send_compression_encodings,
+                    }
+                }
+            }
+
+            impl<T: #server_trait> Clone for _Inner<T> {
+                fn clone(&self) -> Self {
+                    Self(self.0.clone())
+                }
+            }
+
+            impl<T: std::fmt::Debug> std::fmt::Debug for _Inner<T> {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                   write!(f, "{:?}", self.0)
+                }
+            }
+
+            impl<T: #server_trait, C> tonic::server::NamedService for #server_ident<T, C> {
+                const NAME: &'static str = #service_name;
+            }
+
+            impl<T: #server_trait, C> ::tonic_rpc::descriptor::ServiceDescriptor for #server_ident<T, C> {
+                const METHODS: &'static [::tonic_rpc::descriptor::MethodDescriptor] = &[
+                    #( #descriptors ),*
+                ];
+            }
+
+            #json_codec
+        }
+    }
+}
+
+fn generate_trait_method(method: &RustDefMethod) -> TokenStream {
+    let name = format_ident!("{}", method.name);
+    let doc = doc_comments(&method.doc_comments);
+    let (request, response) = method.server_request_response();
+    let request = if method.client_streaming {
+        quote! { tonic::Streaming<#request> }
+    } else {
+        request
+    };
+
+    if method.server_streaming {
+        let stream = method.stream_type();
+        let stream_doc = format!(
+            " Server streaming response type for the {} method.",
+            method.name
+        );
+        quote! {
+            #[doc = #stream_doc]
+            type #stream: futures_core::Stream<Item = Result<#response, tonic::Status>> + Send + 'static;
+
+            #doc
+            async fn #name(&self, request: tonic::Request<#request>)
+                -> Result<tonic::Response<Self::#stream>, tonic::Status>;
+        }
+    } else {
+        quote! {
+            #doc
+            async fn #name(&self, request: tonic::Request<#request>)
+                -> Result<tonic::Response<#response>, tonic::Status>;
+        }
+    }
+}
+
+fn generate_method(
+    service: &RustDefService,
+    method: &RustDefMethod,
+    server_trait: &syn::Ident,
+) -> TokenStream {
+    let path = service.method_path(method);
+    let method_ident = format_ident!("{}", method.name);
+    let service_ident = format_ident!("{}Svc", method.camel_case_name());
+    let (request, response) = method.server_request_response();
+    let codec = method.make_codec(quote! { self.codec }, &path, true);
+    let span = method.span(service, true);
+    let metrics = method.metrics(service, true);
+    let response_stream = method.stream_type();
+
+    let streaming_request = quote! { tonic::Streaming<#request> };
+    let (service_trait, call_request, response_types, call) =
+        match (method.client_streaming, method.server_streaming) {
+            (false, false) => (
+                quote! { tonic::server::UnaryService<#request> },
+                &request,
+                quote! {
+                    type Response = #response;
+                    type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                },
+                quote! { grpc.unary(method, req) },
+            ),
+            (false, true) => (
+                quote! { tonic::server::ServerStreamingService<#request> },
+                &request,
+                quote! {
+                    type Response = #response;
+                    type ResponseStream = T::#response_stream;
+                    type Future = BoxFuture<tonic::Response<Self::ResponseStream>, tonic::Status>;
+                },
+                quote! { grpc.server_streaming(method, req) },
+            ),
+            (true, false) => (
+                quote! { tonic::server::ClientStreamingService<#request> },
+                &streaming_request,
+                quote! {
+                    type Response = #response;
+                    type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                },
+                quote! { grpc.client_streaming(method, req) },
+            ),
+            (true, true) => (
+                quote! { tonic::server::StreamingService<#request> },
+                &streaming_request,
+                quote! {
+                    type Response = #response;
+                    type ResponseStream = T::#response_stream;
+                    type Future = BoxFuture<tonic::Response<Self::ResponseStream>, tonic::Status>;
+                },
+                quote! { grpc.streaming(method, req) },
+            ),
+        };
+
+    quote! {
+        #path => {
+            #[allow(non_camel_case_types)]
+            struct #service_ident<T: #server_trait>(pub Arc<T>);
+
+            impl<T: #server_trait> #service_trait for #service_ident<T> {
+                #response_types
+
+                fn call(&mut self, request: tonic::Request<#call_request>) -> Self::Future {
+                    let inner = self.0.clone();
+                    let fut = async move {
+                        (*inner).#method_ident(request).await
+                    };
+                    Box::pin(fut)
+                }
+            }
+
+            let accept_compression_encodings = self.accept_compression_encodings;
+            let send_compression_encodings = self.send_compression_encodings;
+            let codec = #codec;
+            let context = ::tonic_rpc::instrument::Context::extract(req.headers());
+            let inner = self.inner.clone();
+            let fut = async move {
+                let inner = inner.0;
+                let method = #service_ident(inner);
+
+                let mut grpc = tonic::server::Grpc::new(codec)
+                    .apply_compression_config(accept_compression_encodings, send_compression_encodings);
+
+                let res = #call.await;
+                Ok(res)
+            };
+
+            Box::pin(::tonic_rpc::instrument::server(#span, #metrics, context, fut))
+        }
+    }
+}
@@ -0,0 +1,1477 @@
+//! [Avro](https://avro.apache.org/docs/1.11.1/specification/) schemas and the
+//! `avro` codec.
+//!
+//! Messages are encoded with Avro's binary encoding, driven by the schema given by their
+//! [`AvroSchema`] implementation: values which don't match the schema are rejected when they
+//! are encoded. Each message is sent in Avro's
+//! [single object encoding](https://avro.apache.org/docs/1.11.1/specification/#single-object-encoding),
+//! starting with the [fingerprint](Schema::fingerprint) of its schema, and the codec rejects
+//! messages written with a different schema with `FailedPrecondition`. [`to_vec`] and
+//! [`from_slice`] encode and decode single values without the header.
+//!
+//! `AvroSchema` is implemented for the common standard library types and can be derived for
+//! structs and enums:
+//! - structs with named fields are records with the same fields,
+//! - newtype structs have the schema of the wrapped type,
+//! - enums with only unit variants are Avro enums,
+//! - other enums are unions with a record for each variant, named `{Enum}{Variant}`,
+//! - tuples, such as the requests of methods with several arguments, are records named
+//!   `Tuple{n}` with fields `field0`, `field1`, ...
+//!
+//! The derived schemas follow the `serde` attributes `rename`, `rename_all`, `transparent`
+//! and `skip`. Attributes which change the shape of a type in ways an Avro schema can't
+//! describe, such as `flatten`, `tag` or `skip_serializing_if`, are rejected. Recursive
+//! types are not supported.
+//!
+//! For each `#[tonic_rpc(avro)]` service the macro also generates a function returning the
+//! schemas of its methods, e.g. `increment_avro_schemas()` for a trait named `Increment`.
+//! The schemas are used by [`AvroChannel`] and [`AvroServer`], which send the fingerprints of
+//! the schemas in the metadata of each call, so that mismatched schemas are detected before
+//! any message is sent:
+//! ```no_run
+//! # #[tonic_rpc::tonic_rpc(avro)]
+//! # trait Increment {
+//! #     fn increment(arg: i32) -> i32;
+//! # }
+//! # struct State;
+//! # #[tonic::async_trait]
+//! # impl increment_server::Increment for State {
+//! #     async fn increment(
+//! #         &self,
+//! #         request: tonic::Request<i32>,
+//! #     ) -> Result<tonic::Response<i32>, tonic::Status> {
+//! #         Ok(tonic::Response::new(request.into_inner() + 1))
+//! #     }
+//! # }
+//! use tonic_rpc::avro::{AvroChannel, AvroServer};
+//!
+//! # async fn run() {
+//! tokio::spawn(
+//!     tonic::transport::Server::builder()
+//!         .add_service(AvroServer::new(
+//!             increment_server::IncrementServer::new(State),
+//!             increment_avro_schemas(),
+//!         ))
+//!         .serve("[::1]:8080".parse().unwrap()),
+//! );
+//!
+//! let channel = tonic::transport::Endpoint::from_static("http://[::1]:8080")
+//!     .connect()
+//!     .await
+//!     .unwrap();
+//! let mut client =
+//!     increment_client::IncrementClient::new(AvroChannel::new(channel, increment_avro_schemas()));
+//! # }
+//! # fn main() {}
+//! ```
+//! The server rejects calls without fingerprints, or whose request or response fingerprints
+//! don't match its own, with `FailedPrecondition`, and includes its fingerprints in the
+//! response metadata so that peers can look up the writer's schema.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    fmt::{self, Display},
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use serde::{
+    de::{self, IntoDeserializer},
+    ser, Deserialize, Serialize,
+};
+use tonic::{
+    body::BoxBody,
+    codegen::{
+        http::{HeaderValue, Request, Response},
+        BoxFuture, Service,
+    },
+    transport::NamedService,
+    Status,
+};
+
+pub use tonic_rpc_macro::AvroSchema;
+
+/// The metadata key used for the fingerprint of the request schema.
+pub const REQUEST_FINGERPRINT_HEADER: &str = "avro-request-fingerprint";
+/// The metadata key used for the fingerprint of the response schema.
+pub const RESPONSE_FINGERPRINT_HEADER: &str = "avro-response-fingerprint";
+
+/// An Avro schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schema {
+    Null,
+    Boolean,
+    Int,
+    Long,
+    Float,
+    Double,
+    Bytes,
+    String,
+    Array(Box<Schema>),
+    Map(Box<Schema>),
+    Union(Vec<Schema>),
+    Record {
+        name: String,
+        fields: Vec<(String, Schema)>,
+    },
+    Enum {
+        name: String,
+        symbols: Vec<String>,
+    },
+}
+
+impl Schema {
+    /// The [Parsing Canonical Form](https://avro.apache.org/docs/1.11.1/specification/#parsing-canonical-form-for-schemas)
+    /// of the schema.
+    pub fn canonical_form(&self) -> String {
+        let mut out = String::new();
+        self.write_canonical_form(&mut Vec::new(), &mut out);
+        out
+    }
+
+    /// The 64-bit Rabin fingerprint (`CRC-64-AVRO`) of the canonical form of the schema.
+    pub fn fingerprint(&self) -> u64 {
+        fingerprint(self.canonical_form().as_bytes())
+    }
+
+    fn write_canonical_form<'a>(&'a self, named: &mut Vec<&'a str>, out: &mut String) {
+        match self {
+            Schema::Null => out.push_str("\"null\""),
+            Schema::Boolean => out.push_str("\"boolean\""),
+            Schema::Int => out.push_str("\"int\""),
+            Schema::Long => out.push_str("\"long\""),
+            Schema::Float => out.push_str("\"float\""),
+            Schema::Double => out.push_str("\"double\""),
+            Schema::Bytes => out.push_str("\"bytes\""),
+            Schema::String => out.push_str("\"string\""),
+            Schema::Array(items) => {
+                out.push_str("{\"type\":\"array\",\"items\":");
+                items.write_canonical_form(named, out);
+                out.push('}');
+            }
+            Schema::Map(values) => {
+                out.push_str("{\"type\":\"map\",\"values\":");
+                values.write_canonical_form(named, out);
+                out.push('}');
+            }
+            Schema::Union(variants) => {
+                out.push('[');
+                for (i, variant) in variants.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    variant.write_canonical_form(named, out);
+                }
+                out.push(']');
+            }
+            // Named types are only defined the first time they appear.
+            Schema::Record { name, .. } | Schema::Enum { name, .. }
+                if named.contains(&name.as_str()) =>
+            {
+                out.push_str(&format!("\"{}\"", name))
+            }
+            Schema::Record { name, fields } => {
+                named.push(name);
+                out.push_str(&format!(
+                    "{{\"name\":\"{}\",\"type\":\"record\",\"fields\":[",
+                    name
+                ));
+                for (i, (field, schema)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    out.push_str(&format!("{{\"name\":\"{}\",\"type\":", field));
+                    schema.write_canonical_form(named, out);
+                    out.push('}');
+                }
+                out.push_str("]}");
+            }
+            Schema::Enum { name, symbols } => {
+                named.push(name);
+                let symbols: Vec<_> = symbols.iter().map(|s| format!("\"{}\"", s)).collect();
+                out.push_str(&format!(
+                    "{{\"name\":\"{}\",\"type\":\"enum\",\"symbols\":[{}]}}",
+                    name,
+                    symbols.join(",")
+                ));
+            }
+        }
+    }
+}
+
+const FINGERPRINT_EMPTY: u64 = 0xc15d_213a_a4d7_a795;
+
+const FINGERPRINT_TABLE: [u64; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut fp = i as u64;
+        let mut j = 0;
+        while j < 8 {
+            fp = (fp >> 1) ^ (FINGERPRINT_EMPTY & (fp & 1).wrapping_neg());
+            j += 1;
+        }
+        table[i] = fp;
+        i += 1;
+    }
+    table
+};
+
+fn fingerprint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FINGERPRINT_EMPTY, |fp, byte| {
+        (fp >> 8) ^ FINGERPRINT_TABLE[((fp ^ *byte as u64) & 0xff) as usize]
+    })
+}
+
+/// Types with an Avro schema matching their `serde` representation.
+pub trait AvroSchema {
+    fn schema() -> Schema;
+}
+
+macro_rules! primitive_schema {
+    ($schema:ident, $($ty:ty),*) => {
+        $(
+            impl AvroSchema for $ty {
+                fn schema() -> Schema {
+                    Schema::$schema
+                }
+            }
+        )*
+    };
+}
+
+primitive_schema!(Null, ());
+primitive_schema!(Boolean, bool);
+primitive_schema!(Int, i8, i16, i32, u8, u16);
+primitive_schema!(Long, i64, u32, u64);
+primitive_schema!(Float, f32);
+primitive_schema!(Double, f64);
+primitive_schema!(String, String, str, char);
+
+impl<T: AvroSchema + ?Sized> AvroSchema for &T {
+    fn schema() -> Schema {
+        T::schema()
+    }
+}
+
+impl<T: AvroSchema + ?Sized> AvroSchema for Box<T> {
+    fn schema() -> Schema {
+        T::schema()
+    }
+}
+
+impl<T: AvroSchema> AvroSchema for Option<T> {
+    fn schema() -> Schema {
+        Schema::Union(vec![Schema::Null, T::schema()])
+    }
+}
+
+impl<T: AvroSchema> AvroSchema for Vec<T> {
+    fn schema() -> Schema {
+        Schema::Array(Box::new(T::schema()))
+    }
+}
+
+impl<T: AvroSchema> AvroSchema for [T] {
+    fn schema() -> Schema {
+        Schema::Array(Box::new(T::schema()))
+    }
+}
+
+macro_rules! tuple_schema {
+    ($($len:literal => ($($field:literal: $ty:ident),*),)*) => {
+        $(
+            impl<$($ty: AvroSchema),*> AvroSchema for ($($ty,)*) {
+                fn schema() -> Schema {
+                    Schema::Record {
+                        name: concat!("Tuple", $len).to_string(),
+                        fields: vec![$((concat!("field", $field).to_string(), $ty::schema())),*],
+                    }
+                }
+            }
+        )*
+    };
+}
+
+tuple_schema! {
+    2 => (0: A, 1: B),
+    3 => (0: A, 1: B, 2: C),
+    4 => (0: A, 1: B, 2: C, 3: D),
+    5 => (0: A, 1: B, 2: C, 3: D, 4: E),
+    6 => (0: A, 1: B, 2: C, 3: D, 4: E, 5: F),
+    7 => (0: A, 1: B, 2: C, 3: D, 4: E, 5: F, 6: G),
+    8 => (0: A, 1: B, 2: C, 3: D, 4: E, 5: F, 6: G, 7: H),
+}
+
+impl<V: AvroSchema, S> AvroSchema for HashMap<String, V, S> {
+    fn schema() -> Schema {
+        Schema::Map(Box::new(V::schema()))
+    }
+}
+
+impl<V: AvroSchema> AvroSchema for BTreeMap<String, V> {
+    fn schema() -> Schema {
+        Schema::Map(Box::new(V::schema()))
+    }
+}
+
+/// The schemas used by a method of an `avro` service.
+#[derive(Debug, Clone)]
+pub struct MethodSchemas {
+    path: String,
+    request: Schema,
+    response: Schema,
+    request_fingerprint: u64,
+    response_fingerprint: u64,
+}
+
+impl MethodSchemas {
+    /// `path` is the path of the method, e.g. `/Increment/Increment`.
+    pub fn new(path: impl Into<String>, request: Schema, response: Schema) -> Self {
+        MethodSchemas {
+            path: path.into(),
+            request_fingerprint: request.fingerprint(),
+            response_fingerprint: response.fingerprint(),
+            request,
+            response,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn request(&self) -> &Schema {
+        &self.request
+    }
+
+    pub fn response(&self) -> &Schema {
+        &self.response
+    }
+
+    pub fn request_fingerprint(&self) -> u64 {
+        self.request_fingerprint
+    }
+
+    pub fn response_fingerprint(&self) -> u64 {
+        self.response_fingerprint
+    }
+}
+
+/// The schemas used by the methods of an `avro` service.
+#[derive(Debug, Clone)]
+pub struct ServiceSchemas {
+    methods: Arc<Vec<MethodSchemas>>,
+}
+
+impl ServiceSchemas {
+    pub fn new(methods: Vec<MethodSchemas>) -> Self {
+        ServiceSchemas {
+            methods: Arc::new(methods),
+        }
+    }
+
+    pub fn methods(&self) -> &[MethodSchemas] {
+        &self.methods
+    }
+
+    /// The schemas of the method with the given path.
+    pub fn get(&self, path: &str) -> Option<&MethodSchemas> {
+        self.methods.iter().find(|method| method.path == path)
+    }
+}
+
+fn fingerprint_header(fingerprint: u64) -> HeaderValue {
+    HeaderValue::from_str(&format!("{:016x}", fingerprint))
+        .expect("Hex strings are valid header values")
+}
+
+/// Sends the fingerprints of the request and response schemas with each call.
+///
+/// Wrap a `Channel` in this before passing it to the generated client's `new`.
+#[derive(Debug, Clone)]
+pub struct AvroChannel<T> {
+    inner: T,
+    schemas: ServiceSchemas,
+}
+
+impl<T> AvroChannel<T> {
+    pub fn new(inner: T, schemas: ServiceSchemas) -> Self {
+        AvroChannel { inner, schemas }
+    }
+}
+
+impl<T, B> Service<Request<B>> for AvroChannel<T>
+where
+    T: Service<Request<B>>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = T::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        if let Some(method) = self.schemas.get(request.uri().path()) {
+            let headers = request.headers_mut();
+            headers.insert(
+                REQUEST_FINGERPRINT_HEADER,
+                fingerprint_header(method.request_fingerprint),
+            );
+            headers.insert(
+                RESPONSE_FINGERPRINT_HEADER,
+                fingerprint_header(method.response_fingerprint),
+            );
+        }
+        self.inner.call(request)
+    }
+}
+
+/// Checks the schema fingerprints sent by clients against the schemas of the wrapped server,
+/// and sends the server's fingerprints in the response metadata.
+///
+/// Calls to methods of the service without fingerprints are rejected, so clients must wrap
+/// their channel in an [`AvroChannel`].
+#[derive(Debug, Clone)]
+pub struct AvroServer<S> {
+    inner: S,
+    schemas: ServiceSchemas,
+}
+
+impl<S> AvroServer<S> {
+    pub fn new(inner: S, schemas: ServiceSchemas) -> Self {
+        AvroServer { inner, schemas }
+    }
+}
+
+impl<S: NamedService> NamedService for AvroServer<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S, B> Service<Request<B>> for AvroServer<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let method = match self.schemas.get(request.uri().path()) {
+            Some(method) => method.clone(),
+            None => return Box::pin(self.inner.call(request)),
+        };
+        let expected = [
+            (
+                "request",
+                REQUEST_FINGERPRINT_HEADER,
+                method.request_fingerprint,
+            ),
+            (
+                "response",
+                RESPONSE_FINGERPRINT_HEADER,
+                method.response_fingerprint,
+            ),
+        ];
+        for (kind, header, fingerprint) in expected {
+            let status = match request.headers().get(header) {
+                Some(value) if *value == fingerprint_header(fingerprint) => continue,
+                Some(value) => Status::failed_precondition(format!(
+                    "Avro {} schema fingerprint {:?} does not match {:016x} for {}",
+                    kind,
+                    value,
+                    fingerprint,
+                    method.path()
+                )),
+                None => Status::failed_precondition(format!(
+                    "Missing the Avro {} schema fingerprint for {}",
+                    kind,
+                    method.path()
+                )),
+            };
+            return Box::pin(async move { Ok(status.to_http()) });
+        }
+
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let mut response = response.await?;
+            let headers = response.headers_mut();
+            headers.insert(
+                REQUEST_FINGERPRINT_HEADER,
+                fingerprint_header(method.request_fingerprint),
+            );
+            headers.insert(
+                RESPONSE_FINGERPRINT_HEADER,
+                fingerprint_header(method.response_fingerprint),
+            );
+            Ok(response)
+        })
+    }
+}
+
+/// An error encoding or decoding a message with its Avro schema.
+#[derive(Debug)]
+pub struct Error(String);
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl Schema {
+    /// A short description of the schema for error messages.
+    fn describe(&self) -> String {
+        match self {
+            Schema::Null => "null".to_string(),
+            Schema::Boolean => "boolean".to_string(),
+            Schema::Int => "int".to_string(),
+            Schema::Long => "long".to_string(),
+            Schema::Float => "float".to_string(),
+            Schema::Double => "double".to_string(),
+            Schema::Bytes => "bytes".to_string(),
+            Schema::String => "string".to_string(),
+            Schema::Array(_) => "array".to_string(),
+            Schema::Map(_) => "map".to_string(),
+            Schema::Union(_) => "union".to_string(),
+            Schema::Record { name, .. } => format!("record {}", name),
+            Schema::Enum { name, .. } => format!("enum {}", name),
+        }
+    }
+}
+
+fn mismatch(schema: &Schema, found: &str) -> Error {
+    Error(format!(
+        "Expected a value of the Avro schema {}, found {}",
+        schema.describe(),
+        found
+    ))
+}
+
+/// The two bytes starting messages in the Avro single object encoding.
+const SINGLE_OBJECT_MARKER: [u8; 2] = [0xc3, 0x01];
+
+/// Encode `item` with the binary encoding of its schema, without any header.
+pub fn to_vec<T: Serialize + AvroSchema + ?Sized>(item: &T) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    write(item, &T::schema(), &mut out)?;
+    Ok(out)
+}
+
+/// Decode a `T` from the binary encoding of its schema, without any header.
+pub fn from_slice<'de, T: Deserialize<'de> + AvroSchema>(input: &'de [u8]) -> Result<T, Error> {
+    read(input, &T::schema())
+}
+
+/// Write `item` with the binary encoding of `schema`, failing if it doesn't match the schema.
+pub(crate) fn write<T: Serialize + ?Sized>(
+    item: &T,
+    schema: &Schema,
+    out: &mut Vec<u8>,
+) -> Result<(), Error> {
+    item.serialize(Serializer { out, schema })
+}
+
+/// Read a value with the binary encoding of `schema`, which must be the whole of `input`.
+pub(crate) fn read<'de, T: Deserialize<'de>>(
+    input: &'de [u8],
+    schema: &Schema,
+) -> Result<T, Error> {
+    let mut reader = Reader { input };
+    let item = T::deserialize(Deserializer {
+        reader: &mut reader,
+        schema,
+    })?;
+    if reader.input.is_empty() {
+        Ok(item)
+    } else {
+        Err(Error(format!(
+            "{} trailing bytes after the message",
+            reader.input.len()
+        )))
+    }
+}
+
+/// Write the header of a message in the single object encoding, identifying its schema by
+/// `fingerprint`.
+pub(crate) fn write_header(fingerprint: u64, out: &mut Vec<u8>) {
+    out.extend_from_slice(&SINGLE_OBJECT_MARKER);
+    out.extend_from_slice(&fingerprint.to_le_bytes());
+}
+
+/// Split a message in the single object encoding into the fingerprint of its schema and
+/// its body.
+pub(crate) fn read_header(message: &[u8]) -> Result<(u64, &[u8]), Error> {
+    match message {
+        [0xc3, 0x01, rest @ ..] if rest.len() >= 8 => {
+            let (fingerprint, body) = rest.split_at(8);
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(fingerprint);
+            Ok((u64::from_le_bytes(bytes), body))
+        }
+        _ => Err(Error(
+            "Message is not in the Avro single object encoding".to_string(),
+        )),
+    }
+}
+
+/// Ints and longs are both zig-zag encoded variable length integers.
+fn write_long(out: &mut Vec<u8>, value: i64) {
+    let mut n = ((value << 1) ^ (value >> 63)) as u64;
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_long(out, bytes.len() as i64);
+    out.extend_from_slice(bytes);
+}
+
+/// Serializes a value with the binary encoding of `schema`.
+struct Serializer<'a> {
+    out: &'a mut Vec<u8>,
+    schema: &'a Schema,
+}
+
+impl<'a> Serializer<'a> {
+    /// The record which is the variant `index` of a union of records.
+    fn variant(self, index: u32) -> Result<Fields<'a>, Error> {
+        match self.schema {
+            Schema::Union(variants) => match variants.get(index as usize) {
+                Some(Schema::Record { fields, .. }) => {
+                    write_long(self.out, index.into());
+                    Ok(Fields::new(self.out, fields))
+                }
+                _ => Err(Error(format!("No record for the union variant {}", index))),
+            },
+            schema => Err(mismatch(schema, "an enum variant")),
+        }
+    }
+}
+
+impl<'a> ser::Serializer for Serializer<'a> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Block<'a>;
+    type SerializeTuple = Fields<'a>;
+    type SerializeTupleStruct = Fields<'a>;
+    type SerializeTupleVariant = Fields<'a>;
+    type SerializeMap = Block<'a>;
+    type SerializeStruct = Fields<'a>;
+    type SerializeStructVariant = Fields<'a>;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        match self.schema {
+            Schema::Boolean => {
+                self.out.push(v as u8);
+                Ok(())
+            }
+            schema => Err(mismatch(schema, "a boolean")),
+        }
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        match self.schema {
+            Schema::Int if i32::try_from(v).is_err() => {
+                Err(Error(format!("{} is too large for an int", v)))
+            }
+            Schema::Int | Schema::Long => {
+                write_long(self.out, v);
+                Ok(())
+            }
+            schema => Err(mismatch(schema, "an integer")),
+        }
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        let v = i64::try_from(v).map_err(|_| Error(format!("{} is too large for a long", v)))?;
+        self.serialize_i64(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        match self.schema {
+            Schema::Float => {
+                self.out.extend_from_slice(&v.to_le_bytes());
+                Ok(())
+            }
+            schema => Err(mismatch(schema, "a float")),
+        }
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        match self.schema {
+            Schema::Double => {
+                self.out.extend_from_slice(&v.to_le_bytes());
+                Ok(())
+            }
+            schema => Err(mismatch(schema, "a double")),
+        }
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        match self.schema {
+            Schema::String => {
+                write_bytes(self.out, v.as_bytes());
+                Ok(())
+            }
+            schema => Err(mismatch(schema, "a string")),
+        }
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        match self.schema {
+            Schema::Bytes => {
+                write_bytes(self.out, v);
+                Ok(())
+            }
+            schema => Err(mismatch(schema, "bytes")),
+        }
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        match self.schema {
+            Schema::Union(variants) => match variants.iter().position(|v| *v == Schema::Null) {
+                Some(index) => {
+                    write_long(self.out, index as i64);
+                    Ok(())
+                }
+                None => Err(Error("The union has no null variant".to_string())),
+            },
+            schema => Err(mismatch(schema, "an option")),
+        }
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        match self.schema {
+            Schema::Union(variants) => match variants.iter().position(|v| *v != Schema::Null) {
+                Some(index) => {
+                    write_long(self.out, index as i64);
+                    value.serialize(Serializer {
+                        out: self.out,
+                        schema: &variants[index],
+                    })
+                }
+                None => Err(Error("The union has no variant for a value".to_string())),
+            },
+            schema => Err(mismatch(schema, "an option")),
+        }
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        match self.schema {
+            Schema::Null => Ok(()),
+            schema => Err(mismatch(schema, "a unit")),
+        }
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        match self.schema {
+            Schema::Enum { symbols, .. } if (variant_index as usize) < symbols.len() => {
+                write_long(self.out, variant_index.into());
+                Ok(())
+            }
+            Schema::Enum { name, .. } => Err(Error(format!("No symbol {} in {}", variant, name))),
+            _ => ser::SerializeStruct::end(self.variant(variant_index)?),
+        }
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let mut fields = self.variant(variant_index)?;
+        ser::SerializeTupleVariant::serialize_field(&mut fields, value)?;
+        ser::SerializeTupleVariant::end(fields)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Block<'a>, Error> {
+        match self.schema {
+            Schema::Array(items) => Ok(Block::new(self.out, items)),
+            schema => Err(mismatch(schema, "a sequence")),
+        }
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Fields<'a>, Error> {
+        match self.schema {
+            Schema::Record { fields, .. } => Ok(Fields::new(self.out, fields)),
+            schema => Err(mismatch(schema, "a tuple")),
+        }
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Fields<'a>, Error> {
+        self.serialize_tuple(len)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Fields<'a>, Error> {
+        self.variant(variant_index)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Block<'a>, Error> {
+        match self.schema {
+            Schema::Map(values) => Ok(Block::new(self.out, values)),
+            schema => Err(mismatch(schema, "a map")),
+        }
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Fields<'a>, Error> {
+        self.serialize_tuple(len)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Fields<'a>, Error> {
+        self.variant(variant_index)
+    }
+}
+
+/// Arrays and maps are written as a single block of items, preceded by its length and
+/// followed by an empty block.
+struct Block<'a> {
+    out: &'a mut Vec<u8>,
+    /// The schema of the items of an array or of the values of a map.
+    items: &'a Schema,
+    start: usize,
+    len: i64,
+}
+
+impl<'a> Block<'a> {
+    fn new(out: &'a mut Vec<u8>, items: &'a Schema) -> Self {
+        let start = out.len();
+        Block {
+            out,
+            items,
+            start,
+            len: 0,
+        }
+    }
+
+    fn item<T: Serialize + ?Sized>(&mut self, schema: &Schema, value: &T) -> Result<(), Error> {
+        value.serialize(Serializer {
+            out: self.out,
+            schema,
+        })
+    }
+
+    fn end(self) -> Result<(), Error> {
+        if self.len > 0 {
+            let items = self.out.split_off(self.start);
+            write_long(self.out, self.len);
+            self.out.extend_from_slice(&items);
+        }
+        write_long(self.out, 0);
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for Block<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.len += 1;
+        self.item(self.items, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Block::end(self)
+    }
+}
+
+impl ser::SerializeMap for Block<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.len += 1;
+        self.item(&Schema::String, key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.item(self.items, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Block::end(self)
+    }
+}
+
+/// The fields of a record, written in the order of the schema.
+struct Fields<'a> {
+    out: &'a mut Vec<u8>,
+    fields: &'a [(String, Schema)],
+    next: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(out: &'a mut Vec<u8>, fields: &'a [(String, Schema)]) -> Self {
+        Fields {
+            out,
+            fields,
+            next: 0,
+        }
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, name: Option<&str>, value: &T) -> Result<(), Error> {
+        let (field, schema) = self
+            .fields
+            .get(self.next)
+            .ok_or_else(|| Error(format!("More than {} fields", self.fields.len())))?;
+        if let Some(name) = name.filter(|name| name != field) {
+            return Err(Error(format!(
+                "Expected the field {}, found {}",
+                field, name
+            )));
+        }
+        self.next += 1;
+        value.serialize(Serializer {
+            out: self.out,
+            schema,
+        })
+    }
+
+    fn end(self) -> Result<(), Error> {
+        match self.fields.get(self.next) {
+            Some((field, _)) => Err(Error(format!("Missing the field {}", field))),
+            None => Ok(()),
+        }
+    }
+}
+
+macro_rules! serialize_fields {
+    ($($trait:ident :: $method:ident),*) => {
+        $(
+            impl ser::$trait for Fields<'_> {
+                type Ok = ();
+                type Error = Error;
+
+                fn $method<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+                    self.field(None, value)
+                }
+
+                fn end(self) -> Result<(), Error> {
+                    Fields::end(self)
+                }
+            }
+        )*
+    };
+}
+
+serialize_fields!(
+    SerializeTuple::serialize_element,
+    SerializeTupleStruct::serialize_field,
+    SerializeTupleVariant::serialize_field
+);
+
+impl ser::SerializeStruct for Fields<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(Some(key), value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Fields::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for Fields<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(Some(key), value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Fields::end(self)
+    }
+}
+
+/// The part of a message which hasn't been read yet.
+struct Reader<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> Reader<'de> {
+    fn read_exact(&mut self, len: usize) -> Result<&'de [u8], Error> {
+        if self.input.len() < len {
+            return Err(Error("Unexpected end of message".to_string()));
+        }
+        let (bytes, rest) = self.input.split_at(len);
+        self.input = rest;
+        Ok(bytes)
+    }
+
+    fn read_long(&mut self) -> Result<i64, Error> {
+        let mut n = 0_u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_exact(1)?[0];
+            n |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok((n >> 1) as i64 ^ -((n & 1) as i64));
+            }
+        }
+        Err(Error("Variable length integer is too long".to_string()))
+    }
+
+    fn read_index(&mut self, len: usize) -> Result<usize, Error> {
+        let index = self.read_long()?;
+        usize::try_from(index)
+            .ok()
+            .filter(|index| *index < len)
+            .ok_or_else(|| Error(format!("Invalid index {} of {}", index, len)))
+    }
+
+    fn read_bytes(&mut self) -> Result<&'de [u8], Error> {
+        let len = self.read_long()?;
+        let len = usize::try_from(len).map_err(|_| Error(format!("Invalid length {}", len)))?;
+        self.read_exact(len)
+    }
+
+    fn read_str(&mut self) -> Result<&'de str, Error> {
+        std::str::from_utf8(self.read_bytes()?).map_err(|err| Error(err.to_string()))
+    }
+}
+
+/// Deserializes a value with the binary encoding of `schema`.
+struct Deserializer<'a, 'de> {
+    reader: &'a mut Reader<'de>,
+    schema: &'a Schema,
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'_, 'de> {
+    type Error = Error;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let reader = self.reader;
+        match self.schema {
+            Schema::Null => visitor.visit_unit(),
+            Schema::Boolean => match reader.read_exact(1)?[0] {
+                0 => visitor.visit_bool(false),
+                1 => visitor.visit_bool(true),
+                other => Err(Error(format!("Invalid boolean {}", other))),
+            },
+            Schema::Int => {
+                let value = reader.read_long()?;
+                let value = i32::try_from(value)
+                    .map_err(|_| Error(format!("{} is out of range for an int", value)))?;
+                visitor.visit_i32(value)
+            }
+            Schema::Long => visitor.visit_i64(reader.read_long()?),
+            Schema::Float => {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(reader.read_exact(4)?);
+                visitor.visit_f32(f32::from_le_bytes(bytes))
+            }
+            Schema::Double => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(reader.read_exact(8)?);
+                visitor.visit_f64(f64::from_le_bytes(bytes))
+            }
+            Schema::Bytes => visitor.visit_borrowed_bytes(reader.read_bytes()?),
+            Schema::String => visitor.visit_borrowed_str(reader.read_str()?),
+            Schema::Array(items) => visitor.visit_seq(Blocks::new(reader, items)),
+            Schema::Map(values) => visitor.visit_map(Blocks::new(reader, values)),
+            Schema::Union(variants) => {
+                let index = reader.read_index(variants.len())?;
+                Deserializer {
+                    reader,
+                    schema: &variants[index],
+                }
+                .deserialize_any(visitor)
+            }
+            Schema::Record { fields, .. } => visitor.visit_map(Record::new(reader, fields)),
+            Schema::Enum { symbols, .. } => {
+                let index = reader.read_index(symbols.len())?;
+                visitor.visit_str(&symbols[index])
+            }
+        }
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.schema {
+            Schema::Union(variants) => {
+                let index = self.reader.read_index(variants.len())?;
+                match &variants[index] {
+                    Schema::Null => visitor.visit_none(),
+                    schema => visitor.visit_some(Deserializer {
+                        reader: self.reader,
+                        schema,
+                    }),
+                }
+            }
+            schema => Err(mismatch(schema, "an option")),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_tuple<V: de::Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.schema {
+            Schema::Record { fields, .. } => visitor.visit_seq(Record::new(self.reader, fields)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.schema {
+            Schema::Enum { symbols, .. } => {
+                let index = self.reader.read_index(symbols.len())?;
+                visitor.visit_enum(Variant {
+                    reader: self.reader,
+                    index,
+                    fields: &[],
+                })
+            }
+            Schema::Union(variants) => {
+                let index = self.reader.read_index(variants.len())?;
+                match &variants[index] {
+                    Schema::Record { fields, .. } => visitor.visit_enum(Variant {
+                        reader: self.reader,
+                        index,
+                        fields,
+                    }),
+                    schema => Err(mismatch(schema, "an enum variant")),
+                }
+            }
+            schema => Err(mismatch(schema, "an enum")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct seq map struct identifier ignored_any
+    }
+}
+
+/// A variant of an enum: a symbol of an Avro enum, or a record of a union.
+struct Variant<'a, 'de> {
+    reader: &'a mut Reader<'de>,
+    index: usize,
+    fields: &'a [(String, Schema)],
+}
+
+impl<'a, 'de> de::EnumAccess<'de> for Variant<'a, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let index =
+            u32::try_from(self.index).map_err(|_| Error("Too many variants".to_string()))?;
+        let value = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(index))?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Variant<'_, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.fields {
+            [] => Ok(()),
+            _ => Err(Error(format!("Variant {} is not a unit", self.index))),
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        match self.fields {
+            [(_, schema)] => seed.deserialize(Deserializer {
+                reader: self.reader,
+                schema,
+            }),
+            _ => Err(Error(format!("Variant {} is not a newtype", self.index))),
+        }
+    }
+
+    fn tuple_variant<V: de::Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_seq(Record::new(self.reader, self.fields))
+    }
+
+    fn struct_variant<V: de::Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_map(Record::new(self.reader, self.fields))
+    }
+}
+
+/// The fields of a record, read as a map from their names or as a tuple.
+struct Record<'a, 'de> {
+    reader: &'a mut Reader<'de>,
+    fields: std::slice::Iter<'a, (String, Schema)>,
+    /// The schema of the value of the field whose name was just read.
+    value: Option<&'a Schema>,
+}
+
+impl<'a, 'de> Record<'a, 'de> {
+    fn new(reader: &'a mut Reader<'de>, fields: &'a [(String, Schema)]) -> Self {
+        Record {
+            reader,
+            fields: fields.iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for Record<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.fields.next() {
+            Some((_, schema)) => seed
+                .deserialize(Deserializer {
+                    reader: self.reader,
+                    schema,
+                })
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
+}
+
+impl<'de> de::MapAccess<'de> for Record<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.fields.next() {
+            Some((name, schema)) => {
+                self.value = Some(schema);
+                seed.deserialize(de::value::StrDeserializer::<Error>::new(name))
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let schema = self
+            .value
+            .take()
+            .ok_or_else(|| Error("Record value read before its field name".to_string()))?;
+        seed.deserialize(Deserializer {
+            reader: self.reader,
+            schema,
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
+}
+
+/// The items of an array or map, which are split into blocks.
+struct Blocks<'a, 'de> {
+    reader: &'a mut Reader<'de>,
+    /// The schema of the items of an array or of the values of a map.
+    items: &'a Schema,
+    remaining: u64,
+}
+
+impl<'a, 'de> Blocks<'a, 'de> {
+    fn new(reader: &'a mut Reader<'de>, items: &'a Schema) -> Self {
+        Blocks {
+            reader,
+            items,
+            remaining: 0,
+        }
+    }
+
+    /// Whether there is another item, reading the next block header if needed.
+    fn next(&mut self) -> Result<bool, Error> {
+        if self.remaining == 0 {
+            let len = self.reader.read_long()?;
+            if len < 0 {
+                // Negative lengths are followed by the size of the block in bytes.
+                self.reader.read_long()?;
+            }
+            self.remaining = len.unsigned_abs();
+        }
+        if self.remaining == 0 {
+            return Ok(false);
+        }
+        self.remaining -= 1;
+        Ok(true)
+    }
+
+    fn item<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(Deserializer {
+            reader: self.reader,
+            schema: self.items,
+        })
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for Blocks<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.next()? {
+            self.item(seed).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for Blocks<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if self.next()? {
+            let key = self.reader.read_str()?;
+            seed.deserialize(de::value::BorrowedStrDeserializer::<Error>::new(key))
+                .map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        self.item(seed)
+    }
+}
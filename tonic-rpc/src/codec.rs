@@ -3,30 +3,243 @@ use std::{
     marker::PhantomData,
 };
 
-use bytes::{buf::BufMut, Buf};
+use bytes::{buf::BufMut, Buf, Bytes};
 use serde::{Deserialize, Serialize};
 use tonic::{codec, Status};
 
+use crate::instrument::{MessageBytes, RequestSize};
+
+/// An encoding of `serde` messages.
+///
+/// Codecs are values, so they can hold configuration such as limits, keys or formatting
+/// options. A codec is cloned into the `Encoder` and `Decoder` of every call, so codecs
+/// should be cheap to clone.
 pub trait SerdeCodec {
-    fn write<T, W>(item: T, w: W) -> Result<(), Status>
+    fn write<T, W>(&self, item: T, w: W) -> Result<(), Status>
     where
         T: Serialize,
         W: Write;
 
-    fn read<T, R>(r: R) -> Result<T, Status>
+    fn read<T, R>(&self, r: R) -> Result<T, Status>
     where
         T: for<'de> Deserialize<'de>,
         R: Read;
+
+    /// Write a message sent at `position` in a call. Codecs which bind messages to their
+    /// call, such as [`Encrypted`], override this; others write the message as usual.
+    fn write_at<T, W>(&self, item: T, w: W, position: &MessagePosition) -> Result<(), Status>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        let _ = position;
+        self.write(item, w)
+    }
+
+    /// Read a message received at `position` in a call.
+    fn read_at<T, R>(&self, r: R, position: &MessagePosition) -> Result<T, Status>
+    where
+        T: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        let _ = position;
+        self.read(r)
+    }
+}
+
+/// A [`SerdeCodec`] which writes a message the same way wherever it's sent in a call, so
+/// that it can be serialized ahead of time into an [`Encoded`] message with
+/// [`Encoded::new`].
+///
+/// Codecs binding messages to their [`MessagePosition`], such as [`Encrypted`], don't
+/// implement this, so that their messages can only be encoded for a given position with
+/// [`Encoded::new_at`].
+pub trait PositionIndependent: SerdeCodec {}
+
+/// A [`SerdeCodec`] encoding messages as JSON, whose messages can be read and written as
+/// JSON by the JSON adapters of generated servers using it.
+pub trait JsonEncoding: SerdeCodec {}
+
+/// Whether a message is sent by the client or by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Request,
+    Response,
+}
+
+/// Where a message is sent in a call: the method, the direction of the message and its
+/// index among the messages sent in that direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessagePosition {
+    /// The path of the method, e.g. `/increment.Increment/Increment`.
+    pub path: &'static str,
+    pub direction: Direction,
+    pub sequence: u64,
+}
+
+/// A message sent by a method: either a `serde` value or an [`Encoded`] message.
+pub trait EncodeMessage {
+    /// The type of the message once it's decoded.
+    type Decoded;
+}
+
+/// A message written by an [`Encoder`] using the codec `C`: either a `serde` value, which is
+/// serialized by the codec, or an [`Encoded`] message serialized with the same codec type,
+/// which is written as it is.
+pub trait EncodeWith<C>: EncodeMessage {
+    fn encode<W: Write>(
+        self,
+        codec: &C,
+        w: W,
+        position: Option<&MessagePosition>,
+    ) -> Result<(), Status>;
+}
+
+impl<T: Serialize> EncodeMessage for T {
+    type Decoded = T;
+}
+
+impl<T: Serialize, C: SerdeCodec> EncodeWith<C> for T {
+    fn encode<W: Write>(
+        self,
+        codec: &C,
+        w: W,
+        position: Option<&MessagePosition>,
+    ) -> Result<(), Status> {
+        match position {
+            Some(position) => codec.write_at(self, w, position),
+            None => codec.write(self, w),
+        }
+    }
+}
+
+/// A message which has already been serialized, so that it can be sent many times
+/// without serializing it again, e.g. to send the same update to every subscriber.
+///
+/// Services declare `codec::Encoded<T, C>` in place of `T` as the argument or return type of
+/// a method, where `C` is the codec of the method, e.g. `codec::Encoded<Update, JsonSerdeCodec>`.
+/// The path is required so that the macro can tell it from other types named `Encoded`. The
+/// side sending the message then sends `Encoded<T, C>` values, while the side receiving it
+/// receives `T` as usual. Messages can only be sent by a method whose codec is `C`, so a
+/// message encoded with another codec is rejected at compile time. Cloning an `Encoded`
+/// message doesn't copy the bytes.
+pub struct Encoded<T, C> {
+    bytes: Bytes,
+    _pd: PhantomData<fn() -> (T, C)>,
+}
+
+impl<T, C: PositionIndependent> Encoded<T, C> {
+    /// Serialize `item` with `codec`.
+    pub fn new(codec: &C, item: &T) -> Result<Self, Status>
+    where
+        T: Serialize,
+    {
+        Self::new_as(codec, item)
+    }
+
+    /// Serialize `item`, a borrowed form of `T` with the same serialized form, e.g. a `&str`
+    /// for a `String` or a `&[u8]` for a `Vec<u8>`.
+    pub fn new_as<B>(codec: &C, item: &B) -> Result<Self, Status>
+    where
+        B: Serialize + ?Sized,
+    {
+        let mut bytes = Vec::new();
+        codec.write(item, &mut bytes)?;
+        Ok(Encoded {
+            bytes: bytes.into(),
+            _pd: PhantomData,
+        })
+    }
+}
+
+impl<T, C: SerdeCodec> Encoded<T, C> {
+    /// Serialize `item`, a borrowed form of `T`, to be sent at `position` in a call. This is
+    /// the only way to encode messages with a codec which isn't [`PositionIndependent`], and
+    /// the message fails to decode if it's sent anywhere else.
+    pub fn new_at<B>(codec: &C, item: &B, position: &MessagePosition) -> Result<Self, Status>
+    where
+        B: Serialize + ?Sized,
+    {
+        let mut bytes = Vec::new();
+        codec.write_at(item, &mut bytes, position)?;
+        Ok(Encoded {
+            bytes: bytes.into(),
+            _pd: PhantomData,
+        })
+    }
+}
+
+impl<T, C> Encoded<T, C> {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl<T, C> Clone for Encoded<T, C> {
+    fn clone(&self) -> Self {
+        Encoded {
+            bytes: self.bytes.clone(),
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<T, C> std::fmt::Debug for Encoded<T, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encoded")
+            .field("len", &self.bytes.len())
+            .finish()
+    }
+}
+
+impl<T, C> EncodeMessage for Encoded<T, C> {
+    type Decoded = T;
+}
+
+impl<T, C> EncodeWith<C> for Encoded<T, C> {
+    fn encode<W: Write>(
+        self,
+        _codec: &C,
+        mut w: W,
+        _position: Option<&MessagePosition>,
+    ) -> Result<(), Status> {
+        w.write_all(&self.bytes)
+            .map_err(|io_err| Status::internal(format!("Error writing {}", io_err)))
+    }
+}
+
+/// The method and direction of the messages of a stream, and the position of the next one.
+#[derive(Clone)]
+struct Stream {
+    path: &'static str,
+    direction: Direction,
+    sequence: u64,
+}
+
+impl Stream {
+    fn next(&mut self) -> MessagePosition {
+        let position = MessagePosition {
+            path: self.path,
+            direction: self.direction,
+            sequence: self.sequence,
+        };
+        self.sequence += 1;
+        position
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Encoder<C, T> {
-    _pd: PhantomData<(C, T)>,
+    codec: C,
+    stream: Option<Stream>,
+    size: RequestSize,
+    bytes: MessageBytes,
+    _pd: PhantomData<T>,
 }
 
 impl<C, T> codec::Encoder for Encoder<C, T>
 where
-    T: Serialize,
+    T: EncodeWith<C>,
     C: SerdeCodec,
 {
     type Item = T;
@@ -36,13 +249,23 @@ where
         item: Self::Item,
         dst: &mut codec::EncodeBuf<'_>,
     ) -> Result<(), Self::Error> {
-        C::write(item, dst.writer())
+        let remaining = dst.remaining_mut();
+        let position = self.stream.as_mut().map(Stream::next);
+        item.encode(&self.codec, dst.writer(), position.as_ref())?;
+        let len = remaining - dst.remaining_mut();
+        self.size.add(len);
+        self.bytes.add(len);
+        Ok(())
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Decoder<C, T> {
-    _pd: PhantomData<(C, T)>,
+    codec: C,
+    stream: Option<Stream>,
+    size: RequestSize,
+    bytes: MessageBytes,
+    _pd: PhantomData<T>,
 }
 
 impl<C, T> codec::Decoder for Decoder<C, T>
@@ -56,24 +279,70 @@ where
         &mut self,
         src: &mut codec::DecodeBuf<'_>,
     ) -> Result<Option<Self::Item>, Self::Error> {
-        Ok(Some(C::read::<T, _>(src.reader())?))
+        self.size.add(src.remaining());
+        self.bytes.add(src.remaining());
+        let item = match self.stream.as_mut().map(Stream::next) {
+            Some(position) => self.codec.read_at::<T, _>(src.reader(), &position)?,
+            None => self.codec.read::<T, _>(src.reader())?,
+        };
+        Ok(Some(item))
     }
 }
 
+/// A `tonic` codec encoding messages of type `T` and decoding messages of type `U`
+/// with the `serde` codec `C`, which is cloned into each `Encoder` and `Decoder`.
 pub struct Codec<C, T, U> {
-    _pd: PhantomData<(C, T, U)>,
+    codec: C,
+    /// The path of the method and the direction of the encoded messages, if the codec is
+    /// used for a known method.
+    method: Option<(&'static str, Direction)>,
+    _pd: PhantomData<(T, U)>,
+}
+
+impl<C, T, U> Codec<C, T, U> {
+    pub fn new(codec: C) -> Self {
+        Codec {
+            codec,
+            method: None,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Encode and decode the messages of the method at `path`, as its client or, if
+    /// `server`, as its server, passing the position of each message to the codec.
+    pub fn for_method(mut self, path: &'static str, server: bool) -> Self {
+        let direction = if server {
+            Direction::Response
+        } else {
+            Direction::Request
+        };
+        self.method = Some((path, direction));
+        self
+    }
+
+    fn stream(&self, encoder: bool) -> Option<Stream> {
+        self.method.map(|(path, direction)| Stream {
+            path,
+            direction: match (encoder, direction) {
+                (true, direction) => direction,
+                (false, Direction::Request) => Direction::Response,
+                (false, Direction::Response) => Direction::Request,
+            },
+            sequence: 0,
+        })
+    }
 }
 
-impl<C, T, U> Default for Codec<C, T, U> {
+impl<C: Default, T, U> Default for Codec<C, T, U> {
     fn default() -> Self {
-        Codec { _pd: PhantomData }
+        Codec::new(C::default())
     }
 }
 
 impl<C, T, U> codec::Codec for Codec<C, T, U>
 where
-    C: SerdeCodec + Send + Sync + 'static,
-    T: Serialize + Send + Sync + 'static,
+    C: SerdeCodec + Clone + Send + Sync + 'static,
+    T: EncodeWith<C> + Send + Sync + 'static,
     U: for<'de> Deserialize<'de> + Send + Sync + 'static,
 {
     type Encode = T;
@@ -82,31 +351,129 @@ where
     type Decoder = Decoder<C, U>;
 
     fn encoder(&mut self) -> Self::Encoder {
-        Encoder { _pd: PhantomData }
+        Encoder {
+            codec: self.codec.clone(),
+            stream: self.stream(true),
+            size: RequestSize::encoder(),
+            bytes: MessageBytes::encoder(),
+            _pd: PhantomData,
+        }
     }
 
     fn decoder(&mut self) -> Self::Decoder {
-        Decoder { _pd: PhantomData }
+        Decoder {
+            codec: self.codec.clone(),
+            stream: self.stream(false),
+            size: RequestSize::decoder(),
+            bytes: MessageBytes::decoder(),
+            _pd: PhantomData,
+        }
+    }
+}
+
+/// Builds the `tonic` codec used for a method with request type `T` and response type `U`.
+///
+/// The generated clients and servers hold a value implementing `MakeCodec`, passed to
+/// `with_codec`, and build a codec from it for each call. This is implemented for every
+/// [`SerdeCodec`], so a configured codec such as [`Encrypted`] can be used.
+pub trait MakeCodec<T, U> {
+    type Codec: codec::Codec<Encode = T, Decode = U> + Send + 'static;
+
+    fn make_codec(&self) -> Self::Codec;
+
+    /// Build the codec used by the client, or the server if `server`, of the method at
+    /// `path`. The generated clients and servers call this rather than `make_codec`.
+    fn make_method_codec(&self, path: &'static str, server: bool) -> Self::Codec {
+        let _ = (path, server);
+        self.make_codec()
+    }
+}
+
+impl<C, T, U> MakeCodec<T, U> for C
+where
+    C: SerdeCodec + Clone + Send + Sync + 'static,
+    T: EncodeWith<C> + Send + Sync + 'static,
+    U: for<'de> Deserialize<'de> + Send + Sync + 'static,
+{
+    type Codec = Codec<C, T, U>;
+
+    fn make_codec(&self) -> Self::Codec {
+        Codec::new(self.clone())
+    }
+
+    fn make_method_codec(&self, path: &'static str, server: bool) -> Self::Codec {
+        Codec::new(self.clone()).for_method(path, server)
     }
 }
 
 #[cfg(feature = "bincode")]
 #[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+#[derive(Debug, Clone, Copy, Default)]
 pub struct BincodeSerdeCodec;
+/// Encodes messages like [`BincodeSerdeCodec`], but rejects messages with an encoding
+/// longer than a limit with [`Code::ResourceExhausted`](tonic::Code::ResourceExhausted).
+///
+/// `bincode` allocates collections with the length given in the message, so a limit
+/// bounds the memory used to decode a malformed or malicious message.
+#[cfg(feature = "bincode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+#[derive(Debug, Clone, Copy)]
+pub struct LimitedBincodeSerdeCodec {
+    limit: u64,
+}
+/// Encodes messages as [BSON](https://bsonspec.org/) documents.
+///
+/// BSON only allows a document at the top level, so messages which don't serialize to a
+/// document (such as numbers, sequences or the tuples used for methods with several
+/// arguments) are wrapped in a document with a single `$value` field.
+#[cfg(feature = "bson")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bson")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BsonSerdeCodec;
 #[cfg(feature = "cbor")]
 #[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+#[derive(Debug, Clone, Copy, Default)]
 pub struct CborSerdeCodec;
+/// Encodes messages using the deterministic encoding of
+/// [RFC 8949](https://www.rfc-editor.org/rfc/rfc8949.html#section-4.2), so that equal
+/// messages are always encoded to the same bytes: map keys are sorted by their encoding
+/// and integers, floats and lengths use their shortest form.
+#[cfg(feature = "cbor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CanonicalCborSerdeCodec;
 #[cfg(feature = "json")]
 #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+#[derive(Debug, Clone, Copy, Default)]
 pub struct JsonSerdeCodec;
+/// Encodes messages as indented JSON, which is easier to read when inspecting traffic.
+/// Messages are decoded like [`JsonSerdeCodec`].
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+#[derive(Debug, Clone)]
+pub struct PrettyJsonSerdeCodec {
+    indent: Vec<u8>,
+}
 #[cfg(feature = "messagepack")]
 #[cfg_attr(docsrs, doc(cfg(feature = "messagepack")))]
+#[derive(Debug, Clone, Copy, Default)]
 pub struct MessagePackSerdeCodec;
 
+#[cfg(feature = "postcard")]
+#[cfg_attr(docsrs, doc(cfg(feature = "postcard")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PostcardSerdeCodec;
+/// Encodes messages as pretty-printed [RON](https://github.com/ron-rs/ron), including struct
+/// names, so that captured traffic can be read as Rust values.
+#[cfg(feature = "ron")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ron")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RonSerdeCodec;
+
 #[cfg(feature = "bincode")]
 #[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
 impl SerdeCodec for BincodeSerdeCodec {
-    fn write<T, W>(item: T, w: W) -> Result<(), Status>
+    fn write<T, W>(&self, item: T, w: W) -> Result<(), Status>
     where
         T: Serialize,
         W: Write,
@@ -115,7 +482,7 @@ impl SerdeCodec for BincodeSerdeCodec {
             .map_err(|bincode_err| Status::internal(format!("Error serializing {}", bincode_err)))
     }
 
-    fn read<T, R>(r: R) -> Result<T, Status>
+    fn read<T, R>(&self, r: R) -> Result<T, Status>
     where
         T: for<'de> Deserialize<'de>,
         R: Read,
@@ -125,10 +492,117 @@ impl SerdeCodec for BincodeSerdeCodec {
     }
 }
 
+#[cfg(feature = "bincode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+impl PositionIndependent for BincodeSerdeCodec {}
+
+#[cfg(feature = "bincode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+impl LimitedBincodeSerdeCodec {
+    /// Limit the encoding of each message to `limit` bytes.
+    pub fn new(limit: u64) -> Self {
+        LimitedBincodeSerdeCodec { limit }
+    }
+
+    fn options(&self) -> impl bincode::Options {
+        use bincode::Options;
+
+        // The same encoding as `bincode::serialize`.
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(self.limit)
+    }
+}
+
+#[cfg(feature = "bincode")]
+fn bincode_status(message: &str, bincode_err: bincode::Error) -> Status {
+    match *bincode_err {
+        bincode::ErrorKind::SizeLimit => {
+            Status::resource_exhausted(format!("{} {}", message, bincode_err))
+        }
+        _ => Status::internal(format!("{} {}", message, bincode_err)),
+    }
+}
+
+#[cfg(feature = "bincode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+impl SerdeCodec for LimitedBincodeSerdeCodec {
+    fn write<T, W>(&self, item: T, w: W) -> Result<(), Status>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        bincode::Options::serialize_into(self.options(), w, &item)
+            .map_err(|bincode_err| bincode_status("Error serializing", bincode_err))
+    }
+
+    fn read<T, R>(&self, r: R) -> Result<T, Status>
+    where
+        T: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        bincode::Options::deserialize_from(self.options(), r)
+            .map_err(|bincode_err| bincode_status("Error deserializing", bincode_err))
+    }
+}
+
+#[cfg(feature = "bincode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+impl PositionIndependent for LimitedBincodeSerdeCodec {}
+
+/// The field used to wrap messages which aren't documents. Field names starting with `$`
+/// are reserved by MongoDB, so this won't clash with the fields of a stored document.
+#[cfg(feature = "bson")]
+const BSON_VALUE_FIELD: &str = "$value";
+
+#[cfg(feature = "bson")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bson")))]
+impl SerdeCodec for BsonSerdeCodec {
+    fn write<T, W>(&self, item: T, mut w: W) -> Result<(), Status>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        let document = match bson::to_bson(&item)
+            .map_err(|bson_err| Status::internal(format!("Error serializing {}", bson_err)))?
+        {
+            bson::Bson::Document(document) => document,
+            value => bson::doc! { BSON_VALUE_FIELD: value },
+        };
+        document
+            .to_writer(&mut w)
+            .map_err(|bson_err| Status::internal(format!("Error serializing {}", bson_err)))
+    }
+
+    fn read<T, R>(&self, mut r: R) -> Result<T, Status>
+    where
+        T: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        let mut document = bson::Document::from_reader(&mut r)
+            .map_err(|bson_err| Status::internal(format!("Error deserializing {}", bson_err)))?;
+        let result = if document.len() == 1 && document.contains_key(BSON_VALUE_FIELD) {
+            bson::from_bson(
+                document
+                    .remove(BSON_VALUE_FIELD)
+                    .unwrap_or(bson::Bson::Null),
+            )
+        } else {
+            bson::from_document(document)
+        };
+        result.map_err(|bson_err| Status::internal(format!("Error deserializing {}", bson_err)))
+    }
+}
+
+#[cfg(feature = "bson")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bson")))]
+impl PositionIndependent for BsonSerdeCodec {}
+
 #[cfg(feature = "cbor")]
 #[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
 impl SerdeCodec for CborSerdeCodec {
-    fn write<T, W>(item: T, w: W) -> Result<(), Status>
+    fn write<T, W>(&self, item: T, w: W) -> Result<(), Status>
     where
         T: Serialize,
         W: Write,
@@ -137,7 +611,7 @@ impl SerdeCodec for CborSerdeCodec {
             .map_err(|serde_err| Status::internal(format!("Error serializing {}", serde_err)))
     }
 
-    fn read<T, R>(r: R) -> Result<T, Status>
+    fn read<T, R>(&self, r: R) -> Result<T, Status>
     where
         T: for<'de> Deserialize<'de>,
         R: Read,
@@ -147,10 +621,100 @@ impl SerdeCodec for CborSerdeCodec {
     }
 }
 
+#[cfg(feature = "cbor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+impl PositionIndependent for CborSerdeCodec {}
+
+/// Write the head of a CBOR data item with the shortest encoding of `value`.
+#[cfg(feature = "cbor")]
+fn write_cbor_head(major_type: u8, value: u64, out: &mut Vec<u8>) {
+    let major_type = major_type << 5;
+    if value < 24 {
+        out.push(major_type | value as u8);
+    } else if value <= u8::MAX as u64 {
+        out.push(major_type | 24);
+        out.push(value as u8);
+    } else if value <= u16::MAX as u64 {
+        out.push(major_type | 25);
+        out.extend_from_slice(&(value as u16).to_be_bytes());
+    } else if value <= u32::MAX as u64 {
+        out.push(major_type | 26);
+        out.extend_from_slice(&(value as u32).to_be_bytes());
+    } else {
+        out.push(major_type | 27);
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+#[cfg(feature = "cbor")]
+fn write_canonical_cbor(value: &serde_cbor::Value, out: &mut Vec<u8>) -> serde_cbor::Result<()> {
+    use serde_cbor::Value;
+
+    match value {
+        Value::Array(values) => {
+            write_cbor_head(4, values.len() as u64, out);
+            for value in values {
+                write_canonical_cbor(value, out)?;
+            }
+        }
+        Value::Map(entries) => {
+            let mut encoded = Vec::with_capacity(entries.len());
+            for (key, value) in entries {
+                let mut key_bytes = Vec::new();
+                write_canonical_cbor(key, &mut key_bytes)?;
+                encoded.push((key_bytes, value));
+            }
+            encoded.sort_by(|(a, _), (b, _)| a.cmp(b));
+            write_cbor_head(5, encoded.len() as u64, out);
+            for (key_bytes, value) in encoded {
+                out.extend_from_slice(&key_bytes);
+                write_canonical_cbor(value, out)?;
+            }
+        }
+        Value::Tag(tag, value) => {
+            write_cbor_head(6, *tag, out);
+            write_canonical_cbor(value, out)?;
+        }
+        // `serde_cbor` already uses the shortest encoding for other values.
+        other => serde_cbor::to_writer(out, other)?,
+    }
+    Ok(())
+}
+
+#[cfg(feature = "cbor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+impl SerdeCodec for CanonicalCborSerdeCodec {
+    fn write<T, W>(&self, item: T, mut w: W) -> Result<(), Status>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        let value = serde_cbor::value::to_value(&item)
+            .map_err(|serde_err| Status::internal(format!("Error serializing {}", serde_err)))?;
+        let mut bytes = Vec::new();
+        write_canonical_cbor(&value, &mut bytes)
+            .map_err(|serde_err| Status::internal(format!("Error serializing {}", serde_err)))?;
+        w.write_all(&bytes)
+            .map_err(|io_err| Status::internal(format!("Error writing {}", io_err)))
+    }
+
+    fn read<T, R>(&self, r: R) -> Result<T, Status>
+    where
+        T: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        CborSerdeCodec.read(r)
+    }
+}
+
+#[cfg(feature = "cbor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+impl PositionIndependent for CanonicalCborSerdeCodec {}
+
 #[cfg(feature = "json")]
 #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
 impl SerdeCodec for JsonSerdeCodec {
-    fn write<T, W>(item: T, w: W) -> Result<(), Status>
+    fn write<T, W>(&self, item: T, w: W) -> Result<(), Status>
     where
         T: Serialize,
         W: Write,
@@ -159,7 +723,7 @@ impl SerdeCodec for JsonSerdeCodec {
             .map_err(|serde_err| Status::internal(format!("Error serializing {}", serde_err)))
     }
 
-    fn read<T, R>(r: R) -> Result<T, Status>
+    fn read<T, R>(&self, r: R) -> Result<T, Status>
     where
         T: for<'de> Deserialize<'de>,
         R: Read,
@@ -169,10 +733,66 @@ impl SerdeCodec for JsonSerdeCodec {
     }
 }
 
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+impl PositionIndependent for JsonSerdeCodec {}
+
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+impl JsonEncoding for JsonSerdeCodec {}
+
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+impl PrettyJsonSerdeCodec {
+    /// Indent nested values by `indent` spaces.
+    pub fn new(indent: usize) -> Self {
+        PrettyJsonSerdeCodec {
+            indent: vec![b' '; indent],
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl Default for PrettyJsonSerdeCodec {
+    fn default() -> Self {
+        PrettyJsonSerdeCodec::new(2)
+    }
+}
+
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+impl SerdeCodec for PrettyJsonSerdeCodec {
+    fn write<T, W>(&self, item: T, w: W) -> Result<(), Status>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(&self.indent);
+        item.serialize(&mut serde_json::Serializer::with_formatter(w, formatter))
+            .map_err(|serde_err| Status::internal(format!("Error serializing {}", serde_err)))
+    }
+
+    fn read<T, R>(&self, r: R) -> Result<T, Status>
+    where
+        T: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        JsonSerdeCodec.read(r)
+    }
+}
+
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+impl PositionIndependent for PrettyJsonSerdeCodec {}
+
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+impl JsonEncoding for PrettyJsonSerdeCodec {}
+
 #[cfg(feature = "messagepack")]
 #[cfg_attr(docsrs, doc(cfg(feature = "messagepack")))]
 impl SerdeCodec for MessagePackSerdeCodec {
-    fn write<T, W>(item: T, mut w: W) -> Result<(), Status>
+    fn write<T, W>(&self, item: T, mut w: W) -> Result<(), Status>
     where
         T: Serialize,
         W: Write,
@@ -182,7 +802,7 @@ impl SerdeCodec for MessagePackSerdeCodec {
         })
     }
 
-    fn read<T, R>(r: R) -> Result<T, Status>
+    fn read<T, R>(&self, r: R) -> Result<T, Status>
     where
         T: for<'de> Deserialize<'de>,
         R: Read,
@@ -193,15 +813,1081 @@ impl SerdeCodec for MessagePackSerdeCodec {
     }
 }
 
+#[cfg(feature = "messagepack")]
+#[cfg_attr(docsrs, doc(cfg(feature = "messagepack")))]
+impl PositionIndependent for MessagePackSerdeCodec {}
+
+#[cfg(feature = "postcard")]
+#[cfg_attr(docsrs, doc(cfg(feature = "postcard")))]
+impl SerdeCodec for PostcardSerdeCodec {
+    fn write<T, W>(&self, item: T, w: W) -> Result<(), Status>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        postcard::to_io(&item, w)
+            .map(|_| ())
+            .map_err(|postcard_err| Status::internal(format!("Error serializing {}", postcard_err)))
+    }
+
+    fn read<T, R>(&self, mut r: R) -> Result<T, Status>
+    where
+        T: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)
+            .map_err(|io_err| Status::internal(format!("Error reading {}", io_err)))?;
+        postcard::from_bytes(&buf).map_err(|postcard_err| {
+            Status::internal(format!("Error deserializing {}", postcard_err))
+        })
+    }
+}
+
+#[cfg(feature = "postcard")]
+#[cfg_attr(docsrs, doc(cfg(feature = "postcard")))]
+impl PositionIndependent for PostcardSerdeCodec {}
+
+#[cfg(feature = "ron")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ron")))]
+impl SerdeCodec for RonSerdeCodec {
+    fn write<T, W>(&self, item: T, w: W) -> Result<(), Status>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        let config = ron::ser::PrettyConfig::new().struct_names(true);
+        ron::ser::to_writer_pretty(w, &item, config)
+            .map_err(|ron_err| Status::internal(format!("Error serializing {}", ron_err)))
+    }
+
+    fn read<T, R>(&self, r: R) -> Result<T, Status>
+    where
+        T: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        ron::de::from_reader(r)
+            .map_err(|ron_err| Status::internal(format!("Error deserializing {}", ron_err)))
+    }
+}
+
+#[cfg(feature = "ron")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ron")))]
+impl PositionIndependent for RonSerdeCodec {}
+
 #[cfg(feature = "bincode")]
 #[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
 pub type BincodeCodec<T, U> = Codec<BincodeSerdeCodec, T, U>;
+#[cfg(feature = "bson")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bson")))]
+pub type BsonCodec<T, U> = Codec<BsonSerdeCodec, T, U>;
 #[cfg(feature = "cbor")]
 #[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
 pub type CborCodec<T, U> = Codec<CborSerdeCodec, T, U>;
+#[cfg(feature = "cbor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+pub type CanonicalCborCodec<T, U> = Codec<CanonicalCborSerdeCodec, T, U>;
 #[cfg(feature = "json")]
 #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
 pub type JsonCodec<T, U> = Codec<JsonSerdeCodec, T, U>;
 #[cfg(feature = "messagepack")]
 #[cfg_attr(docsrs, doc(cfg(feature = "messagepack")))]
 pub type MessagePackCodec<T, U> = Codec<MessagePackSerdeCodec, T, U>;
+#[cfg(feature = "postcard")]
+#[cfg_attr(docsrs, doc(cfg(feature = "postcard")))]
+pub type PostcardCodec<T, U> = Codec<PostcardSerdeCodec, T, U>;
+#[cfg(feature = "ron")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ron")))]
+pub type RonCodec<T, U> = Codec<RonSerdeCodec, T, U>;
+
+/// The codec of services using `prost`, building a [`ProstCodec`] for each method.
+#[cfg(feature = "prost")]
+#[cfg_attr(docsrs, doc(cfg(feature = "prost")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Prost;
+
+#[cfg(feature = "prost")]
+impl<T, U> MakeCodec<T, U> for Prost
+where
+    T: prost::Message + Send + 'static,
+    U: prost::Message + Default + Send + 'static,
+{
+    type Codec = ProstCodec<T, U>;
+
+    fn make_codec(&self) -> Self::Codec {
+        ProstCodec::default()
+    }
+}
+
+/// Encodes messages of type `T` and decodes messages of type `U` with `prost`, like
+/// `tonic`'s own `ProstCodec`, recording the size of the messages of the calls.
+#[cfg(feature = "prost")]
+#[cfg_attr(docsrs, doc(cfg(feature = "prost")))]
+pub struct ProstCodec<T, U> {
+    _pd: PhantomData<(T, U)>,
+}
+
+#[cfg(feature = "prost")]
+impl<T, U> Default for ProstCodec<T, U> {
+    fn default() -> Self {
+        ProstCodec { _pd: PhantomData }
+    }
+}
+
+#[cfg(feature = "prost")]
+impl<T, U> codec::Codec for ProstCodec<T, U>
+where
+    T: prost::Message + Send + 'static,
+    U: prost::Message + Default + Send + 'static,
+{
+    type Encode = T;
+    type Decode = U;
+    type Encoder = ProstEncoder<T>;
+    type Decoder = ProstDecoder<U>;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        ProstEncoder {
+            size: RequestSize::encoder(),
+            bytes: MessageBytes::encoder(),
+            _pd: PhantomData,
+        }
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        ProstDecoder {
+            size: RequestSize::decoder(),
+            bytes: MessageBytes::decoder(),
+            _pd: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "prost")]
+#[cfg_attr(docsrs, doc(cfg(feature = "prost")))]
+pub struct ProstEncoder<T> {
+    size: RequestSize,
+    bytes: MessageBytes,
+    _pd: PhantomData<fn(T)>,
+}
+
+#[cfg(feature = "prost")]
+impl<T: prost::Message> codec::Encoder for ProstEncoder<T> {
+    type Item = T;
+    type Error = Status;
+    fn encode(
+        &mut self,
+        item: Self::Item,
+        dst: &mut codec::EncodeBuf<'_>,
+    ) -> Result<(), Self::Error> {
+        let remaining = dst.remaining_mut();
+        item.encode(dst)
+            .map_err(|prost_err| Status::internal(format!("Error serializing {}", prost_err)))?;
+        let len = remaining - dst.remaining_mut();
+        self.size.add(len);
+        self.bytes.add(len);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "prost")]
+#[cfg_attr(docsrs, doc(cfg(feature = "prost")))]
+pub struct ProstDecoder<T> {
+    size: RequestSize,
+    bytes: MessageBytes,
+    _pd: PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "prost")]
+impl<T: prost::Message + Default> codec::Decoder for ProstDecoder<T> {
+    type Item = T;
+    type Error = Status;
+    fn decode(
+        &mut self,
+        src: &mut codec::DecodeBuf<'_>,
+    ) -> Result<Option<Self::Item>, Self::Error> {
+        self.size.add(src.remaining());
+        self.bytes.add(src.remaining());
+        let item = T::decode(src)
+            .map_err(|prost_err| Status::internal(format!("Error deserializing {}", prost_err)))?;
+        Ok(Some(item))
+    }
+}
+
+/// The codec of services using Avro, building an [`AvroCodec`] for each method.
+#[cfg(feature = "avro")]
+#[cfg_attr(docsrs, doc(cfg(feature = "avro")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Avro;
+
+/// Encodes messages of type `T` and decodes messages of type `U` with the Avro schemas of
+/// their types, in the single object encoding, see the [`avro`](crate::avro) module.
+#[cfg(feature = "avro")]
+#[cfg_attr(docsrs, doc(cfg(feature = "avro")))]
+pub struct AvroCodec<T, U> {
+    _pd: PhantomData<(T, U)>,
+}
+
+#[cfg(feature = "avro")]
+impl<T, U> Default for AvroCodec<T, U> {
+    fn default() -> Self {
+        AvroCodec { _pd: PhantomData }
+    }
+}
+
+#[cfg(feature = "avro")]
+impl<T, U> codec::Codec for AvroCodec<T, U>
+where
+    T: Serialize + crate::avro::AvroSchema + Send + 'static,
+    U: for<'de> Deserialize<'de> + crate::avro::AvroSchema + Send + 'static,
+{
+    type Encode = T;
+    type Decode = U;
+    type Encoder = AvroEncoder<T>;
+    type Decoder = AvroDecoder<U>;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        let schema = T::schema();
+        AvroEncoder {
+            fingerprint: schema.fingerprint(),
+            schema,
+            size: RequestSize::encoder(),
+            bytes: MessageBytes::encoder(),
+            _pd: PhantomData,
+        }
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        let schema = U::schema();
+        AvroDecoder {
+            fingerprint: schema.fingerprint(),
+            schema,
+            size: RequestSize::decoder(),
+            bytes: MessageBytes::decoder(),
+            _pd: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "avro")]
+impl<T, U> MakeCodec<T, U> for Avro
+where
+    T: Serialize + crate::avro::AvroSchema + Send + 'static,
+    U: for<'de> Deserialize<'de> + crate::avro::AvroSchema + Send + 'static,
+{
+    type Codec = AvroCodec<T, U>;
+
+    fn make_codec(&self) -> Self::Codec {
+        AvroCodec::default()
+    }
+}
+
+#[cfg(feature = "avro")]
+#[cfg_attr(docsrs, doc(cfg(feature = "avro")))]
+pub struct AvroEncoder<T> {
+    schema: crate::avro::Schema,
+    fingerprint: u64,
+    size: RequestSize,
+    bytes: MessageBytes,
+    _pd: PhantomData<fn(T)>,
+}
+
+#[cfg(feature = "avro")]
+impl<T: Serialize> codec::Encoder for AvroEncoder<T> {
+    type Item = T;
+    type Error = Status;
+    fn encode(
+        &mut self,
+        item: Self::Item,
+        dst: &mut codec::EncodeBuf<'_>,
+    ) -> Result<(), Self::Error> {
+        let mut buf = Vec::new();
+        crate::avro::write_header(self.fingerprint, &mut buf);
+        crate::avro::write(&item, &self.schema, &mut buf)
+            .map_err(|avro_err| Status::internal(format!("Error serializing {}", avro_err)))?;
+        dst.put_slice(&buf);
+        self.size.add(buf.len());
+        self.bytes.add(buf.len());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "avro")]
+#[cfg_attr(docsrs, doc(cfg(feature = "avro")))]
+pub struct AvroDecoder<T> {
+    schema: crate::avro::Schema,
+    fingerprint: u64,
+    size: RequestSize,
+    bytes: MessageBytes,
+    _pd: PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "avro")]
+impl<T: for<'de> Deserialize<'de>> codec::Decoder for AvroDecoder<T> {
+    type Item = T;
+    type Error = Status;
+    fn decode(
+        &mut self,
+        src: &mut codec::DecodeBuf<'_>,
+    ) -> Result<Option<Self::Item>, Self::Error> {
+        self.size.add(src.remaining());
+        self.bytes.add(src.remaining());
+        let message = src.copy_to_bytes(src.remaining());
+        let (fingerprint, body) = crate::avro::read_header(&message)
+            .map_err(|avro_err| Status::internal(format!("Error deserializing {}", avro_err)))?;
+        if fingerprint != self.fingerprint {
+            return Err(Status::failed_precondition(format!(
+                "Avro schema fingerprint {:016x} of the message does not match {:016x}",
+                fingerprint, self.fingerprint
+            )));
+        }
+        let item = crate::avro::read(body, &self.schema)
+            .map_err(|avro_err| Status::internal(format!("Error deserializing {}", avro_err)))?;
+        Ok(Some(item))
+    }
+}
+
+/// A compression algorithm applied to each message by [`Compressed`].
+pub trait Compression {
+    fn compress<W>(&self, bytes: &[u8], w: W) -> Result<(), Status>
+    where
+        W: Write;
+
+    /// Decompress a message, failing with [`decompressed_too_large`] if it decompresses to
+    /// more than `limit` bytes.
+    fn decompress<R>(&self, r: R, limit: u64) -> Result<Vec<u8>, Status>
+    where
+        R: Read;
+}
+
+/// The error for a message which decompresses to more than `limit` bytes.
+pub fn decompressed_too_large(limit: u64) -> Status {
+    Status::resource_exhausted(format!(
+        "Decompressed message is larger than {} bytes",
+        limit
+    ))
+}
+
+/// Read the decompressed message from `r`, reading at most one byte more than `limit` so
+/// that a small compressed message can't expand into an unbounded allocation.
+#[cfg(any(feature = "zstd", feature = "lz4", feature = "snappy"))]
+fn read_decompressed<R: Read>(r: R, limit: u64) -> Result<Vec<u8>, Status> {
+    let mut buf = Vec::new();
+    r.take(limit.saturating_add(1))
+        .read_to_end(&mut buf)
+        .map_err(decompression_error)?;
+    if buf.len() as u64 > limit {
+        return Err(decompressed_too_large(limit));
+    }
+    Ok(buf)
+}
+
+/// The default limit on the decompressed size of a message, matching the default limit
+/// `tonic` places on the size of decoded messages.
+pub const DEFAULT_DECOMPRESSED_LIMIT: u64 = 4 * 1024 * 1024;
+
+/// Compresses each message encoded by the codec `C` with the algorithm `A`,
+/// e.g. `Codec<Compressed<BincodeSerdeCodec, Zstd>, T, U>`.
+///
+/// Messages which decompress to more than a limit, [`DEFAULT_DECOMPRESSED_LIMIT`] by default,
+/// are rejected with [`Code::ResourceExhausted`](tonic::Code::ResourceExhausted).
+#[derive(Debug, Clone, Copy)]
+pub struct Compressed<C, A> {
+    codec: C,
+    compression: A,
+    limit: u64,
+}
+
+impl<C, A> Compressed<C, A> {
+    pub fn new(codec: C, compression: A) -> Self {
+        Compressed {
+            codec,
+            compression,
+            limit: DEFAULT_DECOMPRESSED_LIMIT,
+        }
+    }
+
+    /// Reject messages which decompress to more than `limit` bytes.
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = limit;
+        self
+    }
+}
+
+impl<C: Default, A: Default> Default for Compressed<C, A> {
+    fn default() -> Self {
+        Compressed::new(C::default(), A::default())
+    }
+}
+
+impl<C, A> PositionIndependent for Compressed<C, A>
+where
+    C: PositionIndependent,
+    A: Compression,
+{
+}
+
+impl<C, A> SerdeCodec for Compressed<C, A>
+where
+    C: SerdeCodec,
+    A: Compression,
+{
+    fn write<T, W>(&self, item: T, w: W) -> Result<(), Status>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        let mut buf = Vec::new();
+        self.codec.write(item, &mut buf)?;
+        self.compression.compress(&buf, w)
+    }
+
+    fn read<T, R>(&self, r: R) -> Result<T, Status>
+    where
+        T: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        let buf = self.compression.decompress(r, self.limit)?;
+        self.codec.read(buf.as_slice())
+    }
+
+    fn write_at<T, W>(&self, item: T, w: W, position: &MessagePosition) -> Result<(), Status>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        let mut buf = Vec::new();
+        self.codec.write_at(item, &mut buf, position)?;
+        self.compression.compress(&buf, w)
+    }
+
+    fn read_at<T, R>(&self, r: R, position: &MessagePosition) -> Result<T, Status>
+    where
+        T: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        let buf = self.compression.decompress(r, self.limit)?;
+        self.codec.read_at(buf.as_slice(), position)
+    }
+}
+
+#[cfg(any(feature = "zstd", feature = "lz4", feature = "snappy"))]
+fn compression_error(err: std::io::Error) -> Status {
+    Status::internal(format!("Error compressing {}", err))
+}
+
+#[cfg(any(feature = "zstd", feature = "lz4", feature = "snappy"))]
+fn decompression_error(err: std::io::Error) -> Status {
+    Status::internal(format!("Error decompressing {}", err))
+}
+
+#[cfg(feature = "zstd")]
+#[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Zstd;
+
+#[cfg(feature = "zstd")]
+#[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+impl Compression for Zstd {
+    fn compress<W>(&self, bytes: &[u8], w: W) -> Result<(), Status>
+    where
+        W: Write,
+    {
+        zstd::stream::copy_encode(bytes, w, zstd::DEFAULT_COMPRESSION_LEVEL)
+            .map_err(compression_error)
+    }
+
+    fn decompress<R>(&self, r: R, limit: u64) -> Result<Vec<u8>, Status>
+    where
+        R: Read,
+    {
+        let decoder = zstd::stream::read::Decoder::new(r).map_err(decompression_error)?;
+        read_decompressed(decoder, limit)
+    }
+}
+
+/// Compresses messages with `zstd` at the given level, trading speed for smaller messages.
+#[cfg(feature = "zstd")]
+#[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+#[derive(Debug, Clone, Copy)]
+pub struct ZstdWithLevel {
+    level: i32,
+}
+
+#[cfg(feature = "zstd")]
+#[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+impl ZstdWithLevel {
+    /// Compress at `level`, from 1 to 22. Levels above 19 use much more memory.
+    pub fn new(level: i32) -> Self {
+        ZstdWithLevel { level }
+    }
+}
+
+#[cfg(feature = "zstd")]
+#[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+impl Compression for ZstdWithLevel {
+    fn compress<W>(&self, bytes: &[u8], w: W) -> Result<(), Status>
+    where
+        W: Write,
+    {
+        zstd::stream::copy_encode(bytes, w, self.level).map_err(compression_error)
+    }
+
+    fn decompress<R>(&self, r: R, limit: u64) -> Result<Vec<u8>, Status>
+    where
+        R: Read,
+    {
+        Zstd.decompress(r, limit)
+    }
+}
+
+/// A trained `zstd` dictionary, see [`ZstdWithDictionary`].
+#[cfg(feature = "zstd")]
+#[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+pub trait ZstdDictionary {
+    /// The dictionary, e.g. `include_bytes!("messages.dict")` for a dictionary trained
+    /// with `zstd --train`.
+    fn dictionary() -> &'static [u8];
+}
+
+/// Compresses messages with `zstd` using the dictionary `D`.
+///
+/// Dictionaries trained on typical messages compress small messages much better than
+/// plain `zstd`. Both peers must use the same dictionary.
+#[cfg(feature = "zstd")]
+#[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+pub struct ZstdWithDictionary<D> {
+    _pd: PhantomData<D>,
+}
+
+#[cfg(feature = "zstd")]
+impl<D> std::fmt::Debug for ZstdWithDictionary<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ZstdWithDictionary")
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<D> Clone for ZstdWithDictionary<D> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<D> Copy for ZstdWithDictionary<D> {}
+
+#[cfg(feature = "zstd")]
+impl<D> Default for ZstdWithDictionary<D> {
+    fn default() -> Self {
+        ZstdWithDictionary { _pd: PhantomData }
+    }
+}
+
+#[cfg(feature = "zstd")]
+#[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+impl<D: ZstdDictionary> Compression for ZstdWithDictionary<D> {
+    fn compress<W>(&self, bytes: &[u8], w: W) -> Result<(), Status>
+    where
+        W: Write,
+    {
+        let mut encoder = zstd::stream::write::Encoder::with_dictionary(
+            w,
+            zstd::DEFAULT_COMPRESSION_LEVEL,
+            D::dictionary(),
+        )
+        .map_err(compression_error)?;
+        encoder.write_all(bytes).map_err(compression_error)?;
+        encoder.finish().map(|_| ()).map_err(compression_error)
+    }
+
+    fn decompress<R>(&self, r: R, limit: u64) -> Result<Vec<u8>, Status>
+    where
+        R: Read,
+    {
+        let decoder = zstd::stream::read::Decoder::with_dictionary(
+            std::io::BufReader::new(r),
+            D::dictionary(),
+        )
+        .map_err(decompression_error)?;
+        read_decompressed(decoder, limit)
+    }
+}
+
+#[cfg(feature = "lz4")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lz4")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lz4;
+
+#[cfg(feature = "lz4")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lz4")))]
+impl Compression for Lz4 {
+    fn compress<W>(&self, bytes: &[u8], w: W) -> Result<(), Status>
+    where
+        W: Write,
+    {
+        let mut encoder = lz4_flex::frame::FrameEncoder::new(w);
+        encoder.write_all(bytes).map_err(compression_error)?;
+        encoder
+            .finish()
+            .map(|_| ())
+            .map_err(|lz4_err| Status::internal(format!("Error compressing {}", lz4_err)))
+    }
+
+    fn decompress<R>(&self, r: R, limit: u64) -> Result<Vec<u8>, Status>
+    where
+        R: Read,
+    {
+        read_decompressed(lz4_flex::frame::FrameDecoder::new(r), limit)
+    }
+}
+
+#[cfg(feature = "snappy")]
+#[cfg_attr(docsrs, doc(cfg(feature = "snappy")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Snappy;
+
+#[cfg(feature = "snappy")]
+#[cfg_attr(docsrs, doc(cfg(feature = "snappy")))]
+impl Compression for Snappy {
+    fn compress<W>(&self, bytes: &[u8], w: W) -> Result<(), Status>
+    where
+        W: Write,
+    {
+        let mut encoder = snap::write::FrameEncoder::new(w);
+        encoder.write_all(bytes).map_err(compression_error)?;
+        encoder.flush().map_err(compression_error)
+    }
+
+    fn decompress<R>(&self, r: R, limit: u64) -> Result<Vec<u8>, Status>
+    where
+        R: Read,
+    {
+        read_decompressed(snap::read::FrameDecoder::new(r), limit)
+    }
+}
+
+#[cfg(all(feature = "aes-gcm", not(feature = "chacha20poly1305")))]
+use aes_gcm::aead;
+#[cfg(feature = "chacha20poly1305")]
+use chacha20poly1305::aead;
+
+#[cfg(any(feature = "chacha20poly1305", feature = "aes-gcm"))]
+#[derive(Clone)]
+enum Cipher {
+    #[cfg(feature = "chacha20poly1305")]
+    ChaCha20Poly1305(chacha20poly1305::ChaCha20Poly1305),
+    #[cfg(feature = "aes-gcm")]
+    Aes256Gcm(Box<aes_gcm::Aes256Gcm>),
+}
+
+#[cfg(any(feature = "chacha20poly1305", feature = "aes-gcm"))]
+fn seal<A: aead::Aead + aead::AeadCore>(
+    cipher: &A,
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, Status> {
+    let nonce = A::generate_nonce(&mut aead::OsRng);
+    let payload = aead::Payload {
+        msg: plaintext,
+        aad,
+    };
+    let ciphertext = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|aead_err| Status::internal(format!("Error encrypting {}", aead_err)))?;
+    let mut frame = nonce.to_vec();
+    frame.extend_from_slice(&ciphertext);
+    Ok(frame)
+}
+
+#[cfg(any(feature = "chacha20poly1305", feature = "aes-gcm"))]
+fn open<A: aead::Aead + aead::AeadCore>(
+    cipher: &A,
+    frame: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, Status> {
+    use aead::generic_array::typenum::Unsigned;
+
+    let nonce_size = A::NonceSize::USIZE;
+    if frame.len() < nonce_size {
+        return Err(Status::data_loss("Error decrypting message: missing nonce"));
+    }
+    let (nonce, ciphertext) = frame.split_at(nonce_size);
+    let payload = aead::Payload {
+        msg: ciphertext,
+        aad,
+    };
+    cipher
+        .decrypt(aead::Nonce::<A>::from_slice(nonce), payload)
+        .map_err(|aead_err| Status::data_loss(format!("Error decrypting {}", aead_err)))
+}
+
+/// The associated data authenticated with a message at `position`: the path of the method,
+/// the direction of the message and its index in the stream.
+#[cfg(any(feature = "chacha20poly1305", feature = "aes-gcm"))]
+fn associated_data(position: Option<&MessagePosition>) -> Vec<u8> {
+    let position = match position {
+        Some(position) => position,
+        None => return Vec::new(),
+    };
+    let mut aad = position.path.as_bytes().to_vec();
+    aad.push(0);
+    aad.push(match position.direction {
+        Direction::Request => 0,
+        Direction::Response => 1,
+    });
+    aad.extend_from_slice(&position.sequence.to_be_bytes());
+    aad
+}
+
+/// Encrypts and authenticates each message encoded by the codec `C`.
+///
+/// Every message is sealed with a random 96 bit nonce, which is sent in front of the
+/// ciphertext. In the generated clients and servers, each message is also bound to its
+/// [`MessagePosition`]: the method, whether it's a request or a response, and its index in
+/// the stream. Messages which fail authentication, because they were modified, sealed with
+/// another key, or replayed in another call or position, are rejected with
+/// [`Code::DataLoss`](tonic::Code::DataLoss). For the same reason it isn't
+/// [`PositionIndependent`], so [`Encoded`] messages can only be built with
+/// [`Encoded::new_at`], for the position they're sent at:
+///
+/// ```compile_fail
+/// # use tonic_rpc::codec::{Encoded, Encrypted, SerdeCodec};
+/// # use tonic::Status;
+/// fn encode<C: SerdeCodec>(codec: &Encrypted<C>) -> Result<Encoded<u32, Encrypted<C>>, Status> {
+///     Encoded::new(codec, &1)
+/// }
+/// ```
+///
+/// Pass it to the generated clients and servers with `with_codec`. Ciphertext doesn't
+/// compress, so to compress messages wrap the inner codec in [`Compressed`] instead.
+#[cfg(any(feature = "chacha20poly1305", feature = "aes-gcm"))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(any(feature = "chacha20poly1305", feature = "aes-gcm")))
+)]
+#[derive(Clone)]
+pub struct Encrypted<C> {
+    codec: C,
+    cipher: Cipher,
+}
+
+#[cfg(any(feature = "chacha20poly1305", feature = "aes-gcm"))]
+impl<C> Encrypted<C> {
+    /// Encrypt messages with ChaCha20-Poly1305 using a 256 bit key.
+    #[cfg(feature = "chacha20poly1305")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "chacha20poly1305")))]
+    pub fn chacha20poly1305(codec: C, key: &[u8; 32]) -> Self {
+        use chacha20poly1305::KeyInit;
+
+        Encrypted {
+            codec,
+            cipher: Cipher::ChaCha20Poly1305(chacha20poly1305::ChaCha20Poly1305::new(key.into())),
+        }
+    }
+
+    /// Encrypt messages with AES-256-GCM using a 256 bit key.
+    #[cfg(feature = "aes-gcm")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aes-gcm")))]
+    pub fn aes256gcm(codec: C, key: &[u8; 32]) -> Self {
+        use aes_gcm::KeyInit;
+
+        Encrypted {
+            codec,
+            cipher: Cipher::Aes256Gcm(Box::new(aes_gcm::Aes256Gcm::new(key.into()))),
+        }
+    }
+}
+
+#[cfg(any(feature = "chacha20poly1305", feature = "aes-gcm"))]
+impl<C: std::fmt::Debug> std::fmt::Debug for Encrypted<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Leave out the key.
+        let algorithm = match self.cipher {
+            #[cfg(feature = "chacha20poly1305")]
+            Cipher::ChaCha20Poly1305(_) => "ChaCha20-Poly1305",
+            #[cfg(feature = "aes-gcm")]
+            Cipher::Aes256Gcm(_) => "AES-256-GCM",
+        };
+        f.debug_struct("Encrypted")
+            .field("codec", &self.codec)
+            .field("algorithm", &algorithm)
+            .finish()
+    }
+}
+
+#[cfg(any(feature = "chacha20poly1305", feature = "aes-gcm"))]
+impl<C: SerdeCodec> Encrypted<C> {
+    fn seal<T, W>(
+        &self,
+        item: T,
+        mut w: W,
+        position: Option<&MessagePosition>,
+    ) -> Result<(), Status>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        let mut buf = Vec::new();
+        self.codec.write(item, &mut buf)?;
+        let aad = associated_data(position);
+        let frame = match &self.cipher {
+            #[cfg(feature = "chacha20poly1305")]
+            Cipher::ChaCha20Poly1305(cipher) => seal(cipher, &buf, &aad)?,
+            #[cfg(feature = "aes-gcm")]
+            Cipher::Aes256Gcm(cipher) => seal(cipher.as_ref(), &buf, &aad)?,
+        };
+        w.write_all(&frame)
+            .map_err(|io_err| Status::internal(format!("Error writing {}", io_err)))
+    }
+
+    fn open<T, R>(&self, mut r: R, position: Option<&MessagePosition>) -> Result<T, Status>
+    where
+        T: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        let mut frame = Vec::new();
+        r.read_to_end(&mut frame)
+            .map_err(|io_err| Status::internal(format!("Error reading {}", io_err)))?;
+        let aad = associated_data(position);
+        let buf = match &self.cipher {
+            #[cfg(feature = "chacha20poly1305")]
+            Cipher::ChaCha20Poly1305(cipher) => open(cipher, &frame, &aad)?,
+            #[cfg(feature = "aes-gcm")]
+            Cipher::Aes256Gcm(cipher) => open(cipher.as_ref(), &frame, &aad)?,
+        };
+        self.codec.read(buf.as_slice())
+    }
+}
+
+#[cfg(any(feature = "chacha20poly1305", feature = "aes-gcm"))]
+impl<C> SerdeCodec for Encrypted<C>
+where
+    C: SerdeCodec,
+{
+    fn write<T, W>(&self, item: T, w: W) -> Result<(), Status>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        self.seal(item, w, None)
+    }
+
+    fn read<T, R>(&self, r: R) -> Result<T, Status>
+    where
+        T: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        self.open(r, None)
+    }
+
+    fn write_at<T, W>(&self, item: T, w: W, position: &MessagePosition) -> Result<(), Status>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        self.seal(item, w, Some(position))
+    }
+
+    fn read_at<T, R>(&self, r: R, position: &MessagePosition) -> Result<T, Status>
+    where
+        T: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        self.open(r, Some(position))
+    }
+}
+
+/// A message encoded with [`rkyv`](https://crates.io/crates/rkyv).
+///
+/// Services using the `rkyv` codec send and receive `Archived` values,
+/// which hold the encoded bytes of a `T`. Received messages have been validated,
+/// so they can be accessed without deserialization using [`Archived::get`].
+#[cfg(feature = "rkyv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rkyv")))]
+pub struct Archived<T> {
+    bytes: rkyv::AlignedVec,
+    _pd: PhantomData<T>,
+}
+
+#[cfg(feature = "rkyv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rkyv")))]
+impl<T> Archived<T> {
+    pub fn new(value: &T) -> Result<Self, Status>
+    where
+        T: rkyv::Serialize<rkyv::ser::serializers::AllocSerializer<256>>,
+    {
+        let bytes = rkyv::to_bytes::<_, 256>(value)
+            .map_err(|rkyv_err| Status::internal(format!("Error serializing {}", rkyv_err)))?;
+        Ok(Archived {
+            bytes,
+            _pd: PhantomData,
+        })
+    }
+
+    /// The archived value, accessed without deserializing it.
+    pub fn get(&self) -> &T::Archived
+    where
+        T: rkyv::Archive,
+    {
+        // The bytes were either produced by serializing a `T` or have been validated
+        // by `RkyvDecoder`.
+        unsafe { rkyv::archived_root::<T>(&self.bytes) }
+    }
+
+    pub fn deserialize(&self) -> T
+    where
+        T: rkyv::Archive,
+        T::Archived: rkyv::Deserialize<T, rkyv::Infallible>,
+    {
+        rkyv::Deserialize::deserialize(self.get(), &mut rkyv::Infallible)
+            .expect("Deserializing with rkyv::Infallible can't fail")
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.bytes.as_slice()
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl<T> std::fmt::Debug for Archived<T>
+where
+    T: rkyv::Archive,
+    T::Archived: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.get().fmt(f)
+    }
+}
+
+#[cfg(feature = "rkyv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rkyv")))]
+#[derive(Clone)]
+pub struct RkyvEncoder<T> {
+    size: RequestSize,
+    bytes: MessageBytes,
+    _pd: PhantomData<T>,
+}
+
+#[cfg(feature = "rkyv")]
+impl<T> codec::Encoder for RkyvEncoder<T> {
+    type Item = Archived<T>;
+    type Error = Status;
+    fn encode(
+        &mut self,
+        item: Self::Item,
+        dst: &mut codec::EncodeBuf<'_>,
+    ) -> Result<(), Self::Error> {
+        dst.put_slice(item.as_bytes());
+        self.size.add(item.as_bytes().len());
+        self.bytes.add(item.as_bytes().len());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rkyv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rkyv")))]
+#[derive(Clone)]
+pub struct RkyvDecoder<T> {
+    size: RequestSize,
+    bytes: MessageBytes,
+    _pd: PhantomData<T>,
+}
+
+#[cfg(feature = "rkyv")]
+impl<T> codec::Decoder for RkyvDecoder<T>
+where
+    T: rkyv::Archive,
+    T::Archived: for<'a> rkyv::CheckBytes<rkyv::validation::validators::DefaultValidator<'a>>,
+{
+    type Item = Archived<T>;
+    type Error = Status;
+    fn decode(
+        &mut self,
+        src: &mut codec::DecodeBuf<'_>,
+    ) -> Result<Option<Self::Item>, Self::Error> {
+        self.size.add(src.remaining());
+        self.bytes.add(src.remaining());
+        // Copy into an aligned buffer, as the archived types are accessed in place.
+        let mut bytes = rkyv::AlignedVec::with_capacity(src.remaining());
+        while src.has_remaining() {
+            let chunk_len = {
+                let chunk = src.chunk();
+                bytes.extend_from_slice(chunk);
+                chunk.len()
+            };
+            src.advance(chunk_len);
+        }
+        rkyv::check_archived_root::<T>(&bytes)
+            .map_err(|rkyv_err| Status::internal(format!("Error validating {}", rkyv_err)))?;
+        Ok(Some(Archived {
+            bytes,
+            _pd: PhantomData,
+        }))
+    }
+}
+
+/// A codec for [`Archived`] messages.
+///
+/// Unlike [`Codec`], this doesn't use `serde`, so the message types must
+/// implement the `rkyv` traits instead.
+#[cfg(feature = "rkyv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rkyv")))]
+pub struct RkyvCodec<T, U> {
+    _pd: PhantomData<(T, U)>,
+}
+
+#[cfg(feature = "rkyv")]
+impl<T, U> Default for RkyvCodec<T, U> {
+    fn default() -> Self {
+        RkyvCodec { _pd: PhantomData }
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl<T, U> codec::Codec for RkyvCodec<T, U>
+where
+    T: Send + Sync + 'static,
+    U: rkyv::Archive + Send + Sync + 'static,
+    U::Archived: for<'a> rkyv::CheckBytes<rkyv::validation::validators::DefaultValidator<'a>>,
+{
+    type Encode = Archived<T>;
+    type Decode = Archived<U>;
+    type Encoder = RkyvEncoder<T>;
+    type Decoder = RkyvDecoder<U>;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        RkyvEncoder {
+            size: RequestSize::encoder(),
+            bytes: MessageBytes::encoder(),
+            _pd: PhantomData,
+        }
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        RkyvDecoder {
+            size: RequestSize::decoder(),
+            bytes: MessageBytes::decoder(),
+            _pd: PhantomData,
+        }
+    }
+}
+
+/// The codec of services using `rkyv`, building a [`RkyvCodec`] for each method.
+#[cfg(feature = "rkyv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rkyv")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rkyv;
+
+#[cfg(feature = "rkyv")]
+impl<T, U> MakeCodec<Archived<T>, Archived<U>> for Rkyv
+where
+    T: Send + Sync + 'static,
+    U: rkyv::Archive + Send + Sync + 'static,
+    U::Archived: for<'a> rkyv::CheckBytes<rkyv::validation::validators::DefaultValidator<'a>>,
+{
+    type Codec = RkyvCodec<T, U>;
+
+    fn make_codec(&self) -> Self::Codec {
+        RkyvCodec::default()
+    }
+}
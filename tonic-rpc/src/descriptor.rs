@@ -0,0 +1,29 @@
+//! Descriptions of the methods of generated services, used by adapters serving them over
+//! other protocols than `gRPC`.
+
+/// Describes a method of a generated service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MethodDescriptor {
+    /// The name of the method in the service trait, e.g. `increment`.
+    pub name: &'static str,
+    /// The path of the `gRPC` endpoint of the method, e.g. `/Increment/Increment`.
+    pub path: &'static str,
+    /// The names of the arguments of the method, in order.
+    pub args: &'static [&'static str],
+    pub client_streaming: bool,
+    pub server_streaming: bool,
+}
+
+/// Implemented by generated servers to describe the methods of their service.
+pub trait ServiceDescriptor {
+    const METHODS: &'static [MethodDescriptor];
+
+    /// The method named `name` in the service trait.
+    fn method(name: &str) -> Option<&'static MethodDescriptor> {
+        Self::METHODS.iter().find(|method| method.name == name)
+    }
+}
+
+/// Implemented by generated servers whose messages are encoded as JSON, without compression,
+/// so that adapters can read and write the messages of their methods as JSON.
+pub trait JsonCodec {}
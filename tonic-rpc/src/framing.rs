@@ -0,0 +1,145 @@
+//! Helpers for the adapters that translate other protocols to `gRPC` requests to a
+//! generated server.
+
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tonic::{
+    body::BoxBody,
+    codegen::{
+        http::{header, HeaderMap, Request},
+        Body as _,
+    },
+    transport::Body,
+    Code, Status,
+};
+
+/// Whether `request` is a `gRPC` request, which adapters pass through to the server.
+pub(crate) fn is_grpc<B>(request: &Request<B>) -> bool {
+    request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/grpc"))
+}
+
+/// Prefix a message with the `gRPC` framing: an uncompressed flag and the message length.
+pub(crate) fn frame(message: Bytes) -> Bytes {
+    let mut framed = BytesMut::with_capacity(message.len() + 5);
+    framed.put_u8(0);
+    framed.put_u32(message.len() as u32);
+    framed.put(message);
+    framed.freeze()
+}
+
+/// Splits the body of a `gRPC` response into its messages, followed by the
+/// response's status if it isn't `Ok`.
+pub(crate) struct Messages {
+    body: BoxBody,
+    buf: BytesMut,
+    done: bool,
+}
+
+impl Messages {
+    pub(crate) fn new(body: BoxBody) -> Self {
+        Messages {
+            body,
+            buf: BytesMut::new(),
+            done: false,
+        }
+    }
+
+    fn next_frame(&mut self) -> Result<Option<Bytes>, Status> {
+        if self.buf.len() < 5 {
+            return Ok(None);
+        }
+        if self.buf[0] != 0 {
+            return Err(Status::internal("Compressed messages are not supported"));
+        }
+        let len = (&self.buf[1..5]).get_u32() as usize;
+        if self.buf.len() < len + 5 {
+            return Ok(None);
+        }
+        self.buf.advance(5);
+        Ok(Some(self.buf.split_to(len).freeze()))
+    }
+
+    pub(crate) fn poll_message(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Status>>> {
+        loop {
+            if self.done {
+                return Poll::Ready(None);
+            }
+            match self.next_frame() {
+                Ok(Some(message)) => return Poll::Ready(Some(Ok(message))),
+                Ok(None) => {}
+                Err(status) => {
+                    self.done = true;
+                    return Poll::Ready(Some(Err(status)));
+                }
+            }
+            match ready!(Pin::new(&mut self.body).poll_data(cx)) {
+                Some(Ok(data)) => self.buf.extend_from_slice(&data),
+                Some(Err(status)) => {
+                    self.done = true;
+                    return Poll::Ready(Some(Err(status)));
+                }
+                None => {
+                    self.done = true;
+                    let trailers = ready!(Pin::new(&mut self.body).poll_trailers(cx));
+                    let status = match trailers {
+                        Ok(Some(trailers)) => Status::from_header_map(&trailers),
+                        Ok(None) => None,
+                        Err(status) => Some(status),
+                    };
+                    return Poll::Ready(status.filter(|s| s.code() != Code::Ok).map(Err));
+                }
+            }
+        }
+    }
+}
+
+/// A body containing all of `data`.
+pub(crate) fn full_body(data: Bytes) -> BoxBody {
+    Body::from(data)
+        .map_err(|err| Status::internal(format!("Error writing response {}", err)))
+        .boxed_unsync()
+}
+
+#[cfg(any(feature = "gateway", feature = "jsonrpc"))]
+/// The default limit on the size of the requests read by adapters: the default limit of
+/// `tonic` on the size of decoded messages.
+pub(crate) const DEFAULT_MAX_REQUEST_SIZE: usize = 4 * 1024 * 1024;
+
+#[cfg(any(feature = "gateway", feature = "jsonrpc"))]
+/// Read the whole `body` of a request with `headers`, or `None` if it is larger than `limit`
+/// bytes.
+pub(crate) async fn read_body(
+    headers: &HeaderMap,
+    body: &mut Body,
+    limit: usize,
+) -> Result<Option<Bytes>, Status> {
+    let content_length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|len| len.to_str().ok())
+        .and_then(|len| len.parse::<u64>().ok());
+    if content_length.is_some_and(|len| len > limit as u64) {
+        return Ok(None);
+    }
+    let mut message = BytesMut::new();
+    while let Some(data) = body.data().await {
+        let data = data.map_err(|err| {
+            Status::invalid_argument(format!("Error reading request body {}", err))
+        })?;
+        if message.len() + data.len() > limit {
+            return Ok(None);
+        }
+        message.extend_from_slice(&data);
+    }
+    Ok(Some(message.freeze()))
+}
@@ -0,0 +1,330 @@
+//! A gateway that exposes `#[tonic_rpc(json)]` services to clients which can't speak `gRPC`.
+//!
+//! [`JsonGateway`] wraps a generated server. `gRPC` requests are passed through untouched,
+//! while any other `POST /{Service}/{Method}` request is treated as a plain JSON call:
+//! the body is the JSON encoded request and the response body is the JSON encoded response.
+//! An empty body is treated as `null`, which is the JSON encoding of `()`.
+//!
+//! Server streaming methods can be called by sending an `Accept` header of
+//! `application/x-ndjson` (one JSON response per line) or `text/event-stream`
+//! (one server-sent event per response).
+//!
+//! Errors are returned with the HTTP status corresponding to the `gRPC` code and a body of
+//! `{"code": <grpc code>, "message": <message>}`. If a stream fails after the response
+//! has started, the error is sent as a final `{"error": {..}}` line or as an `error` event.
+//! Requests larger than [`JsonGateway::max_request_size`] are rejected with
+//! `413 Payload Too Large`.
+//!
+//! ```no_run
+//! # #[tonic_rpc::tonic_rpc(json)]
+//! # trait Increment {
+//! #     fn increment(arg: i32) -> i32;
+//! # }
+//! # struct State;
+//! # #[tonic::async_trait]
+//! # impl increment_server::Increment for State {
+//! #     async fn increment(
+//! #         &self,
+//! #         request: tonic::Request<i32>,
+//! #     ) -> Result<tonic::Response<i32>, tonic::Status> {
+//! #         Ok(tonic::Response::new(request.into_inner() + 1))
+//! #     }
+//! # }
+//! # async fn run() {
+//! tonic::transport::Server::builder()
+//!     // JSON clients will usually use HTTP/1.1.
+//!     .accept_http1(true)
+//!     .add_service(tonic_rpc::gateway::JsonGateway::new(
+//!         increment_server::IncrementServer::new(State),
+//!     ))
+//!     .serve("[::1]:8080".parse().unwrap())
+//!     .await
+//!     .unwrap();
+//! # }
+//! # fn main() {}
+//! ```
+//! Now `curl -d 32 http://[::1]:8080/Increment/Increment` responds with `33`.
+
+use std::{
+    convert::Infallible,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use tonic::{
+    body::BoxBody,
+    codegen::{
+        http::{self, header, HeaderValue, Method, Request, Response, StatusCode},
+        poll_fn, Body as _, BoxFuture, Service,
+    },
+    transport::{Body, NamedService},
+    Code, Status,
+};
+
+use crate::{
+    descriptor::JsonCodec,
+    framing::{frame, full_body, is_grpc, read_body, Messages, DEFAULT_MAX_REQUEST_SIZE},
+};
+
+/// Serves plain JSON requests for the wrapped `#[tonic_rpc(json)]` server.
+///
+/// The server must encode its messages with a JSON codec, without compression, so other
+/// servers are rejected at compile time:
+/// ```compile_fail
+/// # use tonic_rpc::gateway::JsonGateway;
+/// fn serve<S>(server: S) -> JsonGateway<S> {
+///     JsonGateway::new(server)
+/// }
+/// ```
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone)]
+pub struct JsonGateway<S> {
+    inner: S,
+    max_request_size: usize,
+}
+
+impl<S: JsonCodec> JsonGateway<S> {
+    pub fn new(inner: S) -> Self {
+        JsonGateway {
+            inner,
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+        }
+    }
+
+    /// Reject JSON requests whose body is larger than `limit` bytes, 4 MiB by default.
+    pub fn max_request_size(mut self, limit: usize) -> Self {
+        self.max_request_size = limit;
+        self
+    }
+}
+
+impl<S: NamedService> NamedService for JsonGateway<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S> Service<Request<Body>> for JsonGateway<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible>
+        + JsonCodec
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        if is_grpc(&request) {
+            return Box::pin(self.inner.call(request));
+        }
+        // Use the service that has been driven to readiness and leave a clone in its place.
+        let clone = self.inner.clone();
+        let inner = std::mem::replace(&mut self.inner, clone);
+        let limit = self.max_request_size;
+        Box::pin(async move { Ok(call_json(inner, request, limit).await) })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// A single JSON response.
+    Single,
+    /// Newline delimited JSON responses.
+    Lines,
+    /// Server-sent events.
+    EventStream,
+}
+
+impl Format {
+    fn from_request<B>(request: &Request<B>) -> Self {
+        let accept = request
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or("");
+        if accept.contains("text/event-stream") {
+            Format::EventStream
+        } else if accept.contains("application/x-ndjson") {
+            Format::Lines
+        } else {
+            Format::Single
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Single => "application/json",
+            Format::Lines => "application/x-ndjson",
+            Format::EventStream => "text/event-stream",
+        }
+    }
+
+    fn message(self, message: Bytes) -> Bytes {
+        match self {
+            Format::Single => message,
+            Format::Lines => [&message[..], b"\n"].concat().into(),
+            Format::EventStream => [b"data: ", &message[..], b"\n\n"].concat().into(),
+        }
+    }
+
+    fn error(self, status: &Status) -> Bytes {
+        let error = error_json(status);
+        match self {
+            Format::Single => error.into(),
+            Format::Lines => format!("{{\"error\":{}}}\n", error).into(),
+            Format::EventStream => format!("event: error\ndata: {}\n\n", error).into(),
+        }
+    }
+}
+
+async fn call_json<S>(mut inner: S, request: Request<Body>, limit: usize) -> Response<BoxBody>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible>,
+{
+    if request.method() != Method::POST {
+        return error_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            &Status::unimplemented("Only POST requests are supported"),
+        );
+    }
+    let format = Format::from_request(&request);
+    let (mut parts, mut body) = request.into_parts();
+
+    let message = match read_body(&parts.headers, &mut body, limit).await {
+        Ok(Some(message)) if message.is_empty() => Bytes::from_static(b"null"),
+        Ok(Some(message)) => message,
+        Ok(None) => {
+            return error_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                &Status::resource_exhausted(format!("Request body is larger than {} bytes", limit)),
+            )
+        }
+        Err(status) => return status_response(status),
+    };
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/grpc"),
+    );
+    parts
+        .headers
+        .insert(header::TE, HeaderValue::from_static("trailers"));
+    let request = Request::from_parts(parts, Body::from(frame(message)));
+
+    let response = match inner.call(request).await {
+        Ok(response) => response,
+        Err(never) => match never {},
+    };
+    if let Some(status) = Status::from_header_map(response.headers()) {
+        if status.code() != Code::Ok {
+            return status_response(status);
+        }
+    }
+
+    let mut messages = Messages::new(response.into_body());
+    match format {
+        Format::Single => {
+            let mut received = Vec::new();
+            while let Some(message) = poll_fn(|cx| messages.poll_message(cx)).await {
+                match message {
+                    Ok(message) => received.push(message),
+                    Err(status) => return status_response(status),
+                }
+            }
+            match received.pop() {
+                Some(message) if received.is_empty() => Response::builder()
+                    .header(header::CONTENT_TYPE, format.content_type())
+                    .body(full_body(message))
+                    .unwrap(),
+                _ => status_response(Status::internal(
+                    "Expected a single response message. Streaming methods must be called \
+                     with `Accept: application/x-ndjson` or `Accept: text/event-stream`",
+                )),
+            }
+        }
+        Format::Lines | Format::EventStream => Response::builder()
+            .header(header::CONTENT_TYPE, format.content_type())
+            .body(FormattedBody { messages, format }.boxed_unsync())
+            .unwrap(),
+    }
+}
+
+/// The body of a streaming JSON response.
+struct FormattedBody {
+    messages: Messages,
+    format: Format,
+}
+
+impl tonic::codegen::Body for FormattedBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let format = self.format;
+        self.messages.poll_message(cx).map(|message| {
+            message.map(|message| match message {
+                Ok(message) => Ok(format.message(message)),
+                Err(status) => Ok(format.error(&status)),
+            })
+        })
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+}
+
+fn error_json(status: &Status) -> String {
+    serde_json::json!({
+        "code": status.code() as i32,
+        "message": status.message(),
+    })
+    .to_string()
+}
+
+fn error_response(http_status: StatusCode, status: &Status) -> Response<BoxBody> {
+    Response::builder()
+        .status(http_status)
+        .header(header::CONTENT_TYPE, Format::Single.content_type())
+        .body(full_body(Format::Single.error(status)))
+        .unwrap()
+}
+
+fn status_response(status: Status) -> Response<BoxBody> {
+    error_response(http_status(status.code()), &status)
+}
+
+/// The HTTP status used for each `gRPC` code.
+fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        Code::Cancelled => StatusCode::REQUEST_TIMEOUT,
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+            StatusCode::BAD_REQUEST
+        }
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::Unknown | Code::Internal | Code::DataLoss => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
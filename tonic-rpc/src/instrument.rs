@@ -0,0 +1,389 @@
+//! Hooks called by the generated clients and servers to instrument every call.
+//!
+//! The generated code always calls them, as it can't depend on the features of this crate:
+//! without the **`tracing`**, **`opentelemetry`** and **`metrics`** features they do nothing.
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{ready, Context as TaskContext, Poll},
+    time::Instant,
+};
+
+use bytes::Bytes;
+use tonic::{
+    body::BoxBody,
+    client::GrpcService,
+    codegen::{http, Body, Service, StdError},
+    Code, Status,
+};
+
+mod context;
+mod metrics;
+
+pub use self::metrics::Metrics;
+pub(crate) use self::metrics::{InFlight, MessageBytes};
+pub use context::{inject, Context};
+
+#[cfg(feature = "tracing")]
+pub use tracing;
+
+/// The span of a call, or nothing without the `tracing` feature.
+#[cfg(feature = "tracing")]
+pub type Span = tracing::Span;
+
+/// The span of a call, or nothing without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone)]
+pub struct Span;
+
+/// The target of the spans of server calls.
+pub const SERVER_TARGET: &str = "tonic_rpc::server";
+/// The target of the spans of client calls.
+pub const CLIENT_TARGET: &str = "tonic_rpc::client";
+
+/// The span of a call handled by a generated server, named `$name` (`Service/Method`).
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "tracing")]
+macro_rules! __server_span {
+    ($name:literal, $kind:literal, $codec:expr) => {
+        $crate::instrument::tracing::info_span!(
+            target: $crate::instrument::SERVER_TARGET,
+            $name,
+            kind = $kind,
+            codec = $codec,
+            request_size = $crate::instrument::tracing::field::Empty,
+            code = $crate::instrument::tracing::field::Empty,
+            latency_us = $crate::instrument::tracing::field::Empty,
+        )
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "tracing"))]
+macro_rules! __server_span {
+    ($name:literal, $kind:literal, $codec:expr) => {
+        $crate::instrument::Span
+    };
+}
+
+/// The span of a call made by a generated client, named `$name` (`Service/Method`).
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "tracing")]
+macro_rules! __client_span {
+    ($name:literal, $kind:literal, $codec:expr) => {
+        $crate::instrument::tracing::info_span!(
+            target: $crate::instrument::CLIENT_TARGET,
+            $name,
+            kind = $kind,
+            codec = $codec,
+            request_size = $crate::instrument::tracing::field::Empty,
+            code = $crate::instrument::tracing::field::Empty,
+            latency_us = $crate::instrument::tracing::field::Empty,
+        )
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "tracing"))]
+macro_rules! __client_span {
+    ($name:literal, $kind:literal, $codec:expr) => {
+        $crate::instrument::Span
+    };
+}
+
+/// Run the handling of a request by a generated server in `span`, counted in `metrics`, and
+/// in the trace `context` of its client.
+pub async fn server<F>(
+    span: Span,
+    metrics: Metrics,
+    context: Context,
+    call: F,
+) -> Result<http::Response<BoxBody>, Infallible>
+where
+    F: Future<Output = Result<http::Response<BoxBody>, Infallible>>,
+{
+    let end = CallEnd::start(span.clone(), metrics);
+    let response = metrics.scope(context.scope(in_span(&span, call))).await;
+    let response = response.unwrap_or_else(|never| match never {});
+    // Failed calls respond with their status in the headers, while the status of successful
+    // ones is sent after the response messages, and recorded by the body once it's sent.
+    let response = match Status::from_header_map(response.headers()) {
+        Some(status) => {
+            end.end(status.code());
+            response
+        }
+        None => response.map(|body| CallBody { body, end }.boxed_unsync()),
+    };
+    Ok(context.response(response))
+}
+
+/// Run a call made by a generated client in `span`, counted in `metrics`.
+///
+/// `call` is passed the end of the call, which it adds to the extensions of its request so
+/// that the [`Instrumented`] transport of the client records it once the response ends.
+pub async fn client<F, Fut, T>(
+    span: Span,
+    metrics: Metrics,
+    call: F,
+) -> Result<tonic::Response<T>, Status>
+where
+    F: FnOnce(CallEnd) -> Fut,
+    Fut: Future<Output = Result<tonic::Response<T>, Status>>,
+{
+    let end = CallEnd::start(span.clone(), metrics);
+    let response = metrics.scope(in_span(&span, call(end.clone()))).await;
+    // Calls failing before their response body, e.g. with the status in the headers of the
+    // response, end here.
+    if let Err(status) = &response {
+        end.end(status.code());
+    }
+    response
+}
+
+#[cfg(feature = "tracing")]
+fn in_span<F: Future>(span: &Span, call: F) -> impl Future<Output = F::Output> {
+    use tracing::Instrument;
+
+    call.instrument(span.clone())
+}
+
+#[cfg(not(feature = "tracing"))]
+fn in_span<F: Future>(_span: &Span, call: F) -> impl Future<Output = F::Output> {
+    call
+}
+
+#[cfg(feature = "tracing")]
+fn record_end(span: &Span, code: tonic::Code, start: Instant) {
+    span.record("code", tracing::field::debug(code));
+    span.record("latency_us", start.elapsed().as_micros() as u64);
+}
+
+#[cfg(not(feature = "tracing"))]
+fn record_end(_span: &Span, _code: tonic::Code, _start: Instant) {}
+
+/// The end of a call, recorded once by whichever of the response body and the generated code
+/// sees it first.
+#[derive(Clone)]
+pub struct CallEnd(Arc<Ending>);
+
+/// A call until it ends. A call whose end is never seen, e.g. as its response was dropped
+/// before it ended, ends with [`Code::Cancelled`] once every handle to it is dropped.
+struct Ending(Mutex<Option<Call>>);
+
+struct Call {
+    span: Span,
+    metrics: Metrics,
+    _in_flight: InFlight,
+    start: Instant,
+}
+
+impl CallEnd {
+    fn start(span: Span, metrics: Metrics) -> Self {
+        let call = Call {
+            span,
+            metrics,
+            _in_flight: metrics.start(),
+            start: Instant::now(),
+        };
+        CallEnd(Arc::new(Ending(Mutex::new(Some(call)))))
+    }
+
+    fn end(&self, code: Code) {
+        let call = self
+            .0
+             .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(call) = call {
+            call.end(code);
+        }
+    }
+}
+
+impl Call {
+    fn end(self, code: Code) {
+        record_end(&self.span, code, self.start);
+        self.metrics.end(code, self.start);
+    }
+}
+
+impl Drop for Ending {
+    fn drop(&mut self) {
+        let call = self
+            .0
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(call) = call {
+            call.end(Code::Cancelled);
+        }
+    }
+}
+
+/// The response body of a call, handled by a generated server or made by a generated client,
+/// which records the end of the call once its messages are sent, with the status in its
+/// trailers.
+struct CallBody {
+    body: BoxBody,
+    end: CallEnd,
+}
+
+impl Body for CallBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let data = ready!(Pin::new(&mut self.body).poll_data(cx));
+        if let Some(Err(status)) = &data {
+            self.end.end(status.code());
+        }
+        Poll::Ready(data)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        let trailers = ready!(Pin::new(&mut self.body).poll_trailers(cx));
+        let code = match &trailers {
+            Ok(trailers) => trailers
+                .as_ref()
+                .and_then(Status::from_header_map)
+                .map_or(Code::Ok, |status| status.code()),
+            Err(status) => status.code(),
+        };
+        self.end.end(code);
+        Poll::Ready(trailers)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+}
+
+/// The transport of a generated client, which records the end of each call once its response
+/// ends: after the status in the trailers of a stream, or when the stream fails or is dropped.
+#[derive(Debug, Clone)]
+pub struct Instrumented<T> {
+    inner: T,
+}
+
+impl<T> Instrumented<T> {
+    pub fn new(inner: T) -> Self {
+        Instrumented { inner }
+    }
+}
+
+impl<T> Service<http::Request<BoxBody>> for Instrumented<T>
+where
+    T: GrpcService<BoxBody>,
+    T::ResponseBody: Body<Data = Bytes> + Send + 'static,
+    <T::ResponseBody as Body>::Error: Into<StdError>,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = T::Error;
+    type Future = ResponseFuture<T::Future>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<BoxBody>) -> Self::Future {
+        let end = request.extensions_mut().remove::<CallEnd>();
+        ResponseFuture {
+            response: Box::pin(self.inner.call(request)),
+            end,
+        }
+    }
+}
+
+/// The response of a call made through an [`Instrumented`] transport.
+pub struct ResponseFuture<F> {
+    response: Pin<Box<F>>,
+    end: Option<CallEnd>,
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<http::Response<B>, E>>,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<StdError>,
+{
+    type Output = Result<http::Response<BoxBody>, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        let response = ready!(self.response.as_mut().poll(cx))?;
+        let end = self.end.take();
+        Poll::Ready(Ok(response.map(|body| {
+            let body = body
+                .map_err(|err| Status::from_error(err.into()))
+                .boxed_unsync();
+            match end {
+                Some(end) => CallBody { body, end }.boxed_unsync(),
+                None => body,
+            }
+        })))
+    }
+}
+
+/// Records the size of the request messages of a call on its span, from the encoder of
+/// a client or the decoder of a server.
+#[derive(Debug, Clone)]
+pub(crate) struct RequestSize {
+    #[cfg(feature = "tracing")]
+    span: Option<Span>,
+    #[cfg(feature = "tracing")]
+    total: u64,
+}
+
+impl RequestSize {
+    /// For an encoder, which encodes the requests of client calls.
+    pub(crate) fn encoder() -> Self {
+        Self::in_span(CLIENT_TARGET)
+    }
+
+    /// For a decoder, which decodes the requests of server calls.
+    pub(crate) fn decoder() -> Self {
+        Self::in_span(SERVER_TARGET)
+    }
+
+    /// Records on the current span if it's the span of a call with `target`. Encoders and
+    /// decoders are created when calls start, but may be used outside of their span.
+    #[cfg(feature = "tracing")]
+    fn in_span(target: &str) -> Self {
+        let span = Span::current();
+        let is_call = span.metadata().is_some_and(|m| m.target() == target);
+        RequestSize {
+            span: is_call.then_some(span),
+            total: 0,
+        }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    fn in_span(_target: &str) -> Self {
+        RequestSize {}
+    }
+
+    /// Add a message of `len` bytes.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn add(&mut self, len: usize) {
+        if let Some(span) = &self.span {
+            self.total += len as u64;
+            span.record("request_size", self.total);
+        }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub(crate) fn add(&mut self, _len: usize) {}
+}
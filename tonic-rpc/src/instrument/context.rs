@@ -0,0 +1,185 @@
+//! Propagation of the OpenTelemetry context of calls in [W3C Trace Context] headers.
+//!
+//! [W3C Trace Context]: https://www.w3.org/TR/trace-context/
+
+use std::future::Future;
+
+#[cfg(feature = "opentelemetry")]
+use opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceId, TraceState};
+use tonic::{body::BoxBody, codegen::http};
+
+/// The version of the `traceparent` headers sent.
+#[cfg(feature = "opentelemetry")]
+const VERSION: u8 = 0;
+#[cfg(feature = "opentelemetry")]
+const TRACEPARENT: &str = "traceparent";
+#[cfg(feature = "opentelemetry")]
+const TRACESTATE: &str = "tracestate";
+
+/// The trace context of the client of a call, or nothing without the `opentelemetry`
+/// feature.
+#[derive(Debug, Clone)]
+pub struct Context {
+    #[cfg(feature = "opentelemetry")]
+    context: opentelemetry::Context,
+}
+
+/// Send the current OpenTelemetry context with a call made by a generated client.
+#[cfg(feature = "opentelemetry")]
+pub fn inject<T>(mut request: tonic::Request<T>) -> tonic::Request<T> {
+    use opentelemetry::trace::TraceContextExt;
+
+    let context = opentelemetry::Context::current();
+    let span_context = context.span().span_context().clone();
+    if !span_context.is_valid() {
+        return request;
+    }
+    let traceparent = format!(
+        "{:02x}-{}-{}-{:02x}",
+        VERSION,
+        span_context.trace_id(),
+        span_context.span_id(),
+        span_context.trace_flags() & TraceFlags::SAMPLED
+    );
+    let metadata = request.metadata_mut();
+    if let Ok(value) = traceparent.parse() {
+        metadata.insert(TRACEPARENT, value);
+    }
+    let tracestate = span_context.trace_state().header();
+    if !tracestate.is_empty() {
+        if let Ok(value) = tracestate.parse() {
+            metadata.insert(TRACESTATE, value);
+        }
+    }
+    request
+}
+
+/// Send the current OpenTelemetry context with a call made by a generated client.
+#[cfg(not(feature = "opentelemetry"))]
+pub fn inject<T>(request: tonic::Request<T>) -> tonic::Request<T> {
+    request
+}
+
+#[cfg(feature = "opentelemetry")]
+impl Context {
+    /// The context of the client of a call handled by a generated server, a child of the
+    /// current context. Calls without a valid `traceparent` header are handled in the
+    /// current context.
+    pub fn extract(headers: &http::HeaderMap) -> Self {
+        use opentelemetry::trace::TraceContextExt;
+
+        let current = opentelemetry::Context::current();
+        let context = match span_context(headers) {
+            Some(span_context) => current.with_remote_span_context(span_context),
+            None => current,
+        };
+        Context { context }
+    }
+
+    /// Run the handling of a call in this context.
+    pub(crate) fn scope<F: Future>(&self, call: F) -> impl Future<Output = F::Output> {
+        use opentelemetry::trace::FutureExt;
+
+        call.with_context(self.context.clone())
+    }
+
+    /// Stream the response messages of a call in this context.
+    pub(crate) fn response(self, response: http::Response<BoxBody>) -> http::Response<BoxBody> {
+        use tonic::codegen::Body;
+
+        response.map(|body| {
+            ContextBody {
+                body,
+                context: self.context,
+            }
+            .boxed_unsync()
+        })
+    }
+}
+
+#[cfg(not(feature = "opentelemetry"))]
+impl Context {
+    /// The context of the client of a call handled by a generated server.
+    pub fn extract(_headers: &http::HeaderMap) -> Self {
+        Context {}
+    }
+
+    /// Run the handling of a call in this context.
+    pub(crate) fn scope<F: Future>(&self, call: F) -> impl Future<Output = F::Output> {
+        call
+    }
+
+    /// Stream the response messages of a call in this context.
+    pub(crate) fn response(self, response: http::Response<BoxBody>) -> http::Response<BoxBody> {
+        response
+    }
+}
+
+/// Parses a `traceparent` header, and the `tracestate` header with it.
+///
+/// Headers of a version above 0 are parsed like version 0, ignoring the fields they add
+/// after the flags, while the version `ff` is invalid.
+#[cfg(feature = "opentelemetry")]
+fn span_context(headers: &http::HeaderMap) -> Option<SpanContext> {
+    let traceparent = headers.get(TRACEPARENT)?.to_str().ok()?.trim();
+    let parts: Vec<_> = traceparent.split('-').collect();
+    let (version, trace_id, span_id, flags, rest) = match parts[..] {
+        [version, trace_id, span_id, flags, ref rest @ ..] => {
+            (version, trace_id, span_id, flags, rest)
+        }
+        _ => return None,
+    };
+    let is_id = |id: &str, len| {
+        id.len() == len && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    };
+    if !is_id(version, 2) || version == "ff" || (version == "00" && !rest.is_empty()) {
+        return None;
+    }
+    if !is_id(trace_id, 32) || !is_id(span_id, 16) || !is_id(flags, 2) {
+        return None;
+    }
+    let trace_id = TraceId::from_hex(trace_id).ok()?;
+    let span_id = SpanId::from_hex(span_id).ok()?;
+    let flags = TraceFlags::new(u8::from_str_radix(flags, 16).ok()?) & TraceFlags::SAMPLED;
+    let trace_state = headers
+        .get(TRACESTATE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(TraceState::default);
+    let span_context = SpanContext::new(trace_id, span_id, flags, true, trace_state);
+    span_context.is_valid().then_some(span_context)
+}
+
+/// A response body polled in the context of its call, so that the messages of a streaming
+/// response are produced in it.
+#[cfg(feature = "opentelemetry")]
+struct ContextBody {
+    body: BoxBody,
+    context: opentelemetry::Context,
+}
+
+#[cfg(feature = "opentelemetry")]
+impl tonic::codegen::Body for ContextBody {
+    type Data = bytes::Bytes;
+    type Error = tonic::Status;
+
+    fn poll_data(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Result<Self::Data, Self::Error>>> {
+        let _guard = self.context.clone().attach();
+        std::pin::Pin::new(&mut self.body).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        let _guard = self.context.clone().attach();
+        std::pin::Pin::new(&mut self.body).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+}
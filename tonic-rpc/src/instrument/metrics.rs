@@ -0,0 +1,203 @@
+//! Metrics of the calls of generated clients and servers, recorded with the [`metrics`]
+//! facade.
+//!
+//! [`metrics`]: https://docs.rs/metrics
+
+use std::{future::Future, time::Instant};
+
+/// The names of the metrics of one side of calls.
+#[cfg(feature = "metrics")]
+#[derive(Debug)]
+struct Names {
+    requests: &'static str,
+    errors: &'static str,
+    latency: &'static str,
+    in_flight: &'static str,
+    encoded: &'static str,
+    decoded: &'static str,
+}
+
+#[cfg(feature = "metrics")]
+const SERVER: Names = Names {
+    requests: "tonic_rpc_server_requests_total",
+    errors: "tonic_rpc_server_errors_total",
+    latency: "tonic_rpc_server_latency_seconds",
+    in_flight: "tonic_rpc_server_in_flight",
+    encoded: "tonic_rpc_server_encoded_bytes_total",
+    decoded: "tonic_rpc_server_decoded_bytes_total",
+};
+
+#[cfg(feature = "metrics")]
+const CLIENT: Names = Names {
+    requests: "tonic_rpc_client_requests_total",
+    errors: "tonic_rpc_client_errors_total",
+    latency: "tonic_rpc_client_latency_seconds",
+    in_flight: "tonic_rpc_client_in_flight",
+    encoded: "tonic_rpc_client_encoded_bytes_total",
+    decoded: "tonic_rpc_client_decoded_bytes_total",
+};
+
+/// The method of a call, which labels its metrics, or nothing without the `metrics` feature.
+#[derive(Debug, Clone, Copy)]
+pub struct Metrics {
+    #[cfg(feature = "metrics")]
+    names: &'static Names,
+    #[cfg(feature = "metrics")]
+    service: &'static str,
+    #[cfg(feature = "metrics")]
+    method: &'static str,
+    #[cfg(feature = "metrics")]
+    codec: &'static str,
+}
+
+#[cfg(feature = "metrics")]
+tokio::task_local! {
+    /// The call being run, whose encoders and decoders count the bytes of its messages.
+    static CALL: Metrics;
+}
+
+#[cfg(feature = "metrics")]
+impl Metrics {
+    /// The metrics of calls of `service`'s `method` handled by a generated server.
+    pub fn server(service: &'static str, method: &'static str, codec: &'static str) -> Self {
+        Metrics {
+            names: &SERVER,
+            service,
+            method,
+            codec,
+        }
+    }
+
+    /// The metrics of calls of `service`'s `method` made by a generated client.
+    pub fn client(service: &'static str, method: &'static str, codec: &'static str) -> Self {
+        Metrics {
+            names: &CLIENT,
+            service,
+            method,
+            codec,
+        }
+    }
+
+    fn labels(&self) -> Vec<::metrics::Label> {
+        vec![
+            ::metrics::Label::from_static_parts("service", self.service),
+            ::metrics::Label::from_static_parts("method", self.method),
+            ::metrics::Label::from_static_parts("codec", self.codec),
+        ]
+    }
+
+    /// Count a new call, which is in flight until the returned guard is dropped.
+    pub(crate) fn start(&self) -> InFlight {
+        ::metrics::counter!(self.names.requests, self.labels()).increment(1);
+        let in_flight = ::metrics::gauge!(self.names.in_flight, self.labels());
+        in_flight.increment(1.0);
+        InFlight(in_flight)
+    }
+
+    /// Run a call, whose encoders and decoders count the bytes of its messages.
+    pub(crate) fn scope<F: Future>(self, call: F) -> impl Future<Output = F::Output> {
+        CALL.scope(self, call)
+    }
+
+    /// Record the end of a call started at `start`.
+    pub(crate) fn end(&self, code: tonic::Code, start: Instant) {
+        ::metrics::histogram!(self.names.latency, self.labels())
+            .record(start.elapsed().as_secs_f64());
+        if code != tonic::Code::Ok {
+            let mut labels = self.labels();
+            labels.push(::metrics::Label::new("code", format!("{:?}", code)));
+            ::metrics::counter!(self.names.errors, labels).increment(1);
+        }
+    }
+
+    fn bytes(&self, name: &'static str) -> ::metrics::Counter {
+        ::metrics::counter!(name, self.labels())
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+impl Metrics {
+    /// The metrics of calls of `service`'s `method` handled by a generated server.
+    pub fn server(_service: &'static str, _method: &'static str, _codec: &'static str) -> Self {
+        Metrics {}
+    }
+
+    /// The metrics of calls of `service`'s `method` made by a generated client.
+    pub fn client(_service: &'static str, _method: &'static str, _codec: &'static str) -> Self {
+        Metrics {}
+    }
+
+    /// Count a new call.
+    pub(crate) fn start(&self) -> InFlight {
+        InFlight {}
+    }
+
+    /// Run a call.
+    pub(crate) fn scope<F: Future>(self, call: F) -> impl Future<Output = F::Output> {
+        call
+    }
+
+    /// Record the end of a call started at `start`.
+    pub(crate) fn end(&self, _code: tonic::Code, _start: Instant) {}
+}
+
+/// Decrements the in-flight gauge of a call when dropped.
+#[cfg(feature = "metrics")]
+pub(crate) struct InFlight(::metrics::Gauge);
+
+/// A call in flight.
+#[cfg(not(feature = "metrics"))]
+pub(crate) struct InFlight {}
+
+#[cfg(feature = "metrics")]
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.decrement(1.0);
+    }
+}
+
+/// Counts the bytes of the messages of a call, from its encoder or its decoder.
+#[derive(Clone)]
+pub(crate) struct MessageBytes {
+    #[cfg(feature = "metrics")]
+    counter: Option<::metrics::Counter>,
+}
+
+impl MessageBytes {
+    /// For an encoder created by the current call, if any.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn encoder() -> Self {
+        MessageBytes {
+            counter: CALL.try_with(|call| call.bytes(call.names.encoded)).ok(),
+        }
+    }
+
+    /// For a decoder created by the current call, if any.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn decoder() -> Self {
+        MessageBytes {
+            counter: CALL.try_with(|call| call.bytes(call.names.decoded)).ok(),
+        }
+    }
+
+    /// Add a message of `len` bytes.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn add(&mut self, len: usize) {
+        if let Some(counter) = &self.counter {
+            counter.increment(len as u64);
+        }
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    pub(crate) fn encoder() -> Self {
+        MessageBytes {}
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    pub(crate) fn decoder() -> Self {
+        MessageBytes {}
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    pub(crate) fn add(&mut self, _len: usize) {}
+}
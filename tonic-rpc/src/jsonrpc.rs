@@ -0,0 +1,409 @@
+//! A [JSON-RPC 2.0](https://www.jsonrpc.org/specification) endpoint for `#[tonic_rpc(json)]`
+//! services, for clients which can't speak `gRPC`.
+//!
+//! [`JsonRpc`] wraps a generated server. `gRPC` requests are passed through untouched, while
+//! any other `POST` request is handled as a JSON-RPC request or batch of requests:
+//! - `method` is the name of the method in the service trait, e.g. `increment`.
+//! - `params` are the arguments of the method, either as an array in order or as an object
+//!   keyed by the argument names. They can be omitted for methods without arguments.
+//! - `result` is the response of the method.
+//! - Errors returned by the method are sent as JSON-RPC errors, with the `gRPC` code in
+//!   the `data` of the error. `InvalidArgument` errors use the `-32602` (invalid params) code,
+//!   `Unimplemented` errors the `-32601` (method not found) code and other errors a code of
+//!   `-32000` minus the `gRPC` code.
+//!
+//! Streaming methods can't be called with JSON-RPC. Requests larger than
+//! [`JsonRpc::max_request_size`] are rejected with `413 Payload Too Large`.
+//!
+//! ```no_run
+//! # #[tonic_rpc::tonic_rpc(json)]
+//! # trait Increment {
+//! #     fn increment(arg: i32) -> i32;
+//! # }
+//! # struct State;
+//! # #[tonic::async_trait]
+//! # impl increment_server::Increment for State {
+//! #     async fn increment(
+//! #         &self,
+//! #         request: tonic::Request<i32>,
+//! #     ) -> Result<tonic::Response<i32>, tonic::Status> {
+//! #         Ok(tonic::Response::new(request.into_inner() + 1))
+//! #     }
+//! # }
+//! # async fn run() {
+//! tonic::transport::Server::builder()
+//!     // JSON-RPC clients will usually use HTTP/1.1.
+//!     .accept_http1(true)
+//!     .add_service(tonic_rpc::jsonrpc::JsonRpc::new(
+//!         increment_server::IncrementServer::new(State),
+//!     ))
+//!     .serve("[::1]:8080".parse().unwrap())
+//!     .await
+//!     .unwrap();
+//! # }
+//! # fn main() {}
+//! ```
+//! When served by a `tonic` server, requests must be sent to a path under the service's
+//! name, e.g. `/Increment/rpc`. Then
+//! ```text
+//! curl -d '{"jsonrpc": "2.0", "method": "increment", "params": [32], "id": 1}' \
+//!     http://[::1]:8080/Increment/rpc
+//! ```
+//! responds with `{"jsonrpc":"2.0","result":33,"id":1}`.
+
+use std::{
+    convert::Infallible,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use serde_json::{json, Map, Value};
+use tonic::{
+    body::BoxBody,
+    codegen::{
+        http::{header, HeaderValue, Method, Request, Response, StatusCode},
+        poll_fn, BoxFuture, Service,
+    },
+    transport::{Body, NamedService},
+    Code, Status,
+};
+
+use crate::{
+    descriptor::{JsonCodec, MethodDescriptor, ServiceDescriptor},
+    framing::{frame, full_body, is_grpc, read_body, Messages, DEFAULT_MAX_REQUEST_SIZE},
+};
+
+/// Serves JSON-RPC requests for the wrapped `#[tonic_rpc(json)]` server.
+///
+/// The server must encode its messages with a JSON codec, without compression, so other
+/// servers are rejected at compile time:
+/// ```compile_fail
+/// # use tonic_rpc::{descriptor::ServiceDescriptor, jsonrpc::JsonRpc};
+/// fn serve<S: ServiceDescriptor>(server: S) -> JsonRpc<S> {
+///     JsonRpc::new(server)
+/// }
+/// ```
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone)]
+pub struct JsonRpc<S> {
+    inner: S,
+    max_request_size: usize,
+}
+
+impl<S: JsonCodec> JsonRpc<S> {
+    pub fn new(inner: S) -> Self {
+        JsonRpc {
+            inner,
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+        }
+    }
+
+    /// Reject requests whose body is larger than `limit` bytes, 4 MiB by default.
+    pub fn max_request_size(mut self, limit: usize) -> Self {
+        self.max_request_size = limit;
+        self
+    }
+}
+
+impl<S: NamedService> NamedService for JsonRpc<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S> Service<Request<Body>> for JsonRpc<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible>
+        + ServiceDescriptor
+        + JsonCodec
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        if is_grpc(&request) {
+            return Box::pin(self.inner.call(request));
+        }
+        let inner = self.inner.clone();
+        let limit = self.max_request_size;
+        Box::pin(async move { Ok(call_json_rpc(inner, request, limit).await) })
+    }
+}
+
+/// A JSON-RPC error object.
+struct Error {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl Error {
+    const PARSE_ERROR: i64 = -32700;
+    const INVALID_REQUEST: i64 = -32600;
+    const METHOD_NOT_FOUND: i64 = -32601;
+    const INVALID_PARAMS: i64 = -32602;
+
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Error {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    fn invalid_request(message: impl Into<String>) -> Self {
+        Error::new(Error::INVALID_REQUEST, message)
+    }
+
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Error::new(Error::INVALID_PARAMS, message)
+    }
+
+    fn to_json(&self) -> Value {
+        let mut error = json!({
+            "code": self.code,
+            "message": self.message,
+        });
+        if let Some(data) = &self.data {
+            error["data"] = data.clone();
+        }
+        error
+    }
+}
+
+impl From<Status> for Error {
+    fn from(status: Status) -> Self {
+        let code = match status.code() {
+            Code::InvalidArgument => Error::INVALID_PARAMS,
+            Code::Unimplemented => Error::METHOD_NOT_FOUND,
+            code => -32000 - code as i64,
+        };
+        Error {
+            code,
+            message: status.message().to_string(),
+            data: Some(json!({ "grpc_code": status.code() as i32 })),
+        }
+    }
+}
+
+/// The response to a request with the given `id`.
+fn response(id: Value, result: Result<Value, Error>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err(error) => json!({ "jsonrpc": "2.0", "error": error.to_json(), "id": id }),
+    }
+}
+
+async fn call_json_rpc<S>(inner: S, request: Request<Body>, limit: usize) -> Response<BoxBody>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible>
+        + ServiceDescriptor
+        + JsonCodec
+        + Clone,
+{
+    if request.method() != Method::POST {
+        let error = Error::invalid_request("Only POST requests are supported");
+        return json_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            Some(response(Value::Null, Err(error))),
+        );
+    }
+    let (parts, mut body) = request.into_parts();
+    let message = match read_body(&parts.headers, &mut body, limit).await {
+        Ok(Some(message)) => message,
+        Ok(None) => {
+            let error =
+                Error::invalid_request(format!("Request body is larger than {} bytes", limit));
+            return json_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                Some(response(Value::Null, Err(error))),
+            );
+        }
+        Err(status) => {
+            let error = Error::invalid_request(status.message());
+            return json_response(StatusCode::OK, Some(response(Value::Null, Err(error))));
+        }
+    };
+
+    let reply = match serde_json::from_slice(&message) {
+        Ok(Value::Array(calls)) if calls.is_empty() => {
+            let error = Error::invalid_request("Empty batch");
+            Some(response(Value::Null, Err(error)))
+        }
+        Ok(Value::Array(calls)) => {
+            let mut responses = Vec::new();
+            for call in calls {
+                responses.extend(call_one(inner.clone(), call).await);
+            }
+            // A batch of notifications gets no response at all.
+            (!responses.is_empty()).then_some(Value::Array(responses))
+        }
+        Ok(call) => call_one(inner, call).await,
+        Err(err) => {
+            let error = Error::new(Error::PARSE_ERROR, format!("Error parsing request {}", err));
+            Some(response(Value::Null, Err(error)))
+        }
+    };
+    json_response(StatusCode::OK, reply)
+}
+
+fn json_response(status: StatusCode, reply: Option<Value>) -> Response<BoxBody> {
+    match reply {
+        Some(reply) => Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(full_body(reply.to_string().into()))
+            .unwrap(),
+        None => Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(full_body(Bytes::new()))
+            .unwrap(),
+    }
+}
+
+/// Handle a single request of a batch, returning its response unless it's a notification.
+async fn call_one<S>(inner: S, call: Value) -> Option<Value>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible> + ServiceDescriptor,
+{
+    let mut call = match call {
+        Value::Object(call) => call,
+        _ => {
+            let error = Error::invalid_request("Requests must be objects");
+            return Some(response(Value::Null, Err(error)));
+        }
+    };
+    let id = match call.remove("id") {
+        Some(id @ (Value::Null | Value::Number(_) | Value::String(_))) => Some(id),
+        Some(_) => {
+            let error = Error::invalid_request("`id` must be a string, a number or null");
+            return Some(response(Value::Null, Err(error)));
+        }
+        None => None,
+    };
+    let result = match parse_call(call) {
+        Ok((method, params)) => call_method(inner, &method, params).await,
+        Err(error) => return Some(response(id.unwrap_or(Value::Null), Err(error))),
+    };
+    id.map(|id| response(id, result))
+}
+
+/// The method name and parameters of a request.
+fn parse_call(mut call: Map<String, Value>) -> Result<(String, Option<Value>), Error> {
+    if call.get("jsonrpc") != Some(&Value::String("2.0".to_string())) {
+        return Err(Error::invalid_request("`jsonrpc` must be \"2.0\""));
+    }
+    let method = match call.remove("method") {
+        Some(Value::String(method)) => method,
+        _ => return Err(Error::invalid_request("`method` must be a string")),
+    };
+    match call.remove("params") {
+        params @ (None | Some(Value::Array(_)) | Some(Value::Object(_))) => Ok((method, params)),
+        Some(_) => Err(Error::invalid_request(
+            "`params` must be an array or an object",
+        )),
+    }
+}
+
+/// The request message of `method` for the given `params`: its argument if it has one, or
+/// an array of its arguments otherwise, which is how tuples are encoded.
+fn request_message(method: &MethodDescriptor, params: Option<Value>) -> Result<Value, Error> {
+    let mut args = match params {
+        None => Vec::new(),
+        Some(Value::Object(mut params)) => {
+            let args = method
+                .args
+                .iter()
+                .map(|arg| {
+                    params.remove(*arg).ok_or_else(|| {
+                        Error::invalid_params(format!("Missing parameter `{}`", arg))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if let Some(param) = params.keys().next() {
+                return Err(Error::invalid_params(format!(
+                    "Unknown parameter `{}`",
+                    param
+                )));
+            }
+            args
+        }
+        Some(Value::Array(params)) => params,
+        Some(_) => unreachable!("params are checked by `parse_call`"),
+    };
+    if args.len() != method.args.len() {
+        return Err(Error::invalid_params(format!(
+            "Expected {} parameters, got {}",
+            method.args.len(),
+            args.len()
+        )));
+    }
+    Ok(match args.len() {
+        0 => Value::Null,
+        1 => args.pop().unwrap(),
+        _ => Value::Array(args),
+    })
+}
+
+/// Call `name` on the `gRPC` server.
+async fn call_method<S>(mut inner: S, name: &str, params: Option<Value>) -> Result<Value, Error>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible> + ServiceDescriptor,
+{
+    let method = S::method(name).ok_or_else(|| {
+        Error::new(
+            Error::METHOD_NOT_FOUND,
+            format!("Unknown method `{}`", name),
+        )
+    })?;
+    if method.client_streaming || method.server_streaming {
+        return Err(Error::new(
+            Error::METHOD_NOT_FOUND,
+            format!("`{}` is a streaming method", name),
+        ));
+    }
+    let message = request_message(method, params)?;
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(method.path)
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/grpc"),
+        )
+        .header(header::TE, HeaderValue::from_static("trailers"))
+        .body(Body::from(frame(message.to_string().into())))
+        .unwrap();
+    poll_fn(|cx| inner.poll_ready(cx))
+        .await
+        .unwrap_or_else(|never| match never {});
+    let response = inner
+        .call(request)
+        .await
+        .unwrap_or_else(|never| match never {});
+    if let Some(status) = Status::from_header_map(response.headers()) {
+        if status.code() != Code::Ok {
+            return Err(status.into());
+        }
+    }
+
+    let mut messages = Messages::new(response.into_body());
+    let message = match poll_fn(|cx| messages.poll_message(cx)).await {
+        Some(message) => message?,
+        None => return Err(Status::internal("Missing response message").into()),
+    };
+    // The trailers may still hold an error.
+    if let Some(Err(status)) = poll_fn(|cx| messages.poll_message(cx)).await {
+        return Err(status.into());
+    }
+    serde_json::from_slice(&message)
+        .map_err(|err| Status::internal(format!("Error parsing response {}", err)).into())
+}
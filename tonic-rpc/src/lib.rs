@@ -103,10 +103,21 @@
 //! Multiple codecs are available for serializing the RPC request/response types.
 //! Each codec is enabled by a [feature flag](https://doc.rust-lang.org/cargo/reference/features.html#the-features-section).
 //! **At least one of these features must be enabled.**
+//! - **`avro`** - using the [Avro](https://avro.apache.org/) binary encoding, see [Avro](#avro)
 //! - **`bincode`** - using [`bincode`](https://crates.io/crates/bincode)
+//! - **`bson`** - using [`bson`](https://crates.io/crates/bson), wrapping messages which
+//!   aren't documents in a document with a single `$value` field
 //! - **`cbor`** - using [`serde_cbor`](https://crates.io/crates/serde_cbor)
+//!   - **`cbor_canonical`** - also enabled by the `cbor` feature, using the deterministic
+//!     encoding of [RFC 8949](https://www.rfc-editor.org/rfc/rfc8949.html#section-4.2)
+//!     so that equal messages are always encoded to the same bytes
 //! - **`json`** - using [`serde_json`](https://crates.io/crates/serde_json)
 //! - **`messagepack`** - using [`rmp-serde`](https://crates.io/crates/rmp-serde)
+//! - **`postcard`** - using [`postcard`](https://crates.io/crates/postcard)
+//! - **`prost`** - using [`prost`](https://crates.io/crates/prost), see [Protobuf](#protobuf)
+//! - **`rkyv`** - using [`rkyv`](https://crates.io/crates/rkyv), see [Zero-copy messages](#zero-copy-messages)
+//! - **`ron`** - using [`ron`](https://crates.io/crates/ron), a readable encoding which keeps
+//!   the shape of Rust types such as newtype variants and tuple structs
 //!
 //! E.g. To use the encode using `cbor`, use the attribute
 //! ```ignore
@@ -118,6 +129,51 @@
 //! ```
 //! in `Cargo.toml`.
 //!
+//! # Compression
+//! Messages of any `serde` based codec can be compressed per message with `zstd`, `lz4` or `snappy`,
+//! each enabled by the feature of the same name:
+//! ```ignore
+//! #[tonic_rpc::tonic_rpc(bincode, compress = "zstd")]
+//! trait Archive {
+//!     fn store(blob: Vec<u8>) -> u64;
+//!
+//!     #[rpc(compress = "none")]
+//!     fn size() -> u64;
+//! }
+//! ```
+//! Methods can override the compression of the service with `#[rpc(compress = "..")]`.
+//! The compression can also be given as the path of a type implementing `tonic_rpc::codec::Compression`,
+//! such as `"::tonic_rpc::codec::ZstdWithDictionary<crate::MyDictionary>"` to compress with a trained
+//! `zstd` dictionary. Without the macro, the same codecs are available as e.g.
+//! `Codec<Compressed<BincodeSerdeCodec, Zstd>, T, U>`. Messages which decompress to more than 4 MiB are
+//! rejected with `ResourceExhausted`; `Compressed::with_limit` sets another limit.
+//!
+//! # Configured codecs
+//! Codecs are values implementing `tonic_rpc::codec::SerdeCodec`, so they can carry configuration.
+//! The generated clients and servers use the codec named in the attribute by default, or the codec passed
+//! to `with_codec`, which is cloned into each call:
+//! ```ignore
+//! let server = store_server::StoreServer::with_codec(State, LimitedBincodeSerdeCodec::new(1 << 20));
+//! let client = store_client::StoreClient::with_codec(channel, PrettyJsonSerdeCodec::new(4));
+//! ```
+//! Besides the default codecs, `tonic_rpc::codec` provides `LimitedBincodeSerdeCodec`, which rejects
+//! messages over a size limit, `PrettyJsonSerdeCodec`, and the `ZstdWithLevel` compression.
+//!
+//! # Encryption
+//! Messages can be sealed with an AEAD so that proxies terminating TLS can neither read nor modify them.
+//! The features `chacha20poly1305` and `aes-gcm` enable `tonic_rpc::codec::Encrypted`, which wraps any
+//! `serde` based codec and is constructed with a 256 bit key. Like other [configured codecs](#configured-codecs),
+//! it's passed to `with_codec`:
+//! ```ignore
+//! let codec = Encrypted::chacha20poly1305(BincodeSerdeCodec, &key);
+//! let server = increment_server::IncrementServer::with_codec(State, codec.clone());
+//! let client = increment_client::IncrementClient::with_codec(channel, codec);
+//! ```
+//! Each message is sent with a random nonce and authenticated along with the method, its direction and its
+//! index in the stream, so messages can't be replayed elsewhere. Messages failing authentication are rejected with
+//! `Code::DataLoss`. To compress encrypted messages, compress the inner codec,
+//! e.g. `Encrypted::aes256gcm(Compressed::new(BincodeSerdeCodec, Zstd), &key)`.
+//!
 //! # Streaming
 //! Streaming can be added on the client or server side by adding the attributes
 //! `#[client_streaming]` or `#[server_streaming]` to a function in the service trait.
@@ -125,6 +181,235 @@
 //!
 //! Examples that use streaming can be found in the [tests folder](https://github.com/adamrk/tonic-rpc/tree/main/tonic-rpc/tests).
 //!
+//! # Local and remote services
+//! The macro also generates an `IncrementAsync` trait in the `increment_async` module, with an async
+//! version of each method of the declared trait. It's implemented by `IncrementClient`, over any channel, and by
+//! every type implementing `increment_server::Increment`, so that code generic over it runs against
+//! either a remote or a local service:
+//! ```ignore
+//! async fn increment_twice<S: increment_async::IncrementAsync>(service: &S) -> Result<i32, Status> {
+//!     let once = service.increment(1).await?;
+//!     service.increment(once).await
+//! }
+//!
+//! increment_twice(&State).await?;
+//! increment_twice(&IncrementClient::connect(addr).await?).await?;
+//! ```
+//! `#[client_streaming]` methods and methods sending pre-encoded messages are left out of it, and
+//! services using the `rkyv` codec don't have one.
+//!
+//! # Dispatching calls
+//! With `serde` based codecs, the `increment_dispatch` module contains a serializable `IncrementCall`
+//! enum with a variant for each unary method, a matching `IncrementReply` enum and a `dispatch`
+//! function handling a call with an implementation of `increment_server::Increment`. This routes
+//! requests received over other channels, such as message queues, to the same handlers:
+//! ```ignore
+//! let call: IncrementCall = serde_json::from_slice(&message)?;
+//! let reply: IncrementReply = increment_dispatch::dispatch(&State, call).await?;
+//! ```
+//! Each variant holds the request or response of its method, e.g. `IncrementCall::Increment(i32)`.
+//!
+//! # Pre-encoded messages
+//! With `serde` based codecs, a method can declare `tonic_rpc::codec::Encoded<T, C>`, where `C` is
+//! the codec of the method, instead of `T` as its argument or return type. The sending side then
+//! sends messages serialized ahead of time, while the receiving side receives `T` as usual. This
+//! avoids serializing the same message for every stream it's sent on:
+//! ```ignore
+//! #[tonic_rpc::tonic_rpc(json)]
+//! trait Feed {
+//!     #[server_streaming]
+//!     fn subscribe() -> codec::Encoded<Update, JsonSerdeCodec>;
+//! }
+//!
+//! let update = Encoded::new(&JsonSerdeCodec, &update)?;
+//! for subscriber in &subscribers {
+//!     subscriber.send(Ok(update.clone())).await?;
+//! }
+//! ```
+//! The type must be written as `codec::Encoded` or `tonic_rpc::codec::Encoded`, so that message
+//! types named `Encoded` aren't mistaken for it. Messages serialized with another codec type are
+//! rejected at compile time, and cloning a message doesn't copy the bytes. Codecs binding messages
+//! to their position in a call, such as `Encrypted`, don't implement `PositionIndependent`, so their
+//! messages can't be serialized ahead of time with `Encoded::new`.
+//!
+//! # Zero-copy messages
+//! Services using the `rkyv` codec send and receive messages as `tonic_rpc::codec::Archived<T>`
+//! instead of `T`. Received messages are validated when they are decoded and can then be read
+//! in place with `Archived::get`, or deserialized on demand with `Archived::deserialize`.
+//! The types must implement `rkyv::Archive` and `rkyv::Serialize`, and their archived form must
+//! implement `bytecheck::CheckBytes`, e.g. by adding `#[archive(check_bytes)]`.
+//! ```ignore
+//! #[tonic_rpc::tonic_rpc(rkyv)]
+//! trait Snapshots {
+//!     fn snapshot(version: u64) -> Snapshot;
+//! }
+//! ```
+//! becomes
+//! ```ignore
+//! async fn snapshot(&self, request: tonic::Request<Archived<u64>>)
+//!     -> Result<tonic::Response<Archived<Snapshot>>, tonic::Status>
+//! ```
+//!
+//! # Protobuf
+//! Services using the `prost` codec are wire compatible with `gRPC` services defined in `proto` files.
+//! Each method must take a single argument and return a single value, and these types must implement
+//! `prost::Message` instead of the `serde` traits. `()` is encoded as `google.protobuf.Empty`.
+//!
+//! For these services the macro also generates a function returning the `proto` file of the
+//! service, defining its messages, which can be used to generate clients in other languages.
+//! The messages must implement `tonic_rpc::proto::ProtoMessage`, which can be derived alongside
+//! `prost::Message` for structs with scalar, string, bytes and message fields:
+//! ```ignore
+//! #[derive(Clone, PartialEq, prost::Message, tonic_rpc::proto::ProtoMessage)]
+//! pub struct IncRequest {
+//!     #[prost(int32, tag = "1")]
+//!     pub num: i32,
+//! }
+//!
+//! #[tonic_rpc::tonic_rpc(prost)]
+//! trait Increment {
+//!     fn increment(arg: IncRequest) -> IncResponse;
+//! }
+//!
+//! // Generated:
+//! pub fn increment_proto() -> String { .. }
+//! ```
+//! `ProtoMessage` is also implemented for the scalar types `prost` encodes as the
+//! `google.protobuf` wrapper messages, such as `String` and `i32`.
+//!
+//! # Avro
+//! Services using the `avro` codec encode messages with the Avro schema of their types, given by
+//! the `tonic_rpc::avro::AvroSchema` trait. It can be derived for structs and enums with
+//! `#[derive(AvroSchema)]`, following their `serde` attributes. Each message starts with the
+//! fingerprint of its schema, and messages written with another schema are rejected. The macro
+//! also generates a function returning the schemas of each method:
+//! ```ignore
+//! #[tonic_rpc::tonic_rpc(avro)]
+//! trait Ingest {
+//!     fn ingest(event: Event) -> u64;
+//! }
+//!
+//! // Generated:
+//! pub fn ingest_avro_schemas() -> tonic_rpc::avro::ServiceSchemas { .. }
+//! ```
+//! Wrapping the server in an `AvroServer` and the client's channel in an `AvroChannel` sends the
+//! fingerprints of these schemas in the metadata of each call, so that mismatched schemas are rejected
+//! before any message is sent. The `AvroServer` rejects calls without fingerprints.
+//! See the [`avro`](avro) module for details.
+//!
+//! # JSON gateway
+//! With the **`gateway`** feature, services using the `json` codec can also be called by clients
+//! that can't speak `gRPC`. Wrapping the generated server in a `JsonGateway` accepts
+//! `POST /{Service}/{Method}` requests with a plain JSON body and responds with plain JSON,
+//! or with newline delimited JSON or server-sent events for server streaming methods.
+//! Servers using another codec, or compressing their messages, are rejected at compile time.
+//! See the [`gateway`](gateway) module for details.
+//!
+//! # JSON-RPC
+//! With the **`jsonrpc`** feature, services using the `json` codec can also be called with
+//! [JSON-RPC 2.0](https://www.jsonrpc.org/specification) by wrapping the generated server in a
+//! `JsonRpc`. The `method` of a request is the name of the trait method and its `params` are the
+//! arguments, by position or by name. Batches are supported, and errors returned by the
+//! service are sent as JSON-RPC error objects. Servers using another codec, or compressing their
+//! messages, are rejected at compile time.
+//! See the [`jsonrpc`](jsonrpc) module for details.
+//!
+//! # Subprocess plugins
+//! With the **`stdio`** feature, a generated server can be served on the standard input and output
+//! of a process, and called by the parent process through the pipes of the child. Plugins run as
+//! subprocesses then only implement the generated server trait.
+//! See the [`transport::stdio`](transport::stdio) module for details.
+//!
+//! # Unix domain sockets
+//! On Unix, with the **`unix`** feature, `tonic_rpc::serve_unix` serves a router on a Unix domain
+//! socket, removing the socket file left by a server which is no longer running, and generated
+//! clients connect to it with `connect_unix`:
+//! ```ignore
+//! let router = Server::builder().add_service(IncrementServer::new(State));
+//! tokio::spawn(tonic_rpc::serve_unix("/run/increment.sock", router));
+//!
+//! // Once the server is listening:
+//! let mut client = IncrementClient::connect_unix("/run/increment.sock").await?;
+//! ```
+//! See the [`transport::unix`](transport::unix) module for setting the permissions of the socket.
+//!
+//! # WebSockets
+//! With the **`websocket`** feature, wrapping a generated server in a `WebSocket` serves it to clients
+//! which can't hold HTTP/2 streams, such as browsers behind restrictive proxies. Calls of all kinds
+//! are multiplexed on a single connection, and a `WebSocketChannel` gives the generated client over it:
+//! ```ignore
+//! let router = Server::builder()
+//!     .accept_http1(true)
+//!     .add_service(WebSocket::new(IncrementServer::new(State)));
+//!
+//! let channel = WebSocketChannel::connect("ws://[::1]:8080/Increment/ws").await?;
+//! let mut client = IncrementClient::new(channel);
+//! ```
+//! See the [`transport::websocket`](transport::websocket) module for the protocol used.
+//!
+//! # Tracing
+//! With the **`tracing`** feature, the generated servers and clients run each call in an `INFO`
+//! span named after the service and the method, e.g. `Increment/Increment`, with the targets
+//! `tonic_rpc::server` and `tonic_rpc::client`. The spans have the fields:
+//! - `kind`: `unary`, `client_streaming`, `server_streaming` or `bidi_streaming`.
+//! - `codec`: the type of the codec.
+//! - `request_size`: the total size of the encoded requests.
+//! - `code`: the `tonic::Code` the call completed with. This is the status sent or received
+//!   after the response messages, e.g. the error ending a stream, or `Cancelled` if the
+//!   response is dropped before it ends.
+//! - `latency_us`: the time taken by the call, in microseconds, including sending or receiving
+//!   all of the response messages.
+//!
+//! # OpenTelemetry
+//! With the **`opentelemetry`** feature, the generated clients send the current OpenTelemetry
+//! context with each call, in W3C Trace Context `traceparent` and `tracestate` headers. The
+//! generated servers handle each call in the context of its client, including while sending
+//! the messages of streaming responses, so spans started by the implementation of a service are
+//! children of the span of the client. Calls without a `traceparent` header are handled in the
+//! current context.
+//!
+//! # Metrics
+//! With the **`metrics`** feature, the generated servers and clients record metrics of their
+//! calls with the [`metrics`](https://docs.rs/metrics) facade, to be exported by any recorder,
+//! e.g. [`metrics-exporter-prometheus`](https://docs.rs/metrics-exporter-prometheus). The metrics
+//! of servers are named `tonic_rpc_server_*` and those of clients `tonic_rpc_client_*`, and are
+//! labelled with the `service`, the `method` and the `codec` type:
+//! - `requests_total`: the number of calls.
+//! - `errors_total`: the number of failed calls, also labelled with their `code`.
+//! - `latency_seconds`: a histogram of the time taken by the calls.
+//! - `in_flight`: the number of calls in progress.
+//! - `encoded_bytes_total` and `decoded_bytes_total`: the total size of the messages encoded
+//!   and decoded.
+//!
+//! Calls end once their response messages and status are sent or received, so the errors ending
+//! a stream are counted, and responses dropped before they end are counted as `Cancelled`.
+//!
+//! # Packages
+//! By default the generated services have no `gRPC` package, so the `increment` method above
+//! is served at `/Increment/Increment`. A package can be given with the `package` option:
+//! ```ignore
+//! #[tonic_rpc::tonic_rpc(json, package = "myteam.billing.v1")]
+//! ```
+//! which serves the same method at `/myteam.billing.v1.Increment/Increment`.
+//! Services with the same name in different packages can be added to the same server,
+//! e.g. to serve multiple versions of a trait side-by-side from different modules.
+//!
+//! # Wire names
+//! The service name on the wire is the name of the trait and the method names are
+//! the function names converted to `UpperCamelCase`. Either can be overridden, e.g. to
+//! match the routes of an existing `gRPC` service or to rename Rust code without breaking clients:
+//! ```ignore
+//! #[tonic_rpc::tonic_rpc(json, name = "UserService")]
+//! trait Users {
+//!     #[rpc(name = "GetUser")]
+//!     fn lookup(id: u32) -> String;
+//! }
+//! ```
+//! serves `lookup` at `/UserService/GetUser`.
+//! The associated stream type of a `#[server_streaming]` method is named after
+//! the Rust method, e.g. `LookupStream`, so overriding a wire name doesn't change the server
+//! implementations.
+//!
 //! # Request/Response types
 //!
 //! The traits and functions generated by `tonic-rpc` will be transformations
@@ -142,6 +427,21 @@
 //! async fn f(&self, arg: tonic::Request<(X,Y)>) -> ..
 //! ```
 //!
+//! Borrowed arguments are received as their owned types: `&str` as `String`,
+//! `&[T]` as `Vec<T>` and any other `&T` as `T`.
+//! Only top-level borrows are supported: a type such as `Option<&str>` is rejected at
+//! compile time.
+//! ```ignore
+//! fn lookup(key: &str, ids: &[u32]) -> ..
+//! ```
+//! becomes
+//! ```ignore
+//! async fn lookup(&self, arg: tonic::Request<(String, Vec<u32>)>) -> ..
+//! ```
+//! while the client accepts `(&str, &[u32])` and serializes it without cloning.
+//! Borrowed arguments require a `serde` encoding, and `#[client_streaming]` clients
+//! send the owned types.
+//!
 //! ## Return value
 //! ```ignore
 //! fn f(..) -> Z
@@ -175,7 +475,35 @@
 //!
 
 #![cfg_attr(docsrs, feature(doc_cfg))]
+// `tonic::Status` is the error type used throughout `tonic`.
+#![allow(clippy::result_large_err)]
 
 pub use tonic_rpc_macro::tonic_rpc;
 
+// Used by the `Serialize` and `Deserialize` impls of the generated call enums.
+#[doc(hidden)]
+pub use serde;
+
+#[cfg(feature = "avro")]
+#[cfg_attr(docsrs, doc(cfg(feature = "avro")))]
+pub mod avro;
 pub mod codec;
+pub mod descriptor;
+#[cfg(any(feature = "gateway", feature = "jsonrpc", feature = "websocket"))]
+mod framing;
+#[cfg(feature = "gateway")]
+#[cfg_attr(docsrs, doc(cfg(feature = "gateway")))]
+pub mod gateway;
+// Called by the generated clients and servers to instrument calls.
+#[doc(hidden)]
+pub mod instrument;
+#[cfg(feature = "jsonrpc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "jsonrpc")))]
+pub mod jsonrpc;
+#[cfg(feature = "prost")]
+#[cfg_attr(docsrs, doc(cfg(feature = "prost")))]
+pub mod proto;
+pub mod transport;
+#[cfg(all(unix, feature = "unix"))]
+#[cfg_attr(docsrs, doc(cfg(all(unix, feature = "unix"))))]
+pub use transport::unix::serve as serve_unix;
@@ -0,0 +1,139 @@
+//! `proto` definitions of the messages of services using the `prost` codec.
+//!
+//! The macro generates a function returning the `proto` file of each `prost` service, defining
+//! its messages with their [`ProtoMessage`] implementation. `ProtoMessage` can be derived
+//! alongside `prost::Message` for structs whose fields are scalars, strings, bytes or other
+//! messages. It is implemented for `()`, encoded as `google.protobuf.Empty`, and for the
+//! scalar types `prost` encodes like the `google.protobuf` wrapper messages.
+
+use std::fmt::Write;
+
+pub use tonic_rpc_macro::ProtoMessage;
+
+/// A message which can be defined in a `proto` file.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be described in a `proto` file",
+    note = "derive `tonic_rpc::proto::ProtoMessage` alongside `prost::Message`"
+)]
+pub trait ProtoMessage {
+    /// The name of the message in `proto` files.
+    const NAME: &'static str;
+
+    /// Add the definition of this message, and of the messages it contains, to `file`.
+    fn define(file: &mut ProtoFile);
+}
+
+/// A `proto` file being generated, containing a service and the definitions of its messages.
+#[derive(Debug, Default)]
+pub struct ProtoFile {
+    imports: Vec<&'static str>,
+    /// The messages defined in the file, in the order they are first used.
+    messages: Vec<(&'static str, Option<String>)>,
+}
+
+impl ProtoFile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define the message `T` if it isn't defined yet, and return its name.
+    pub fn message<T: ProtoMessage + ?Sized>(&mut self) -> &'static str {
+        T::define(self);
+        T::NAME
+    }
+
+    /// Import the `proto` file at `path`.
+    pub fn import(&mut self, path: &'static str) {
+        if !self.imports.contains(&path) {
+            self.imports.push(path);
+        }
+    }
+
+    /// Reserve the definition of the message `name`, returning `false` if it is already
+    /// defined. Messages reserve their definition before defining the messages they contain,
+    /// so that recursive messages are only defined once.
+    pub fn declare(&mut self, name: &'static str) -> bool {
+        if self.messages.iter().any(|(defined, _)| *defined == name) {
+            return false;
+        }
+        self.messages.push((name, None));
+        true
+    }
+
+    /// Set the definition of the declared message `name`.
+    pub fn define(&mut self, name: &'static str, definition: String) {
+        if let Some((_, slot)) = self
+            .messages
+            .iter_mut()
+            .find(|(defined, _)| *defined == name)
+        {
+            *slot = Some(definition);
+        }
+    }
+
+    /// The `proto` file in `package`, containing `service` followed by the messages.
+    pub fn finish(self, package: &str, service: &str) -> String {
+        let mut proto = "syntax = \"proto3\";\n\n".to_string();
+        if !package.is_empty() {
+            let _ = write!(proto, "package {};\n\n", package);
+        }
+        for import in &self.imports {
+            let _ = writeln!(proto, "import \"{}\";", import);
+        }
+        if !self.imports.is_empty() {
+            proto.push('\n');
+        }
+        proto.push_str(service);
+        for (_, definition) in self.messages {
+            if let Some(definition) = definition {
+                proto.push('\n');
+                proto.push_str(&definition);
+            }
+        }
+        proto
+    }
+}
+
+impl ProtoMessage for () {
+    const NAME: &'static str = "google.protobuf.Empty";
+
+    fn define(file: &mut ProtoFile) {
+        file.import("google/protobuf/empty.proto");
+    }
+}
+
+impl<T: ProtoMessage + ?Sized> ProtoMessage for Box<T> {
+    const NAME: &'static str = T::NAME;
+
+    fn define(file: &mut ProtoFile) {
+        T::define(file)
+    }
+}
+
+macro_rules! wrapper_messages {
+    ($($ty:ty => $name:literal,)*) => {
+        $(
+            impl ProtoMessage for $ty {
+                const NAME: &'static str = concat!("google.protobuf.", $name);
+
+                fn define(file: &mut ProtoFile) {
+                    file.import("google/protobuf/wrappers.proto");
+                }
+            }
+        )*
+    };
+}
+
+// `prost` encodes these types as the value of the `google.protobuf` wrapper messages.
+wrapper_messages! {
+    bool => "BoolValue",
+    i32 => "Int32Value",
+    i64 => "Int64Value",
+    u32 => "UInt32Value",
+    u64 => "UInt64Value",
+    f32 => "FloatValue",
+    f64 => "DoubleValue",
+    String => "StringValue",
+    Vec<u8> => "BytesValue",
+    bytes::Bytes => "BytesValue",
+}
@@ -0,0 +1,29 @@
+//! Transports for serving and calling generated services over other channels than TCP.
+
+#[cfg(feature = "stdio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "stdio")))]
+pub mod stdio;
+#[cfg(all(unix, feature = "unix"))]
+#[cfg_attr(docsrs, doc(cfg(all(unix, feature = "unix"))))]
+pub mod unix;
+#[cfg(feature = "websocket")]
+#[cfg_attr(docsrs, doc(cfg(feature = "websocket")))]
+pub mod websocket;
+
+/// Expands to the items of generated clients which use the `unix` feature, e.g.
+/// `connect_unix`, when it's enabled.
+#[doc(hidden)]
+#[macro_export]
+#[cfg(all(unix, feature = "unix"))]
+macro_rules! __unix {
+    ($($item:tt)*) => {
+        $($item)*
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(all(unix, feature = "unix")))]
+macro_rules! __unix {
+    ($($item:tt)*) => {};
+}
@@ -0,0 +1,274 @@
+//! Serve and call generated services over the standard input and output of a process, e.g.
+//! to run plugins as subprocesses.
+//!
+//! The plugin serves its generated server on its own stdin and stdout with [`serve`], and the
+//! host connects to it through the pipes of the child process with [`connect`]. Requests use
+//! HTTP/2 over the pipes, exactly as they would over TCP, so the plugin only implements the
+//! trait generated by `#[tonic_rpc]`. Since stdout carries the requests, the plugin must not
+//! print anything to it: log to stderr instead.
+//!
+//! ```no_run
+//! # #[tonic_rpc::tonic_rpc(json)]
+//! # trait Increment {
+//! #     fn increment(arg: i32) -> i32;
+//! # }
+//! # struct State;
+//! # #[tonic::async_trait]
+//! # impl increment_server::Increment for State {
+//! #     async fn increment(
+//! #         &self,
+//! #         request: tonic::Request<i32>,
+//! #     ) -> Result<tonic::Response<i32>, tonic::Status> {
+//! #         Ok(tonic::Response::new(request.into_inner() + 1))
+//! #     }
+//! # }
+//! use std::process::Stdio;
+//! use tonic_rpc::transport::stdio::{self, Pipe};
+//!
+//! // In the plugin:
+//! # async fn plugin() {
+//! stdio::serve(increment_server::IncrementServer::new(State))
+//!     .await
+//!     .unwrap();
+//! # }
+//!
+//! // In the host:
+//! # async fn host() {
+//! let mut child = tokio::process::Command::new("increment-plugin")
+//!     .stdin(Stdio::piped())
+//!     .stdout(Stdio::piped())
+//!     .spawn()
+//!     .unwrap();
+//! let channel = stdio::connect(Pipe::child(&mut child).unwrap())
+//!     .await
+//!     .unwrap();
+//! let mut client = increment_client::IncrementClient::new(channel);
+//! assert_eq!(33, client.increment(32).await.unwrap().into_inner());
+//! # }
+//! # fn main() {}
+//! ```
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf, Stdin, Stdout},
+    process::{Child, ChildStdin, ChildStdout},
+    sync::oneshot,
+};
+use tonic::{
+    body::BoxBody,
+    codegen::{
+        futures_core::Stream,
+        http::{Request, Response, Uri},
+        Service,
+    },
+    transport::{server::Connected, Body, Channel, Endpoint, NamedService, Server},
+};
+
+/// A bidirectional byte stream made of a reader and a writer, such as the standard input and
+/// output of a process or the pipes to a child process.
+#[derive(Debug)]
+pub struct Pipe<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R, W> Pipe<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        Pipe { reader, writer }
+    }
+}
+
+impl Pipe<Stdin, Stdout> {
+    /// The standard input and output of this process.
+    pub fn stdio() -> Self {
+        Pipe::new(tokio::io::stdin(), tokio::io::stdout())
+    }
+}
+
+impl Pipe<ChildStdout, ChildStdin> {
+    /// The pipes to the stdout and stdin of `child`, which must have been spawned with both
+    /// piped. The pipes are taken from `child`.
+    pub fn child(child: &mut Child) -> io::Result<Self> {
+        match (child.stdout.take(), child.stdin.take()) {
+            (Some(stdout), Some(stdin)) => Ok(Pipe::new(stdout, stdin)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "the child process's stdin and stdout must be piped",
+            )),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin, W: Unpin> AsyncRead for Pipe<R, W> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.reader).poll_read(cx, buf)
+    }
+}
+
+impl<R: Unpin, W: AsyncWrite + Unpin> AsyncWrite for Pipe<R, W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
+
+/// The pipe served by [`serve_pipe`], which signals the server to shut down once the
+/// connection using it is closed.
+struct ServedPipe<R, W> {
+    pipe: Pipe<R, W>,
+    _closed: oneshot::Sender<()>,
+}
+
+impl<R, W> Connected for ServedPipe<R, W> {
+    type ConnectInfo = ();
+
+    fn connect_info(&self) {}
+}
+
+impl<R: AsyncRead + Unpin, W: Unpin> AsyncRead for ServedPipe<R, W> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.pipe).poll_read(cx, buf)
+    }
+}
+
+impl<R: Unpin, W: AsyncWrite + Unpin> AsyncWrite for ServedPipe<R, W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.pipe).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.pipe).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.pipe).poll_shutdown(cx)
+    }
+}
+
+/// The connections accepted by the server: the pipe, and then none until it shuts down.
+struct Incoming<IO>(Option<IO>);
+
+impl<IO: Unpin> Stream for Incoming<IO> {
+    type Item = Result<IO, Infallible>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.0.take() {
+            Some(io) => Poll::Ready(Some(Ok(io))),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Serve `service` on the standard input and output of this process, until the client
+/// closes the connection.
+pub async fn serve<S>(service: S) -> Result<(), tonic::transport::Error>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible>
+        + NamedService
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    serve_pipe(service, Pipe::stdio()).await
+}
+
+/// Serve `service` on `pipe`, until the client closes the connection.
+pub async fn serve_pipe<S, R, W>(
+    service: S,
+    pipe: Pipe<R, W>,
+) -> Result<(), tonic::transport::Error>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible>
+        + NamedService
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (closed_tx, closed_rx) = oneshot::channel();
+    let pipe = ServedPipe {
+        pipe,
+        _closed: closed_tx,
+    };
+    Server::builder()
+        .add_service(service)
+        .serve_with_incoming_shutdown(Incoming(Some(pipe)), async {
+            // The sender is dropped along with the connection.
+            let _ = closed_rx.await;
+        })
+        .await
+}
+
+/// Connects a [`Channel`] to the server at the other end of a pipe. A pipe can only be
+/// connected once, so the channel doesn't reconnect if the connection is lost.
+struct PipeConnector<R, W>(Option<Pipe<R, W>>);
+
+impl<R, W> Service<Uri> for PipeConnector<R, W>
+where
+    R: Send + 'static,
+    W: Send + 'static,
+{
+    type Response = Pipe<R, W>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Pipe<R, W>>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let pipe = self.0.take().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotConnected,
+                "the pipe was already connected",
+            )
+        });
+        Box::pin(async move { pipe })
+    }
+}
+
+/// Connect to the server at the other end of `pipe`, e.g. the pipes to a child process
+/// serving with [`serve`]. The returned channel can be used to create any generated client.
+pub async fn connect<R, W>(pipe: Pipe<R, W>) -> Result<Channel, tonic::transport::Error>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    // The URI is only used for the `:authority` of requests.
+    Endpoint::from_static("http://stdio")
+        .connect_with_connector(PipeConnector(Some(pipe)))
+        .await
+}
@@ -0,0 +1,226 @@
+//! Serve and call generated services over Unix domain sockets, e.g. to talk to sidecar
+//! processes on the same host.
+//!
+//! Servers are served with [`serve`], also available as [`serve_unix`](crate::serve_unix),
+//! or with [`UnixSocket`] to set the permissions of the socket. Generated clients connect
+//! with `connect_unix`.
+//!
+//! ```no_run
+//! # #[tonic_rpc::tonic_rpc(json)]
+//! # trait Increment {
+//! #     fn increment(arg: i32) -> i32;
+//! # }
+//! # struct State;
+//! # #[tonic::async_trait]
+//! # impl increment_server::Increment for State {
+//! #     async fn increment(
+//! #         &self,
+//! #         request: tonic::Request<i32>,
+//! #     ) -> Result<tonic::Response<i32>, tonic::Status> {
+//! #         Ok(tonic::Response::new(request.into_inner() + 1))
+//! #     }
+//! # }
+//! # async fn run() {
+//! let router = tonic::transport::Server::builder()
+//!     .add_service(increment_server::IncrementServer::new(State));
+//! tokio::spawn(tonic_rpc::serve_unix("/run/increment.sock", router));
+//!
+//! // Once the server is listening:
+//! let mut client = increment_client::IncrementClient::connect_unix("/run/increment.sock")
+//!     .await
+//!     .unwrap();
+//! # }
+//! # fn main() {}
+//! ```
+
+use std::{
+    fs,
+    future::Future,
+    io,
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tokio::net::{UnixListener, UnixStream};
+use tonic::{
+    codegen::{futures_core::Stream, http::Uri, Service, StdError},
+    transport::{server::Router, Channel, Endpoint},
+};
+
+/// A Unix domain socket to serve a router on.
+///
+/// A socket file left over by a server which is no longer running is removed before binding,
+/// while an error is returned if a server is still listening on it or if the path isn't a
+/// socket. The socket file is removed when the server stops.
+#[derive(Debug, Clone)]
+pub struct UnixSocket {
+    path: PathBuf,
+    permissions: Option<u32>,
+}
+
+impl UnixSocket {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        UnixSocket {
+            path: path.as_ref().to_path_buf(),
+            permissions: None,
+        }
+    }
+
+    /// Set the permissions of the socket file, e.g. `0o660` to let the group connect.
+    /// By default they depend on the umask of the process.
+    ///
+    /// The socket is created in a private directory and only moved to its path once it has
+    /// these permissions, so no other user can connect to it in the meantime.
+    #[must_use]
+    pub fn permissions(mut self, mode: u32) -> Self {
+        self.permissions = Some(mode);
+        self
+    }
+
+    /// Serve `router` on the socket.
+    pub async fn serve(self, router: Router) -> Result<(), StdError> {
+        self.serve_with_shutdown(router, std::future::pending())
+            .await
+    }
+
+    /// Serve `router` on the socket until `signal` completes.
+    pub async fn serve_with_shutdown<F>(self, router: Router, signal: F) -> Result<(), StdError>
+    where
+        F: Future<Output = ()>,
+    {
+        remove_stale_socket(&self.path)?;
+        let listener = match self.permissions {
+            Some(mode) => bind_with_permissions(&self.path, mode)?,
+            None => UnixListener::bind(&self.path)?,
+        };
+        let _socket_file = SocketFile(self.path);
+        router
+            .serve_with_incoming_shutdown(Incoming(listener), signal)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Serve `router` on the Unix domain socket at `path`.
+///
+/// See [`UnixSocket`] for how existing socket files are handled.
+pub async fn serve<P: AsRef<Path>>(path: P, router: Router) -> Result<(), StdError> {
+    UnixSocket::new(path).serve(router).await
+}
+
+/// Remove the socket file at `path` if no server is listening on it anymore.
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and isn't a socket", path.display()),
+        ));
+    }
+    match std::os::unix::net::UnixStream::connect(path) {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("A server is already listening on {}", path.display()),
+        )),
+        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path),
+        Err(err) => Err(err),
+    }
+}
+
+/// Bind a listener on `path` whose socket file has the permissions `mode`. The socket is
+/// bound in a new directory next to `path` which only the current user can access, and
+/// renamed to `path` once its permissions are set.
+fn bind_with_permissions(path: &Path, mode: u32) -> io::Result<UnixListener> {
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} isn't a socket path", path.display()),
+        )
+    })?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.subsec_nanos());
+    let dir = PrivateDir(parent.join(format!(
+        ".{}.{}.{}",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        nanos
+    )));
+    // Fails if the directory already exists, so it can't have been prepared by another user.
+    fs::DirBuilder::new().mode(0o700).create(&dir.0)?;
+    let private_path = dir.0.join(file_name);
+    let listener = UnixListener::bind(&private_path)?;
+    fs::set_permissions(&private_path, fs::Permissions::from_mode(mode))?;
+    fs::rename(&private_path, path)?;
+    Ok(listener)
+}
+
+/// Removes the private directory a socket was bound in.
+struct PrivateDir(PathBuf);
+
+impl Drop for PrivateDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Removes the socket file when the server stops.
+struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// The connections accepted on a listener.
+struct Incoming(UnixListener);
+
+impl Stream for Incoming {
+    type Item = io::Result<UnixStream>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0
+            .poll_accept(cx)
+            .map(|accepted| Some(accepted.map(|(stream, _addr)| stream)))
+    }
+}
+
+/// Connects a [`Channel`] to the socket at a path.
+struct UnixConnector(PathBuf);
+
+impl Service<Uri> for UnixConnector {
+    type Response = UnixStream;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<UnixStream>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let path = self.0.clone();
+        Box::pin(async move { UnixStream::connect(path).await })
+    }
+}
+
+/// Connect to the server listening on the Unix domain socket at `path`. The returned channel
+/// can be used to create any generated client, and reconnects to the socket if the
+/// connection is lost.
+pub async fn connect<P: AsRef<Path>>(path: P) -> Result<Channel, tonic::transport::Error> {
+    // The URI is only used for the `:authority` of requests.
+    Endpoint::from_static("http://localhost")
+        .connect_with_connector(UnixConnector(path.as_ref().to_path_buf()))
+        .await
+}
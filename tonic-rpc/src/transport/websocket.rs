@@ -0,0 +1,692 @@
+//! Serve and call generated services over WebSocket connections, for clients behind proxies
+//! which can't hold HTTP/2 streams.
+//!
+//! [`WebSocket`] wraps a generated server to accept WebSocket connections, while `gRPC`
+//! requests are passed through untouched. Each call is a logical stream multiplexed on the
+//! connection, so all kinds of methods are supported and calls don't block each other: a
+//! call which doesn't keep up with its messages fails instead of holding up the connection.
+//! A [`WebSocketChannel`] connects to it, and can be used to create the generated client:
+//! the client has the same API as over `gRPC`.
+//!
+//! ```no_run
+//! # #[tonic_rpc::tonic_rpc(json)]
+//! # trait Increment {
+//! #     fn increment(arg: i32) -> i32;
+//! # }
+//! # struct State;
+//! # #[tonic::async_trait]
+//! # impl increment_server::Increment for State {
+//! #     async fn increment(
+//! #         &self,
+//! #         request: tonic::Request<i32>,
+//! #     ) -> Result<tonic::Response<i32>, tonic::Status> {
+//! #         Ok(tonic::Response::new(request.into_inner() + 1))
+//! #     }
+//! # }
+//! use tonic_rpc::transport::websocket::{WebSocket, WebSocketChannel};
+//!
+//! # async fn server() {
+//! tonic::transport::Server::builder()
+//!     // The WebSocket handshake uses HTTP/1.1.
+//!     .accept_http1(true)
+//!     .add_service(WebSocket::new(increment_server::IncrementServer::new(State)))
+//!     .serve("[::1]:8080".parse().unwrap())
+//!     .await
+//!     .unwrap();
+//! # }
+//!
+//! # async fn client() {
+//! // When served by a `tonic` server, connect to a path under the service's name.
+//! let channel = WebSocketChannel::connect("ws://[::1]:8080/Increment/ws")
+//!     .await
+//!     .unwrap();
+//! let mut client = increment_client::IncrementClient::new(channel);
+//! assert_eq!(33, client.increment(32).await.unwrap().into_inner());
+//! # }
+//! # fn main() {}
+//! ```
+//!
+//! # Protocol
+//! Every WebSocket message is a binary frame of a logical stream: the stream's id as a
+//! big-endian `u32`, a byte for the kind of frame, and its payload.
+//! - `0`, open: the client starts a call. The payload is the path of the method, e.g.
+//!   `/Increment/Increment`.
+//! - `1`, message: a request or response message, encoded with the service's codec.
+//! - `2`, end: the client has sent all of its requests.
+//! - `3`, status: the server has sent all of its responses. The payload is the `gRPC` code as
+//!   a big-endian `u32`, followed by the UTF-8 message of the status.
+//! - `4`, cancel: the client abandons the call.
+//!
+//! A unary call is then an open, a message and an end from the client, answered with a
+//! message and a status. Status details and metadata aren't sent.
+//!
+//! The messages of each stream are buffered up to a fixed number. Once a call's buffer is
+//! full, the call fails with `ResourceExhausted`: reading from the connection doesn't wait
+//! for it to catch up. A server accepts up to
+//! [`WebSocket::max_concurrent_streams`] calls at once on a connection.
+//!
+//! Only `ws://` URIs are supported by [`WebSocketChannel`]; use a proxy to terminate TLS.
+
+use std::{
+    collections::HashMap,
+    convert::{Infallible, TryInto},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    task::{ready, Context, Poll},
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_util::{Sink, SinkExt, StreamExt};
+use hyper::upgrade::Upgraded;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    sync::mpsc::{self, error::TrySendError, Receiver, Sender},
+    task::JoinHandle,
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        self, handshake::derive_accept_key, protocol::Role, protocol::WebSocketConfig, Message,
+    },
+    WebSocketStream,
+};
+use tonic::{
+    body::BoxBody,
+    codegen::{
+        futures_core::Stream,
+        http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri},
+        poll_fn, Body as HttpBody, BoxFuture, Service, StdError,
+    },
+    transport::{Body, NamedService},
+    Code, Status,
+};
+
+use crate::framing::{frame, full_body, is_grpc, Messages};
+
+/// The largest message accepted from the peer.
+const MAX_MESSAGE_SIZE: usize = 16 << 20;
+
+/// The number of messages buffered for each stream, and for the connection to write.
+const BUFFER_SIZE: usize = 32;
+
+/// The default limit on the number of calls in progress on a server connection.
+const DEFAULT_MAX_CONCURRENT_STREAMS: usize = 100;
+
+const OPEN: u8 = 0;
+const MESSAGE: u8 = 1;
+const END: u8 = 2;
+const STATUS: u8 = 3;
+const CANCEL: u8 = 4;
+
+/// A frame of the logical stream `stream`.
+fn encode(stream: u32, kind: u8, payload: &[u8]) -> Message {
+    let mut data = BytesMut::with_capacity(payload.len() + 5);
+    data.put_u32(stream);
+    data.put_u8(kind);
+    data.put_slice(payload);
+    Message::Binary(data.to_vec())
+}
+
+/// The stream, kind and payload of a frame.
+fn decode(mut data: Bytes) -> Option<(u32, u8, Bytes)> {
+    if data.len() < 5 {
+        return None;
+    }
+    let stream = data.get_u32();
+    let kind = data.get_u8();
+    Some((stream, kind, data))
+}
+
+fn encode_status(stream: u32, status: &Status) -> Message {
+    let mut payload = BytesMut::new();
+    payload.put_u32(status.code() as u32);
+    payload.put_slice(status.message().as_bytes());
+    encode(stream, STATUS, &payload)
+}
+
+fn decode_status(mut payload: Bytes) -> Status {
+    if payload.len() < 4 {
+        return Status::internal("Invalid status frame");
+    }
+    let code = Code::from_i32(payload.get_u32() as i32);
+    Status::new(code, String::from_utf8_lossy(&payload))
+}
+
+fn config() -> WebSocketConfig {
+    WebSocketConfig {
+        max_message_size: Some(MAX_MESSAGE_SIZE),
+        max_frame_size: Some(MAX_MESSAGE_SIZE),
+        ..Default::default()
+    }
+}
+
+/// Write the messages sent on `messages` until all senders are dropped, then close the
+/// connection.
+async fn write_messages<S>(mut sink: S, mut messages: Receiver<Message>)
+where
+    S: Sink<Message, Error = tungstenite::Error> + Unpin,
+{
+    while let Some(message) = messages.recv().await {
+        if sink.send(message).await.is_err() {
+            return;
+        }
+    }
+    let _ = sink.close().await;
+}
+
+/// The payload of the next binary message of the connection, or `None` once it's closed.
+/// Pings are answered by `tungstenite`.
+async fn next_binary<S>(stream: &mut S) -> Option<Bytes>
+where
+    S: futures_util::Stream<Item = Result<Message, tungstenite::Error>> + Unpin,
+{
+    loop {
+        match stream.next().await? {
+            Ok(Message::Binary(data)) => return Some(data.into()),
+            Ok(Message::Ping(_) | Message::Pong(_)) => continue,
+            Ok(Message::Text(_) | Message::Close(_) | Message::Frame(_)) | Err(_) => return None,
+        }
+    }
+}
+
+/// Serves WebSocket connections for the wrapped generated server.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone)]
+pub struct WebSocket<S> {
+    inner: S,
+    max_concurrent_streams: usize,
+}
+
+impl<S> WebSocket<S> {
+    pub fn new(inner: S) -> Self {
+        WebSocket {
+            inner,
+            max_concurrent_streams: DEFAULT_MAX_CONCURRENT_STREAMS,
+        }
+    }
+
+    /// Reject the calls opened while `limit` calls are in progress on the same connection
+    /// with `ResourceExhausted`, 100 by default.
+    pub fn max_concurrent_streams(mut self, limit: usize) -> Self {
+        self.max_concurrent_streams = limit;
+        self
+    }
+}
+
+impl<S: NamedService> NamedService for WebSocket<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S> Service<Request<Body>> for WebSocket<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        if is_grpc(&request) {
+            return Box::pin(self.inner.call(request));
+        }
+        let accept = match upgrade_key(&request) {
+            Some(key) => derive_accept_key(key.as_bytes()),
+            None => {
+                let response = Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(full_body(Bytes::from_static(
+                        b"Expected a WebSocket upgrade",
+                    )))
+                    .unwrap();
+                return Box::pin(async { Ok(response) });
+            }
+        };
+        let upgrade = hyper::upgrade::on(&mut request);
+        let inner = self.inner.clone();
+        let max_streams = self.max_concurrent_streams;
+        tokio::spawn(async move {
+            if let Ok(io) = upgrade.await {
+                serve_connection(inner, io, max_streams).await;
+            }
+        });
+        let response = Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(header::CONNECTION, "Upgrade")
+            .header(header::UPGRADE, "websocket")
+            .header(header::SEC_WEBSOCKET_ACCEPT, accept)
+            .body(full_body(Bytes::new()))
+            .unwrap();
+        Box::pin(async { Ok(response) })
+    }
+}
+
+/// The key of a WebSocket opening handshake, if `request` is one.
+fn upgrade_key<B>(request: &Request<B>) -> Option<&HeaderValue> {
+    let has_token = |name, token: &str| {
+        request.headers().get_all(name).iter().any(|value| {
+            value.to_str().is_ok_and(|value| {
+                value
+                    .split(',')
+                    .any(|item| item.trim().eq_ignore_ascii_case(token))
+            })
+        })
+    };
+    if request.method() == Method::GET
+        && has_token(header::CONNECTION, "upgrade")
+        && has_token(header::UPGRADE, "websocket")
+        && has_token(header::SEC_WEBSOCKET_VERSION, "13")
+    {
+        request.headers().get(header::SEC_WEBSOCKET_KEY)
+    } else {
+        None
+    }
+}
+
+/// A call in progress on a server connection.
+struct ServerStream {
+    requests: Option<Sender<Bytes>>,
+    task: JoinHandle<()>,
+}
+
+/// The request messages of a call, as a request body.
+struct RequestMessages(Receiver<Bytes>);
+
+impl Stream for RequestMessages {
+    type Item = Result<Bytes, Infallible>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0
+            .poll_recv(cx)
+            .map(|message| message.map(frame).map(Ok))
+    }
+}
+
+async fn serve_connection<S>(service: S, io: Upgraded, max_streams: usize)
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    // Unmasked frames from the client are rejected.
+    let io = WebSocketStream::from_raw_socket(io, Role::Server, Some(config())).await;
+    let (writer, mut reader) = io.split();
+    let (outgoing, messages) = mpsc::channel(BUFFER_SIZE);
+    let writer = tokio::spawn(write_messages(writer, messages));
+    let mut streams: HashMap<u32, ServerStream> = HashMap::new();
+
+    while let Some(data) = next_binary(&mut reader).await {
+        let (id, kind, payload) = match decode(data) {
+            Some(frame) => frame,
+            None => break,
+        };
+        match kind {
+            OPEN => {
+                streams.retain(|_, stream| !stream.task.is_finished());
+                let status = if streams.contains_key(&id) {
+                    Some(Status::already_exists("Stream is already open"))
+                } else if streams.len() >= max_streams {
+                    Some(Status::resource_exhausted("Too many concurrent streams"))
+                } else {
+                    None
+                };
+                if let Some(status) = status {
+                    let _ = outgoing.send(encode_status(id, &status)).await;
+                    continue;
+                }
+                let (requests, messages) = mpsc::channel(BUFFER_SIZE);
+                let path = String::from_utf8_lossy(&payload).into_owned();
+                let task = tokio::spawn(serve_stream(
+                    service.clone(),
+                    id,
+                    path,
+                    Body::wrap_stream(RequestMessages(messages)),
+                    outgoing.clone(),
+                ));
+                let requests = Some(requests);
+                streams.insert(id, ServerStream { requests, task });
+            }
+            MESSAGE => {
+                let requests = streams.get(&id).and_then(|s| s.requests.as_ref());
+                if let Some(Err(TrySendError::Full(_))) = requests.map(|r| r.try_send(payload)) {
+                    // The call fails rather than holding up the other calls.
+                    if let Some(stream) = streams.remove(&id) {
+                        stream.task.abort();
+                    }
+                    let status = Status::resource_exhausted("Too many buffered request messages");
+                    let _ = outgoing.send(encode_status(id, &status)).await;
+                }
+            }
+            END => {
+                if let Some(stream) = streams.get_mut(&id) {
+                    stream.requests = None;
+                }
+            }
+            CANCEL => {
+                if let Some(stream) = streams.remove(&id) {
+                    stream.task.abort();
+                }
+            }
+            _ => break,
+        }
+    }
+
+    for stream in streams.into_values() {
+        stream.task.abort();
+    }
+    drop(outgoing);
+    let _ = writer.await;
+}
+
+/// Serve the call `id` to `path`, sending its responses and status on `outgoing`.
+async fn serve_stream<S>(service: S, id: u32, path: String, body: Body, outgoing: Sender<Message>)
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible>,
+{
+    let status = match call(service, id, &path, body, &outgoing).await {
+        Ok(()) => Status::new(Code::Ok, ""),
+        Err(status) => status,
+    };
+    let _ = outgoing.send(encode_status(id, &status)).await;
+}
+
+async fn call<S>(
+    mut service: S,
+    id: u32,
+    path: &str,
+    body: Body,
+    outgoing: &Sender<Message>,
+) -> Result<(), Status>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible>,
+{
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(path)
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/grpc"),
+        )
+        .header(header::TE, HeaderValue::from_static("trailers"))
+        .body(body)
+        .map_err(|err| Status::invalid_argument(format!("Invalid method path {}", err)))?;
+    poll_fn(|cx| service.poll_ready(cx))
+        .await
+        .unwrap_or_else(|never| match never {});
+    let response = service
+        .call(request)
+        .await
+        .unwrap_or_else(|never| match never {});
+    if let Some(status) = Status::from_header_map(response.headers()) {
+        if status.code() != Code::Ok {
+            return Err(status);
+        }
+    }
+
+    let mut messages = Messages::new(response.into_body());
+    while let Some(message) = poll_fn(|cx| messages.poll_message(cx)).await {
+        let _ = outgoing.send(encode(id, MESSAGE, &message?)).await;
+    }
+    Ok(())
+}
+
+/// What the server sent on a logical stream.
+#[derive(Debug)]
+enum Event {
+    Message(Bytes),
+    Status(Status),
+}
+
+type Streams = Arc<Mutex<HashMap<u32, Sender<Event>>>>;
+
+/// A channel to a server served with [`WebSocket`], which can be used to create any
+/// generated client.
+///
+/// The channel doesn't reconnect if the connection is lost.
+#[derive(Debug, Clone)]
+pub struct WebSocketChannel {
+    outgoing: Sender<Message>,
+    streams: Streams,
+    next_stream: Arc<AtomicU32>,
+}
+
+impl WebSocketChannel {
+    /// Connect to the WebSocket endpoint at `dst`, e.g. `ws://[::1]:8080/Increment/ws`.
+    pub async fn connect<D>(dst: D) -> Result<Self, StdError>
+    where
+        D: TryInto<Uri>,
+        D::Error: Into<StdError>,
+    {
+        let uri: Uri = dst.try_into().map_err(Into::into)?;
+        if !matches!(uri.scheme_str(), Some("ws") | Some("http")) {
+            return Err("Only ws:// URIs are supported".into());
+        }
+        let authority = uri.authority().ok_or("Missing host")?.clone();
+        let host = authority
+            .host()
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let io = TcpStream::connect((host, authority.port_u16().unwrap_or(80))).await?;
+        Self::with_io(uri, io).await
+    }
+
+    /// Perform the opening handshake with `uri` on `io`, an established connection.
+    pub async fn with_io<IO>(uri: Uri, io: IO) -> Result<Self, StdError>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (io, _response) =
+            tokio_tungstenite::client_async_with_config(uri, io, Some(config())).await?;
+
+        let (writer, reader) = io.split();
+        let (outgoing, messages) = mpsc::channel(BUFFER_SIZE);
+        tokio::spawn(write_messages(writer, messages));
+        let streams = Streams::default();
+        tokio::spawn(read_events(reader, streams.clone(), outgoing.clone()));
+        Ok(WebSocketChannel {
+            outgoing,
+            streams,
+            next_stream: Arc::new(AtomicU32::new(0)),
+        })
+    }
+}
+
+/// Dispatch the frames sent by the server to their streams, until the connection is closed.
+///
+/// The message buffer of each stream keeps a slot for its status, so a call which doesn't
+/// read its responses fast enough fails with `ResourceExhausted` and is cancelled.
+async fn read_events<S>(mut reader: S, streams: Streams, outgoing: Sender<Message>)
+where
+    S: futures_util::Stream<Item = Result<Message, tungstenite::Error>> + Unpin,
+{
+    while let Some(data) = next_binary(&mut reader).await {
+        let (id, kind, payload) = match decode(data) {
+            Some(frame) => frame,
+            None => break,
+        };
+        let overflow = {
+            let mut streams = streams.lock().unwrap();
+            match kind {
+                MESSAGE => match streams.get(&id) {
+                    Some(events) if events.capacity() > 1 => {
+                        let _ = events.try_send(Event::Message(payload));
+                        false
+                    }
+                    Some(_) => {
+                        let status =
+                            Status::resource_exhausted("Too many buffered response messages");
+                        let events = streams.remove(&id).unwrap();
+                        let _ = events.try_send(Event::Status(status));
+                        true
+                    }
+                    None => false,
+                },
+                STATUS => {
+                    if let Some(events) = streams.remove(&id) {
+                        let _ = events.try_send(Event::Status(decode_status(payload)));
+                    }
+                    false
+                }
+                _ => break,
+            }
+        };
+        if overflow {
+            let _ = outgoing.send(encode(id, CANCEL, &[])).await;
+        }
+    }
+    // The calls in progress fail.
+    streams.lock().unwrap().clear();
+}
+
+impl Service<Request<BoxBody>> for WebSocketChannel {
+    type Response = Response<ResponseBody>;
+    type Error = Status;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.outgoing.is_closed() {
+            Poll::Ready(Err(connection_closed()))
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn call(&mut self, request: Request<BoxBody>) -> Self::Future {
+        let id = self.next_stream.fetch_add(1, Ordering::Relaxed);
+        // With a slot for the status.
+        let (events_tx, events) = mpsc::channel(BUFFER_SIZE + 1);
+        self.streams.lock().unwrap().insert(id, events_tx);
+        let outgoing = self.outgoing.clone();
+        let streams = self.streams.clone();
+        Box::pin(async move {
+            let open = encode(id, OPEN, request.uri().path().as_bytes());
+            if outgoing.send(open).await.is_err() {
+                streams.lock().unwrap().remove(&id);
+                return Err(connection_closed());
+            }
+            let requests = tokio::spawn(send_requests(
+                id,
+                Messages::new(request.into_body()),
+                outgoing.clone(),
+            ));
+            let body = ResponseBody {
+                id,
+                events,
+                done: false,
+                ok: false,
+                requests,
+                outgoing,
+                streams,
+            };
+            Ok(Response::builder()
+                .header(header::CONTENT_TYPE, "application/grpc")
+                .body(body)
+                .unwrap())
+        })
+    }
+}
+
+fn connection_closed() -> Status {
+    Status::unavailable("WebSocket connection closed")
+}
+
+/// Send the request messages of the call `id`.
+async fn send_requests(id: u32, mut messages: Messages, outgoing: Sender<Message>) {
+    while let Some(message) = poll_fn(|cx| messages.poll_message(cx)).await {
+        let frame = match message {
+            Ok(message) => encode(id, MESSAGE, &message),
+            Err(_) => {
+                let _ = outgoing.send(encode(id, CANCEL, &[])).await;
+                return;
+            }
+        };
+        if outgoing.send(frame).await.is_err() {
+            return;
+        }
+    }
+    let _ = outgoing.send(encode(id, END, &[])).await;
+}
+
+/// The body of the response to a call made through a [`WebSocketChannel`].
+///
+/// Dropping it before the call completes cancels the call.
+#[derive(Debug)]
+pub struct ResponseBody {
+    id: u32,
+    events: Receiver<Event>,
+    done: bool,
+    ok: bool,
+    requests: JoinHandle<()>,
+    outgoing: Sender<Message>,
+    streams: Streams,
+}
+
+impl HttpBody for ResponseBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        let event = ready!(self.events.poll_recv(cx));
+        Poll::Ready(match event {
+            Some(Event::Message(message)) => Some(Ok(frame(message))),
+            Some(Event::Status(status)) => {
+                self.done = true;
+                self.ok = status.code() == Code::Ok;
+                // A failed call ends with its status as an error, which tonic returns as is.
+                (!self.ok).then_some(Err(status))
+            }
+            None => {
+                self.done = true;
+                Some(Err(connection_closed()))
+            }
+        })
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let mut trailers = HeaderMap::new();
+        if self.ok {
+            trailers.insert("grpc-status", HeaderValue::from_static("0"));
+        }
+        Poll::Ready(Ok(Some(trailers)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done
+    }
+}
+
+impl Drop for ResponseBody {
+    fn drop(&mut self) {
+        self.requests.abort();
+        if !self.done {
+            self.streams.lock().unwrap().remove(&self.id);
+            let cancel = encode(self.id, CANCEL, &[]);
+            if let Err(TrySendError::Full(cancel)) = self.outgoing.try_send(cancel) {
+                let outgoing = self.outgoing.clone();
+                tokio::spawn(async move { outgoing.send(cancel).await });
+            }
+        }
+    }
+}
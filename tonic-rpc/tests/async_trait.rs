@@ -0,0 +1,98 @@
+#![cfg(feature = "json")]
+
+use tokio_stream::StreamExt;
+use tonic_rpc::tonic_rpc;
+
+mod util;
+
+#[tonic_rpc(json)]
+trait Counter {
+    fn add(x: i32, y: i32) -> i32;
+    fn greet(name: &str) -> String;
+    fn reset();
+
+    #[server_streaming]
+    fn count(to: u32) -> u32;
+
+    #[client_streaming]
+    fn sum(x: i32) -> i32;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl counter_server::Counter for State {
+    type CountStream = tokio_stream::Iter<std::vec::IntoIter<Result<u32, tonic::Status>>>;
+
+    async fn add(
+        &self,
+        request: tonic::Request<(i32, i32)>,
+    ) -> Result<tonic::Response<i32>, tonic::Status> {
+        let (x, y) = request.into_inner();
+        Ok(tonic::Response::new(x + y))
+    }
+
+    async fn greet(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<String>, tonic::Status> {
+        Ok(tonic::Response::new(format!(
+            "hello {}",
+            request.into_inner()
+        )))
+    }
+
+    async fn reset(
+        &self,
+        _request: tonic::Request<()>,
+    ) -> Result<tonic::Response<()>, tonic::Status> {
+        Ok(tonic::Response::new(()))
+    }
+
+    async fn count(
+        &self,
+        request: tonic::Request<u32>,
+    ) -> Result<tonic::Response<Self::CountStream>, tonic::Status> {
+        let values: Vec<_> = (1..=request.into_inner()).map(Ok).collect();
+        Ok(tonic::Response::new(tokio_stream::iter(values)))
+    }
+
+    async fn sum(
+        &self,
+        request: tonic::Request<tonic::Streaming<i32>>,
+    ) -> Result<tonic::Response<i32>, tonic::Status> {
+        let mut stream = request.into_inner();
+        let mut sum = 0;
+        while let Some(x) = stream.message().await? {
+            sum += x;
+        }
+        Ok(tonic::Response::new(sum))
+    }
+}
+
+/// Business logic written once for local and remote counters.
+async fn exercise<C: counter_async::CounterAsync>(counter: &C) -> (i32, String, Vec<u32>) {
+    counter.reset().await.unwrap();
+    let sum = counter.add(1, 2).await.unwrap();
+    let greeting = counter.greet("world").await.unwrap();
+    let counts = counter
+        .count(3)
+        .await
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    (sum, greeting, counts)
+}
+
+#[tokio::test]
+async fn test_local_and_remote() {
+    let expected = (3, "hello world".to_string(), vec![1, 2, 3]);
+    assert_eq!(expected, exercise(&State).await);
+
+    let addr = util::run_server(counter_server::CounterServer::new(State)).await;
+    let client = counter_client::CounterClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+    assert_eq!(expected, exercise(&client).await);
+}
@@ -0,0 +1,335 @@
+#![cfg(feature = "avro")]
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tonic::Code;
+use tonic_rpc::{
+    avro::{
+        self, AvroChannel, AvroSchema, AvroServer, MethodSchemas, Schema, ServiceSchemas,
+        RESPONSE_FINGERPRINT_HEADER,
+    },
+    tonic_rpc,
+};
+
+mod util;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, AvroSchema)]
+pub struct Event {
+    id: i64,
+    source: String,
+    tags: Vec<String>,
+    counts: HashMap<String, i32>,
+    parent: Option<i64>,
+    level: Level,
+    payload: Payload,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, AvroSchema)]
+pub enum Level {
+    Info,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, AvroSchema)]
+pub enum Payload {
+    Empty,
+    Text(String),
+    Point(f32, f32),
+    Bounds { min: f64, max: f64 },
+}
+
+#[tonic_rpc(avro, package = "lake.v1")]
+trait Ingest {
+    fn ingest(event: Event) -> u64;
+
+    fn relabel(event: Event, source: String, level: Level) -> Event;
+
+    fn clear() -> ();
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl ingest_server::Ingest for State {
+    async fn ingest(
+        &self,
+        request: tonic::Request<Event>,
+    ) -> Result<tonic::Response<u64>, tonic::Status> {
+        Ok(tonic::Response::new(request.into_inner().tags.len() as u64))
+    }
+
+    async fn relabel(
+        &self,
+        request: tonic::Request<(Event, String, Level)>,
+    ) -> Result<tonic::Response<Event>, tonic::Status> {
+        let (event, source, level) = request.into_inner();
+        Ok(tonic::Response::new(Event {
+            source,
+            level,
+            ..event
+        }))
+    }
+
+    async fn clear(
+        &self,
+        _request: tonic::Request<()>,
+    ) -> Result<tonic::Response<()>, tonic::Status> {
+        Ok(tonic::Response::new(()))
+    }
+}
+
+fn event() -> Event {
+    Event {
+        id: -3,
+        source: "sensor".to_string(),
+        tags: vec!["a".to_string(), "b".to_string()],
+        counts: vec![("x".to_string(), 64)].into_iter().collect(),
+        parent: Some(1),
+        level: Level::Error,
+        payload: Payload::Bounds { min: 0.5, max: 2.0 },
+    }
+}
+
+fn encode<T: Serialize + AvroSchema + ?Sized>(item: &T) -> Vec<u8> {
+    avro::to_vec(item).unwrap()
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, AvroSchema)]
+#[serde(rename = "Reading", rename_all = "camelCase")]
+pub struct SensorReading {
+    sensor_id: u32,
+    #[serde(rename = "value")]
+    reading: f64,
+    #[serde(skip)]
+    cached: bool,
+    unit: Unit,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, AvroSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Unit {
+    DegreesCelsius,
+    #[serde(rename = "PCT")]
+    Percent,
+}
+
+/// Claims to be an int, but serializes as a string.
+#[derive(Serialize)]
+struct Mislabeled(String);
+
+impl AvroSchema for Mislabeled {
+    fn schema() -> Schema {
+        Schema::Int
+    }
+}
+
+#[test]
+fn test_fingerprints() {
+    // From the test data of the Avro specification.
+    assert_eq!(7195948357588979594, Schema::Null.fingerprint() as i64);
+    assert_eq!(8247732601305521295, Schema::Int.fingerprint() as i64);
+}
+
+#[test]
+fn test_derived_schemas() {
+    assert_eq!(
+        r#"{"name":"Level","type":"enum","symbols":["Info","Error"]}"#,
+        Level::schema().canonical_form()
+    );
+    assert_eq!(
+        concat!(
+            r#"[{"name":"PayloadEmpty","type":"record","fields":[]},"#,
+            r#"{"name":"PayloadText","type":"record","fields":[{"name":"field0","type":"string"}]},"#,
+            r#"{"name":"PayloadPoint","type":"record","fields":[{"name":"field0","type":"float"},{"name":"field1","type":"float"}]},"#,
+            r#"{"name":"PayloadBounds","type":"record","fields":[{"name":"min","type":"double"},{"name":"max","type":"double"}]}]"#
+        ),
+        Payload::schema().canonical_form()
+    );
+
+    let schemas = ingest_avro_schemas();
+    let relabel = schemas.get("/lake.v1.Ingest/Relabel").unwrap();
+    assert!(relabel.request().canonical_form().starts_with(concat!(
+        r#"{"name":"Tuple3","type":"record","fields":["#,
+        r#"{"name":"field0","type":{"name":"Event","type":"record","fields":["#,
+        r#"{"name":"id","type":"long"},{"name":"source","type":"string"},"#,
+        r#"{"name":"tags","type":{"type":"array","items":"string"}},"#,
+        r#"{"name":"counts","type":{"type":"map","values":"int"}},"#,
+        r#"{"name":"parent","type":["null","long"]},"#,
+        r#"{"name":"level","type":{"name":"Level","type":"enum","symbols":["Info","Error"]}},"#,
+    )));
+    assert_eq!(&Event::schema(), relabel.response());
+    assert_eq!(
+        &Schema::Null,
+        schemas.get("/lake.v1.Ingest/Clear").unwrap().request()
+    );
+}
+
+#[test]
+fn test_serde_attributes() {
+    assert_eq!(
+        concat!(
+            r#"{"name":"Reading","type":"record","fields":["#,
+            r#"{"name":"sensorId","type":"long"},{"name":"value","type":"double"},"#,
+            r#"{"name":"unit","type":{"name":"Unit","type":"enum","symbols":["DEGREES_CELSIUS","PCT"]}}]}"#
+        ),
+        SensorReading::schema().canonical_form()
+    );
+
+    let reading = SensorReading {
+        sensor_id: 7,
+        reading: 0.5,
+        cached: false,
+        unit: Unit::Percent,
+    };
+    let bytes = encode(&reading);
+    assert_eq!(vec![0x0e, 0, 0, 0, 0, 0, 0, 0xe0, 0x3f, 0x02], bytes);
+    assert_eq!(reading, avro::from_slice(&bytes).unwrap());
+}
+
+#[test]
+fn test_binary_encoding() {
+    assert_eq!(vec![0x05], encode(&-3_i64));
+    assert_eq!(vec![0x80, 0x01], encode(&64_i32));
+    assert_eq!(vec![0x06, b'a', b'b', b'c'], encode("abc"));
+    assert_eq!(vec![0x00], encode(&None::<i32>));
+    assert_eq!(vec![0x02, 0x02], encode(&Some(1)));
+    assert_eq!(vec![0x04, 0x02, 0x04, 0x00], encode(&vec![1, 2]));
+    assert_eq!(vec![0x00], encode(&Vec::<i32>::new()));
+    assert_eq!(vec![0x02], encode(&Level::Error));
+    assert_eq!(
+        vec![0x02, 0x06, b'h', b'i', b'!'],
+        encode(&Payload::Text("hi!".to_string()))
+    );
+    assert_eq!(event(), avro::from_slice(&encode(&event())).unwrap());
+}
+
+#[test]
+fn test_schema_mismatch() {
+    let err = avro::to_vec(&Mislabeled("1".to_string())).unwrap_err();
+    assert_eq!(
+        "Expected a value of the Avro schema int, found a string",
+        err.to_string()
+    );
+    assert!(avro::from_slice::<i32>(&[0x80]).is_err());
+    assert!(avro::from_slice::<i32>(&[0x02, 0x00]).is_err());
+}
+
+#[tokio::test]
+async fn test_avro_codec() {
+    let schemas = ingest_avro_schemas();
+    let addr = util::run_server(AvroServer::new(
+        ingest_server::IngestServer::new(State),
+        schemas.clone(),
+    ))
+    .await;
+    let channel = tonic::transport::Endpoint::new(addr)
+        .unwrap()
+        .connect()
+        .await
+        .expect("Failed to connect");
+    let mut client = ingest_client::IngestClient::new(AvroChannel::new(channel, schemas.clone()));
+
+    let response = client
+        .ingest(event())
+        .await
+        .expect("Failed to send request");
+    let ingest = schemas.get("/lake.v1.Ingest/Ingest").unwrap();
+    assert_eq!(
+        format!("{:016x}", ingest.response_fingerprint()),
+        response
+            .metadata()
+            .get(RESPONSE_FINGERPRINT_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+    );
+    assert_eq!(2, response.into_inner());
+
+    let relabeled = client
+        .relabel((event(), "gateway".to_string(), Level::Info))
+        .await
+        .expect("Failed to send request")
+        .into_inner();
+    assert_eq!(
+        Event {
+            source: "gateway".to_string(),
+            level: Level::Info,
+            ..event()
+        },
+        relabeled
+    );
+
+    client.clear(()).await.expect("Failed to send request");
+}
+
+#[tokio::test]
+async fn test_fingerprint_mismatch() {
+    let addr = util::run_server(AvroServer::new(
+        ingest_server::IngestServer::new(State),
+        ingest_avro_schemas(),
+    ))
+    .await;
+    let channel = tonic::transport::Endpoint::new(addr)
+        .unwrap()
+        .connect()
+        .await
+        .expect("Failed to connect");
+    // A client built against an older version of `Event`.
+    let schemas = ServiceSchemas::new(vec![MethodSchemas::new(
+        "/lake.v1.Ingest/Ingest",
+        Schema::Record {
+            name: "Event".to_string(),
+            fields: vec![("id".to_string(), Schema::Long)],
+        },
+        Schema::Long,
+    )]);
+    let mut client = ingest_client::IngestClient::new(AvroChannel::new(channel, schemas));
+
+    let status = client.ingest(event()).await.unwrap_err();
+    assert_eq!(Code::FailedPrecondition, status.code());
+}
+
+/// A client built against an older version of the `Ingest` service.
+mod old {
+    use serde::{Deserialize, Serialize};
+    use tonic_rpc::{avro::AvroSchema, tonic_rpc};
+
+    #[derive(Debug, Serialize, Deserialize, AvroSchema)]
+    pub struct Event {
+        pub id: i64,
+    }
+
+    #[tonic_rpc(avro, package = "lake.v1")]
+    trait Ingest {
+        fn ingest(event: Event) -> u64;
+    }
+}
+
+#[tokio::test]
+async fn test_missing_fingerprint() {
+    let addr = util::run_server(AvroServer::new(
+        ingest_server::IngestServer::new(State),
+        ingest_avro_schemas(),
+    ))
+    .await;
+    let mut client = ingest_client::IngestClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    let status = client.ingest(event()).await.unwrap_err();
+    assert_eq!(Code::FailedPrecondition, status.code());
+}
+
+#[tokio::test]
+async fn test_codec_fingerprint_mismatch() {
+    // Without an `AvroServer`, the codec still rejects messages written with another schema.
+    let addr = util::run_server(ingest_server::IngestServer::new(State)).await;
+    let mut client = old::ingest_client::IngestClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    let status = client.ingest(old::Event { id: 1 }).await.unwrap_err();
+    assert_eq!(Code::FailedPrecondition, status.code());
+    assert!(status.message().contains("fingerprint"));
+}
@@ -0,0 +1,126 @@
+#![cfg(feature = "json")]
+
+use serde::{Deserialize, Serialize};
+use tonic_rpc::tonic_rpc;
+
+mod util;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[tonic_rpc(json)]
+trait Dictionary {
+    fn lookup(key: &str) -> String;
+    fn total(values: &[u32], scale: u32) -> u64;
+    fn shift(point: &Point, by: &Point) -> Point;
+    fn owned(key: String) -> usize;
+
+    #[client_streaming]
+    fn concat(part: &str) -> String;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl dictionary_server::Dictionary for State {
+    async fn lookup(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<String>, tonic::Status> {
+        Ok(tonic::Response::new(request.into_inner().to_uppercase()))
+    }
+
+    async fn total(
+        &self,
+        request: tonic::Request<(Vec<u32>, u32)>,
+    ) -> Result<tonic::Response<u64>, tonic::Status> {
+        let (values, scale) = request.into_inner();
+        let sum: u64 = values.iter().map(|value| u64::from(*value)).sum();
+        Ok(tonic::Response::new(sum * u64::from(scale)))
+    }
+
+    async fn shift(
+        &self,
+        request: tonic::Request<(Point, Point)>,
+    ) -> Result<tonic::Response<Point>, tonic::Status> {
+        let (point, by) = request.into_inner();
+        Ok(tonic::Response::new(Point {
+            x: point.x + by.x,
+            y: point.y + by.y,
+        }))
+    }
+
+    async fn owned(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<usize>, tonic::Status> {
+        Ok(tonic::Response::new(request.into_inner().len()))
+    }
+
+    async fn concat(
+        &self,
+        request: tonic::Request<tonic::Streaming<String>>,
+    ) -> Result<tonic::Response<String>, tonic::Status> {
+        let mut stream = request.into_inner();
+        let mut result = String::new();
+        while let Some(part) = stream.message().await? {
+            result.push_str(&part);
+        }
+        Ok(tonic::Response::new(result))
+    }
+}
+
+#[tokio::test]
+async fn test_borrowed_args() {
+    let addr = util::run_server(dictionary_server::DictionaryServer::new(State)).await;
+    let mut client = dictionary_client::DictionaryClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    let key = String::from("hello");
+    let response = client
+        .lookup(key.as_str())
+        .await
+        .expect("Failed to send request");
+    assert_eq!("HELLO", response.into_inner());
+
+    let values = vec![1, 2, 3];
+    let response = client
+        .total((values.as_slice(), 10))
+        .await
+        .expect("Failed to send request");
+    assert_eq!(60, response.into_inner());
+
+    let point = Point { x: 1, y: 2 };
+    let mut request = tonic::Request::new((&point, &Point { x: 10, y: 20 }));
+    request
+        .metadata_mut()
+        .insert("x-request-id", "42".parse().unwrap());
+    let response = client.shift(request).await.expect("Failed to send request");
+    assert_eq!(Point { x: 11, y: 22 }, response.into_inner());
+
+    let response = client
+        .owned(key.clone())
+        .await
+        .expect("Failed to send request");
+    assert_eq!(5, response.into_inner());
+}
+
+#[tokio::test]
+async fn test_borrowed_streaming_args() {
+    let addr = util::run_server(dictionary_server::DictionaryServer::new(State)).await;
+    let mut client = dictionary_client::DictionaryClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    // Streamed requests are sent as owned values.
+    let parts = vec!["ab".to_string(), "cd".to_string()];
+    let response = client
+        .concat(tokio_stream::iter(parts))
+        .await
+        .expect("Failed to send request");
+    assert_eq!("abcd", response.into_inner());
+}
@@ -0,0 +1,125 @@
+#![cfg(feature = "bson")]
+
+use bson::{doc, Document};
+use serde::{Deserialize, Serialize};
+use tonic_rpc::{
+    codec::{BsonSerdeCodec, SerdeCodec},
+    tonic_rpc,
+};
+
+mod util;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    name: String,
+    visits: i64,
+    tags: Vec<String>,
+}
+
+#[tonic_rpc(bson)]
+trait Profiles {
+    fn visit(profile: Profile) -> Profile;
+
+    fn tag(profile: Profile, tag: String) -> Profile;
+
+    fn count(profile: Profile) -> i64;
+
+    fn forget(profile: Profile) -> ();
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl profiles_server::Profiles for State {
+    async fn visit(
+        &self,
+        request: tonic::Request<Profile>,
+    ) -> Result<tonic::Response<Profile>, tonic::Status> {
+        let mut profile = request.into_inner();
+        profile.visits += 1;
+        Ok(tonic::Response::new(profile))
+    }
+
+    async fn tag(
+        &self,
+        request: tonic::Request<(Profile, String)>,
+    ) -> Result<tonic::Response<Profile>, tonic::Status> {
+        let (mut profile, tag) = request.into_inner();
+        profile.tags.push(tag);
+        Ok(tonic::Response::new(profile))
+    }
+
+    async fn count(
+        &self,
+        request: tonic::Request<Profile>,
+    ) -> Result<tonic::Response<i64>, tonic::Status> {
+        Ok(tonic::Response::new(request.into_inner().visits))
+    }
+
+    async fn forget(
+        &self,
+        _request: tonic::Request<Profile>,
+    ) -> Result<tonic::Response<()>, tonic::Status> {
+        Ok(tonic::Response::new(()))
+    }
+}
+
+fn profile() -> Profile {
+    Profile {
+        name: "alice".to_string(),
+        visits: 1,
+        tags: vec![],
+    }
+}
+
+fn encode<T: Serialize>(item: T) -> Document {
+    let mut bytes = Vec::new();
+    BsonSerdeCodec.write(item, &mut bytes).unwrap();
+    Document::from_reader(bytes.as_slice()).unwrap()
+}
+
+#[test]
+fn test_documents_are_not_wrapped() {
+    assert_eq!(
+        doc! { "name": "alice", "visits": 1_i64, "tags": [] },
+        encode(profile())
+    );
+}
+
+#[test]
+fn test_other_values_are_wrapped() {
+    assert_eq!(doc! { "$value": 3_i64 }, encode(3_i64));
+    assert_eq!(doc! { "$value": ["a", 1_i32] }, encode(("a", 1_i32)));
+    assert_eq!(doc! { "$value": null }, encode(()));
+}
+
+#[tokio::test]
+async fn test_bson_codec() {
+    let addr = util::run_server(profiles_server::ProfilesServer::new(State)).await;
+    let mut client = profiles_client::ProfilesClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    let visited = client
+        .visit(profile())
+        .await
+        .expect("Failed to send request")
+        .into_inner();
+    assert_eq!(2, visited.visits);
+
+    let tagged = client
+        .tag((visited, "admin".to_string()))
+        .await
+        .expect("Failed to send request")
+        .into_inner();
+    assert_eq!(vec!["admin".to_string()], tagged.tags);
+
+    let count = client
+        .count(tagged.clone())
+        .await
+        .expect("Failed to send request")
+        .into_inner();
+    assert_eq!(2, count);
+
+    client.forget(tagged).await.expect("Failed to send request");
+}
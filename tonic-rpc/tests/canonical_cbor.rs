@@ -0,0 +1,94 @@
+#![cfg(feature = "cbor")]
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tonic_rpc::{
+    codec::{CanonicalCborSerdeCodec, SerdeCodec},
+    tonic_rpc,
+};
+
+mod util;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    user: String,
+    counts: HashMap<String, u64>,
+    score: f64,
+}
+
+#[tonic_rpc(cbor_canonical)]
+trait Audit {
+    fn record(record: AuditRecord) -> Vec<u8>;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl audit_server::Audit for State {
+    async fn record(
+        &self,
+        request: tonic::Request<AuditRecord>,
+    ) -> Result<tonic::Response<Vec<u8>>, tonic::Status> {
+        let mut bytes = Vec::new();
+        CanonicalCborSerdeCodec.write(request.into_inner(), &mut bytes)?;
+        Ok(tonic::Response::new(bytes))
+    }
+}
+
+fn encode<T: Serialize>(item: T) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    CanonicalCborSerdeCodec.write(item, &mut bytes).unwrap();
+    bytes
+}
+
+fn record(keys: impl Iterator<Item = u64>) -> AuditRecord {
+    AuditRecord {
+        user: "alice".to_string(),
+        counts: keys.map(|i| (format!("key{}", i), i)).collect(),
+        score: 1.5,
+    }
+}
+
+#[test]
+fn test_sorted_map_keys() {
+    let map: HashMap<&str, u8> = vec![("b", 1), ("aa", 3), ("a", 2)].into_iter().collect();
+    // Keys are sorted by their encoded bytes, so shorter strings come first.
+    assert_eq!(
+        vec![0xa3, 0x61, b'a', 0x02, 0x61, b'b', 0x01, 0x62, b'a', b'a', 0x03],
+        encode(map)
+    );
+}
+
+#[test]
+fn test_shortest_forms() {
+    assert_eq!(vec![0x17], encode(23_u64));
+    assert_eq!(vec![0x18, 0x18], encode(24_i64));
+    assert_eq!(vec![0x19, 0x01, 0xf4], encode(500_u64));
+    assert_eq!(vec![0x20], encode(-1_i64));
+    assert_eq!(vec![0xf9, 0x3e, 0x00], encode(1.5_f64));
+}
+
+#[test]
+fn test_stable_bytes() {
+    let forwards = encode(record(0..100));
+    let backwards = encode(record((0..100).rev()));
+    assert_eq!(forwards, backwards);
+    // Struct fields are sorted like map keys: "user", then "score", then "counts".
+    assert_eq!(&[0xa3, 0x64, b'u', b's', b'e', b'r'], &forwards[..6]);
+    assert_eq!(&[0x65, b's', b'c', b'o', b'r', b'e'], &forwards[12..18]);
+}
+
+#[tokio::test]
+async fn test_canonical_cbor_codec() {
+    let addr = util::run_server(audit_server::AuditServer::new(State)).await;
+    let mut client = audit_client::AuditClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    let response = client
+        .record(record(0..20))
+        .await
+        .expect("Failed to send request");
+    assert_eq!(encode(record((0..20).rev())), response.into_inner());
+}
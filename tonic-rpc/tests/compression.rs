@@ -0,0 +1,167 @@
+#![cfg(all(
+    feature = "bincode",
+    feature = "zstd",
+    feature = "lz4",
+    feature = "snappy"
+))]
+
+use tonic_rpc::{
+    codec::{
+        BincodeSerdeCodec, Compressed, Lz4, SerdeCodec, Snappy, Zstd, ZstdDictionary,
+        ZstdWithDictionary, ZstdWithLevel,
+    },
+    tonic_rpc,
+};
+
+mod util;
+
+pub struct Dict;
+
+impl ZstdDictionary for Dict {
+    fn dictionary() -> &'static [u8] {
+        b"the quick brown fox jumps over the lazy dog"
+    }
+}
+
+#[tonic_rpc(bincode, compress = "zstd")]
+trait Echo {
+    fn zstd(words: Vec<String>) -> Vec<String>;
+
+    #[rpc(compress = "lz4")]
+    fn lz4(words: Vec<String>) -> Vec<String>;
+
+    #[rpc(compress = "snappy")]
+    fn snappy(words: Vec<String>) -> Vec<String>;
+
+    #[rpc(compress = "::tonic_rpc::codec::ZstdWithDictionary<crate::Dict>")]
+    fn dictionary(words: Vec<String>) -> Vec<String>;
+
+    #[rpc(compress = "none")]
+    fn uncompressed(words: Vec<String>) -> Vec<String>;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl echo_server::Echo for State {
+    async fn zstd(
+        &self,
+        request: tonic::Request<Vec<String>>,
+    ) -> Result<tonic::Response<Vec<String>>, tonic::Status> {
+        Ok(tonic::Response::new(request.into_inner()))
+    }
+
+    async fn lz4(
+        &self,
+        request: tonic::Request<Vec<String>>,
+    ) -> Result<tonic::Response<Vec<String>>, tonic::Status> {
+        Ok(tonic::Response::new(request.into_inner()))
+    }
+
+    async fn snappy(
+        &self,
+        request: tonic::Request<Vec<String>>,
+    ) -> Result<tonic::Response<Vec<String>>, tonic::Status> {
+        Ok(tonic::Response::new(request.into_inner()))
+    }
+
+    async fn dictionary(
+        &self,
+        request: tonic::Request<Vec<String>>,
+    ) -> Result<tonic::Response<Vec<String>>, tonic::Status> {
+        Ok(tonic::Response::new(request.into_inner()))
+    }
+
+    async fn uncompressed(
+        &self,
+        request: tonic::Request<Vec<String>>,
+    ) -> Result<tonic::Response<Vec<String>>, tonic::Status> {
+        Ok(tonic::Response::new(request.into_inner()))
+    }
+}
+
+fn words() -> Vec<String> {
+    vec!["the quick brown fox".to_string(); 50]
+}
+
+fn encode<C: SerdeCodec>(codec: C) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    codec.write(words(), &mut bytes).unwrap();
+    bytes
+}
+
+fn round_trip<C: SerdeCodec + Copy>(codec: C) {
+    let bytes = encode(codec);
+    assert!(bytes.len() < encode(BincodeSerdeCodec).len() / 4);
+    assert_eq!(
+        words(),
+        codec.read::<Vec<String>, _>(bytes.as_slice()).unwrap()
+    );
+}
+
+#[test]
+fn test_compressed_round_trip() {
+    round_trip(Compressed::new(BincodeSerdeCodec, Zstd));
+    round_trip(Compressed::new(BincodeSerdeCodec, ZstdWithLevel::new(19)));
+    round_trip(Compressed::new(BincodeSerdeCodec, Lz4));
+    round_trip(Compressed::new(BincodeSerdeCodec, Snappy));
+    round_trip(Compressed::new(
+        BincodeSerdeCodec,
+        ZstdWithDictionary::<Dict>::default(),
+    ));
+}
+
+#[test]
+fn test_dictionary_is_required() {
+    let bytes = encode(Compressed::new(
+        BincodeSerdeCodec,
+        ZstdWithDictionary::<Dict>::default(),
+    ));
+    assert!(Compressed::new(BincodeSerdeCodec, Zstd)
+        .read::<Vec<String>, _>(bytes.as_slice())
+        .is_err());
+}
+
+#[test]
+fn test_decompressed_limit() {
+    // A megabyte of zeros compresses to a few bytes with every algorithm.
+    let zeros = vec![0_u8; 1024 * 1024];
+    fn check<A: tonic_rpc::codec::Compression + Copy>(compression: A, zeros: &[u8]) {
+        let mut bytes = Vec::new();
+        Compressed::new(BincodeSerdeCodec, compression)
+            .write(zeros, &mut bytes)
+            .unwrap();
+        let limited = Compressed::new(BincodeSerdeCodec, compression).with_limit(64 * 1024);
+        let status = limited.read::<Vec<u8>, _>(bytes.as_slice()).unwrap_err();
+        assert_eq!(tonic::Code::ResourceExhausted, status.code());
+        assert_eq!(
+            zeros,
+            Compressed::new(BincodeSerdeCodec, compression)
+                .read::<Vec<u8>, _>(bytes.as_slice())
+                .unwrap()
+        );
+    }
+    check(Zstd, &zeros);
+    check(Lz4, &zeros);
+    check(Snappy, &zeros);
+}
+
+#[tokio::test]
+async fn test_compressed_methods() {
+    let addr = util::run_server(echo_server::EchoServer::new(State)).await;
+    let mut client = echo_client::EchoClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    assert_eq!(words(), client.zstd(words()).await.unwrap().into_inner());
+    assert_eq!(words(), client.lz4(words()).await.unwrap().into_inner());
+    assert_eq!(words(), client.snappy(words()).await.unwrap().into_inner());
+    assert_eq!(
+        words(),
+        client.dictionary(words()).await.unwrap().into_inner()
+    );
+    assert_eq!(
+        words(),
+        client.uncompressed(words()).await.unwrap().into_inner()
+    );
+}
@@ -0,0 +1,154 @@
+#![cfg(all(feature = "bincode", feature = "json"))]
+
+use std::{
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+use tonic::Code;
+use tonic_rpc::{
+    codec::{BincodeSerdeCodec, LimitedBincodeSerdeCodec, PrettyJsonSerdeCodec, SerdeCodec},
+    tonic_rpc,
+};
+
+mod util;
+
+#[tonic_rpc(bincode)]
+trait Store {
+    fn put(blob: Vec<u8>) -> usize;
+}
+
+#[tonic_rpc(json)]
+trait Describe {
+    fn describe(name: String, sizes: Vec<u32>) -> String;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl store_server::Store for State {
+    async fn put(
+        &self,
+        request: tonic::Request<Vec<u8>>,
+    ) -> Result<tonic::Response<usize>, tonic::Status> {
+        Ok(tonic::Response::new(request.into_inner().len()))
+    }
+}
+
+#[tonic::async_trait]
+impl describe_server::Describe for State {
+    async fn describe(
+        &self,
+        request: tonic::Request<(String, Vec<u32>)>,
+    ) -> Result<tonic::Response<String>, tonic::Status> {
+        let (name, sizes) = request.into_inner();
+        Ok(tonic::Response::new(format!("{}: {:?}", name, sizes)))
+    }
+}
+
+/// A codec counting the bytes it writes, shared by all the calls using it.
+#[derive(Clone, Default)]
+struct CountingCodec {
+    written: Arc<AtomicUsize>,
+}
+
+impl SerdeCodec for CountingCodec {
+    fn write<T, W>(&self, item: T, mut w: W) -> Result<(), tonic::Status>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        let mut bytes = Vec::new();
+        BincodeSerdeCodec.write(item, &mut bytes)?;
+        self.written.fetch_add(bytes.len(), Ordering::SeqCst);
+        w.write_all(&bytes)
+            .map_err(|io_err| tonic::Status::internal(io_err.to_string()))
+    }
+
+    fn read<T, R>(&self, r: R) -> Result<T, tonic::Status>
+    where
+        T: for<'de> Deserialize<'de>,
+        R: Read,
+    {
+        BincodeSerdeCodec.read(r)
+    }
+}
+
+#[test]
+fn test_pretty_json() {
+    let mut bytes = Vec::new();
+    PrettyJsonSerdeCodec::new(4)
+        .write(("disk", vec![1, 2]), &mut bytes)
+        .unwrap();
+    assert_eq!(
+        "[\n    \"disk\",\n    [\n        1,\n        2\n    ]\n]",
+        String::from_utf8(bytes).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_pretty_json_service() {
+    let addr = util::run_server(describe_server::DescribeServer::with_codec(
+        State,
+        PrettyJsonSerdeCodec::default(),
+    ))
+    .await;
+    // The default codec reads indented JSON.
+    let mut client = describe_client::DescribeClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    let response = client
+        .describe(("disk".to_string(), vec![1, 2]))
+        .await
+        .expect("Failed to send request");
+    assert_eq!("disk: [1, 2]", response.into_inner());
+}
+
+#[tokio::test]
+async fn test_limited_bincode() {
+    let addr = util::run_server(store_server::StoreServer::with_codec(
+        State,
+        LimitedBincodeSerdeCodec::new(1024),
+    ))
+    .await;
+    let mut client = store_client::StoreClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    let response = client
+        .put(vec![0; 1000])
+        .await
+        .expect("Failed to send request");
+    assert_eq!(1000, response.into_inner());
+
+    let status = client.put(vec![0; 2000]).await.unwrap_err();
+    assert_eq!(Code::ResourceExhausted, status.code());
+}
+
+#[tokio::test]
+async fn test_shared_codec_state() {
+    let addr = util::run_server(store_server::StoreServer::new(State)).await;
+    let channel = tonic::transport::Endpoint::new(addr)
+        .unwrap()
+        .connect()
+        .await
+        .expect("Failed to connect");
+    let codec = CountingCodec::default();
+    let mut client = store_client::StoreClient::with_codec(channel, codec.clone());
+
+    client
+        .put(vec![0; 10])
+        .await
+        .expect("Failed to send request");
+    client
+        .put(vec![0; 20])
+        .await
+        .expect("Failed to send request");
+    // Each `Vec` is prefixed by its length as a `u64`.
+    assert_eq!(46, codec.written.load(Ordering::SeqCst));
+}
@@ -0,0 +1,112 @@
+#![cfg(feature = "json")]
+
+use tokio::sync::mpsc;
+use tonic::Code;
+use tonic_rpc::tonic_rpc;
+
+mod util;
+
+#[tonic_rpc(json)]
+trait Jobs {
+    fn add(x: i32, y: i32) -> i32;
+    fn label(name: &str) -> String;
+    fn fail();
+
+    #[server_streaming]
+    fn watch() -> i32;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl jobs_server::Jobs for State {
+    type WatchStream = tokio_stream::Empty<Result<i32, tonic::Status>>;
+
+    async fn add(
+        &self,
+        request: tonic::Request<(i32, i32)>,
+    ) -> Result<tonic::Response<i32>, tonic::Status> {
+        let (x, y) = request.into_inner();
+        Ok(tonic::Response::new(x + y))
+    }
+
+    async fn label(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<String>, tonic::Status> {
+        Ok(tonic::Response::new(format!(
+            "job-{}",
+            request.into_inner()
+        )))
+    }
+
+    async fn fail(
+        &self,
+        _request: tonic::Request<()>,
+    ) -> Result<tonic::Response<()>, tonic::Status> {
+        Err(tonic::Status::unavailable("no workers"))
+    }
+
+    async fn watch(
+        &self,
+        _request: tonic::Request<()>,
+    ) -> Result<tonic::Response<Self::WatchStream>, tonic::Status> {
+        Ok(tonic::Response::new(tokio_stream::empty()))
+    }
+}
+
+use jobs_dispatch::{dispatch, JobsCall, JobsReply};
+
+#[test]
+fn test_serialized_calls() {
+    assert_eq!(
+        r#"{"Add":[1,2]}"#,
+        serde_json::to_string(&JobsCall::Add((1, 2))).unwrap()
+    );
+    assert_eq!(
+        r#"{"Label":"a"}"#,
+        serde_json::to_string(&JobsCall::Label("a".to_string())).unwrap()
+    );
+    assert_eq!(
+        r#"{"Fail":null}"#,
+        serde_json::to_string(&JobsCall::Fail(())).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_dispatch_over_queue() {
+    // A queue of serialized calls, as a message broker would deliver them.
+    let (tx, mut rx) = mpsc::channel::<String>(10);
+    for call in [JobsCall::Add((2, 3)), JobsCall::Label("build".to_string())] {
+        tx.send(serde_json::to_string(&call).unwrap())
+            .await
+            .unwrap();
+    }
+    drop(tx);
+
+    let mut replies = Vec::new();
+    while let Some(message) = rx.recv().await {
+        let call: JobsCall = serde_json::from_str(&message).unwrap();
+        let reply = dispatch(&State, call).await.unwrap();
+        replies.push(serde_json::to_string(&reply).unwrap());
+    }
+    assert_eq!(vec![r#"{"Add":5}"#, r#"{"Label":"job-build"}"#], replies);
+
+    let reply: JobsReply = serde_json::from_str(&replies[1]).unwrap();
+    assert!(matches!(reply, JobsReply::Label(label) if label == "job-build"));
+
+    match dispatch(&State, JobsCall::Fail(())).await {
+        Err(status) => assert_eq!(Code::Unavailable, status.code()),
+        Ok(_) => panic!("Expected the call to fail"),
+    }
+}
+
+#[tokio::test]
+async fn test_same_handlers_over_grpc() {
+    let addr = util::run_server(jobs_server::JobsServer::new(State)).await;
+    let mut client = jobs_client::JobsClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+    let response = client.add((2, 3)).await.expect("Failed to send request");
+    assert_eq!(5, response.into_inner());
+}
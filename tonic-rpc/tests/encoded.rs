@@ -0,0 +1,149 @@
+#![cfg(feature = "json")]
+
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Status;
+use tonic_rpc::{
+    codec::{self, Encoded, JsonSerdeCodec},
+    tonic_rpc,
+};
+
+mod util;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Update {
+    topic: String,
+    value: i64,
+}
+
+#[tonic_rpc(json)]
+trait Feed {
+    #[server_streaming]
+    fn subscribe() -> codec::Encoded<Update, JsonSerdeCodec>;
+
+    fn publish(update: tonic_rpc::codec::Encoded<Update, JsonSerdeCodec>) -> usize;
+}
+
+type EncodedUpdate = Result<Encoded<Update, JsonSerdeCodec>, Status>;
+
+#[derive(Default)]
+struct State {
+    subscribers: Mutex<Vec<mpsc::Sender<EncodedUpdate>>>,
+}
+
+#[tonic::async_trait]
+impl feed_server::Feed for State {
+    type SubscribeStream = ReceiverStream<EncodedUpdate>;
+
+    async fn subscribe(
+        &self,
+        _request: tonic::Request<()>,
+    ) -> Result<tonic::Response<Self::SubscribeStream>, Status> {
+        let (tx, rx) = mpsc::channel(10);
+        self.subscribers.lock().unwrap().push(tx);
+        Ok(tonic::Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn publish(
+        &self,
+        request: tonic::Request<Update>,
+    ) -> Result<tonic::Response<usize>, Status> {
+        // Serialize the update once for all the subscribers.
+        let update = Encoded::new(&JsonSerdeCodec, &request.into_inner())?;
+        let subscribers = self.subscribers.lock().unwrap().clone();
+        for subscriber in &subscribers {
+            subscriber.send(Ok(update.clone())).await.unwrap();
+        }
+        Ok(tonic::Response::new(subscribers.len()))
+    }
+}
+
+fn update(value: i64) -> Update {
+    Update {
+        topic: "prices".to_string(),
+        value,
+    }
+}
+
+#[test]
+fn test_encoded_bytes() {
+    let encoded = Encoded::new(&JsonSerdeCodec, &update(3)).unwrap();
+    assert_eq!(
+        br#"{"topic":"prices","value":3}"#,
+        encoded.clone().as_bytes()
+    );
+}
+
+#[tokio::test]
+async fn test_fan_out() {
+    let addr = util::run_server(feed_server::FeedServer::new(State::default())).await;
+    let mut client = feed_client::FeedClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+    let mut first = client.subscribe(()).await.unwrap().into_inner();
+    let mut second = client.subscribe(()).await.unwrap().into_inner();
+
+    for value in 0..3 {
+        let request = Encoded::new(&JsonSerdeCodec, &update(value)).unwrap();
+        let subscribers = client
+            .publish(request)
+            .await
+            .expect("Failed to send request");
+        assert_eq!(2, subscribers.into_inner());
+        assert_eq!(update(value), first.message().await.unwrap().unwrap());
+        assert_eq!(update(value), second.message().await.unwrap().unwrap());
+    }
+}
+
+/// A service whose own message type is named `Encoded`.
+mod blobs {
+    use serde::{Deserialize, Serialize};
+    use tonic_rpc::tonic_rpc;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Encoded(pub String);
+
+    #[tonic_rpc(json)]
+    pub trait Blobs {
+        fn store(blob: Encoded) -> Encoded;
+    }
+
+    pub struct State;
+
+    #[tonic::async_trait]
+    impl blobs_server::Blobs for State {
+        async fn store(
+            &self,
+            request: tonic::Request<Encoded>,
+        ) -> Result<tonic::Response<Encoded>, tonic::Status> {
+            Ok(tonic::Response::new(Encoded(format!(
+                "stored {}",
+                request.into_inner().0
+            ))))
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_message_type_named_encoded() {
+    use blobs::{
+        blobs_async::BlobsAsync,
+        blobs_dispatch::{dispatch, BlobsCall, BlobsReply},
+        Encoded,
+    };
+
+    let stored = Encoded("stored a".to_string());
+    assert_eq!(
+        stored,
+        BlobsAsync::store(&blobs::State, Encoded("a".to_string()))
+            .await
+            .unwrap()
+    );
+    match dispatch(&blobs::State, BlobsCall::Store(Encoded("a".to_string()))).await {
+        Ok(BlobsReply::Store(reply)) => assert_eq!(stored, reply),
+        Err(status) => panic!("Failed to dispatch the call: {}", status),
+    }
+}
@@ -0,0 +1,185 @@
+#![cfg(all(feature = "bincode", feature = "chacha20poly1305", feature = "aes-gcm"))]
+
+use tonic::Code;
+use tonic_rpc::{
+    codec::{BincodeSerdeCodec, Direction, Encrypted, MessagePosition, SerdeCodec},
+    tonic_rpc,
+};
+
+mod util;
+
+const KEY: [u8; 32] = [7; 32];
+
+#[tonic_rpc(bincode)]
+trait Vault {
+    fn reveal(secret: String, times: usize) -> String;
+
+    fn length(secret: &str) -> usize;
+
+    #[server_streaming]
+    fn letters(secret: String) -> char;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl vault_server::Vault for State {
+    type LettersStream = tokio_stream::Iter<std::vec::IntoIter<Result<char, tonic::Status>>>;
+
+    async fn length(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<usize>, tonic::Status> {
+        Ok(tonic::Response::new(request.into_inner().len()))
+    }
+
+    async fn letters(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<Self::LettersStream>, tonic::Status> {
+        let letters: Vec<_> = request.into_inner().chars().map(Ok).collect();
+        Ok(tonic::Response::new(tokio_stream::iter(letters)))
+    }
+
+    async fn reveal(
+        &self,
+        request: tonic::Request<(String, usize)>,
+    ) -> Result<tonic::Response<String>, tonic::Status> {
+        let (secret, times) = request.into_inner();
+        Ok(tonic::Response::new(secret.repeat(times)))
+    }
+}
+
+fn encode(codec: &Encrypted<BincodeSerdeCodec>, item: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    codec.write(item, &mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn test_sealed_messages() {
+    for codec in [
+        Encrypted::chacha20poly1305(BincodeSerdeCodec, &KEY),
+        Encrypted::aes256gcm(BincodeSerdeCodec, &KEY),
+    ] {
+        let bytes = encode(&codec, "attack at dawn");
+        assert!(!bytes.windows(6).any(|window| window == b"attack"));
+        // Each message uses a fresh nonce.
+        assert_ne!(bytes, encode(&codec, "attack at dawn"));
+        assert_eq!(
+            "attack at dawn",
+            codec.read::<String, _>(bytes.as_slice()).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_tampered_messages() {
+    let codec = Encrypted::chacha20poly1305(BincodeSerdeCodec, &KEY);
+    let mut bytes = encode(&codec, "attack at dawn");
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    let status = codec.read::<String, _>(bytes.as_slice()).unwrap_err();
+    assert_eq!(Code::DataLoss, status.code());
+
+    let status = codec.read::<String, _>(&bytes[..4]).unwrap_err();
+    assert_eq!(Code::DataLoss, status.code());
+
+    let bytes = encode(&codec, "attack at dawn");
+    let other_cipher = Encrypted::aes256gcm(BincodeSerdeCodec, &KEY);
+    let status = other_cipher
+        .read::<String, _>(bytes.as_slice())
+        .unwrap_err();
+    assert_eq!(Code::DataLoss, status.code());
+}
+
+#[test]
+fn test_messages_are_bound_to_their_position() {
+    let codec = Encrypted::chacha20poly1305(BincodeSerdeCodec, &KEY);
+    let position = MessagePosition {
+        path: "/Vault/Reveal",
+        direction: Direction::Request,
+        sequence: 0,
+    };
+    let mut bytes = Vec::new();
+    codec
+        .write_at("attack at dawn", &mut bytes, &position)
+        .unwrap();
+    assert_eq!(
+        "attack at dawn",
+        codec
+            .read_at::<String, _>(bytes.as_slice(), &position)
+            .unwrap()
+    );
+
+    let replays = [
+        MessagePosition {
+            path: "/Vault/Other",
+            ..position
+        },
+        MessagePosition {
+            direction: Direction::Response,
+            ..position
+        },
+        MessagePosition {
+            sequence: 1,
+            ..position
+        },
+    ];
+    for replay in &replays {
+        let status = codec
+            .read_at::<String, _>(bytes.as_slice(), replay)
+            .unwrap_err();
+        assert_eq!(Code::DataLoss, status.code());
+    }
+    let status = codec.read::<String, _>(bytes.as_slice()).unwrap_err();
+    assert_eq!(Code::DataLoss, status.code());
+}
+
+#[tokio::test]
+async fn test_encrypted_service() {
+    let codec = Encrypted::chacha20poly1305(BincodeSerdeCodec, &KEY);
+    let addr = util::run_server(vault_server::VaultServer::with_codec(State, codec.clone())).await;
+    let channel = tonic::transport::Endpoint::new(addr.clone())
+        .unwrap()
+        .connect()
+        .await
+        .expect("Failed to connect");
+    let mut client = vault_client::VaultClient::with_codec(channel.clone(), codec);
+
+    let response = client
+        .reveal(("ab".to_string(), 3))
+        .await
+        .expect("Failed to send request");
+    assert_eq!("ababab", response.into_inner());
+    assert_eq!(
+        4,
+        client
+            .length("abcd")
+            .await
+            .expect("Failed to send request")
+            .into_inner()
+    );
+    let letters: Vec<char> = tokio_stream::StreamExt::collect::<Result<_, _>>(
+        client
+            .letters("abc".to_string())
+            .await
+            .expect("Failed to send request")
+            .into_inner(),
+    )
+    .await
+    .expect("Failed to receive letters");
+    assert_eq!(vec!['a', 'b', 'c'], letters);
+
+    let mut client = vault_client::VaultClient::with_codec(
+        channel,
+        Encrypted::chacha20poly1305(BincodeSerdeCodec, &[8; 32]),
+    );
+    let status = client.reveal(("ab".to_string(), 3)).await.unwrap_err();
+    assert_eq!(Code::DataLoss, status.code());
+
+    let mut client = vault_client::VaultClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+    assert!(client.reveal(("ab".to_string(), 3)).await.is_err());
+}
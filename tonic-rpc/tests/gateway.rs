@@ -0,0 +1,216 @@
+#![cfg(feature = "gateway")]
+
+use hyper::{body::to_bytes, header, Body, Client, Method, Request, StatusCode};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::transport::Server;
+use tonic_rpc::{gateway::JsonGateway, tonic_rpc};
+
+#[tonic_rpc(json)]
+trait Counter {
+    fn increment(arg: i32) -> i32;
+
+    fn add(x: i32, y: i32) -> i32;
+
+    fn zero() -> i32;
+
+    #[server_streaming]
+    fn count_to(arg: i32) -> i32;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl counter_server::Counter for State {
+    type CountToStream = ReceiverStream<Result<i32, tonic::Status>>;
+
+    async fn increment(
+        &self,
+        request: tonic::Request<i32>,
+    ) -> Result<tonic::Response<i32>, tonic::Status> {
+        request
+            .into_inner()
+            .checked_add(1)
+            .map(tonic::Response::new)
+            .ok_or_else(|| tonic::Status::out_of_range("Overflow"))
+    }
+
+    async fn add(
+        &self,
+        request: tonic::Request<(i32, i32)>,
+    ) -> Result<tonic::Response<i32>, tonic::Status> {
+        let (x, y) = request.into_inner();
+        Ok(tonic::Response::new(x + y))
+    }
+
+    async fn zero(
+        &self,
+        _request: tonic::Request<()>,
+    ) -> Result<tonic::Response<i32>, tonic::Status> {
+        Ok(tonic::Response::new(0))
+    }
+
+    async fn count_to(
+        &self,
+        request: tonic::Request<i32>,
+    ) -> Result<tonic::Response<Self::CountToStream>, tonic::Status> {
+        let max = request.into_inner();
+        let (tx, rx) = tokio::sync::mpsc::channel(4);
+        tokio::spawn(async move {
+            for i in 1..=max.min(3) {
+                tx.send(Ok(i)).await.unwrap();
+            }
+            if max > 3 {
+                tx.send(Err(tonic::Status::invalid_argument("Too far")))
+                    .await
+                    .unwrap();
+            }
+        });
+        Ok(tonic::Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+async fn run_gateway() -> String {
+    serve(JsonGateway::new(counter_server::CounterServer::new(State))).await
+}
+
+async fn serve(gateway: JsonGateway<counter_server::CounterServer<State>>) -> String {
+    let listener = TcpListener::bind("[::1]:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        Server::builder()
+            .accept_http1(true)
+            .add_service(gateway)
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+    format!("http://{}", addr)
+}
+
+async fn post(addr: &str, path: &str, accept: &str, body: &str) -> (StatusCode, String, String) {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("{}{}", addr, path))
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::ACCEPT, accept)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = Client::new().request(request).await.unwrap();
+    let status = response.status();
+    let content_type = response.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let body = to_bytes(response.into_body()).await.unwrap();
+    (
+        status,
+        content_type,
+        String::from_utf8(body.to_vec()).unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn test_unary() {
+    let addr = run_gateway().await;
+    assert_eq!(
+        (
+            StatusCode::OK,
+            "application/json".to_string(),
+            "6".to_string()
+        ),
+        post(&addr, "/Counter/Increment", "application/json", "5").await
+    );
+    assert_eq!(
+        (
+            StatusCode::OK,
+            "application/json".to_string(),
+            "7".to_string()
+        ),
+        post(&addr, "/Counter/Add", "application/json", "[3, 4]").await
+    );
+    assert_eq!(
+        (
+            StatusCode::OK,
+            "application/json".to_string(),
+            "0".to_string()
+        ),
+        post(&addr, "/Counter/Zero", "application/json", "").await
+    );
+}
+
+#[tokio::test]
+async fn test_errors() {
+    let addr = run_gateway().await;
+    assert_eq!(
+        (
+            StatusCode::BAD_REQUEST,
+            "application/json".to_string(),
+            r#"{"code":11,"message":"Overflow"}"#.to_string()
+        ),
+        post(
+            &addr,
+            "/Counter/Increment",
+            "application/json",
+            &i32::MAX.to_string()
+        )
+        .await
+    );
+    let (status, _, _) = post(&addr, "/Counter/Missing", "application/json", "5").await;
+    assert_eq!(StatusCode::NOT_IMPLEMENTED, status);
+    let (status, _, _) = post(&addr, "/Counter/Increment", "application/json", "\"5\"").await;
+    assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, status);
+}
+
+#[tokio::test]
+async fn test_server_streaming() {
+    let addr = run_gateway().await;
+    assert_eq!(
+        (
+            StatusCode::OK,
+            "application/x-ndjson".to_string(),
+            "1\n2\n3\n".to_string()
+        ),
+        post(&addr, "/Counter/CountTo", "application/x-ndjson", "3").await
+    );
+    assert_eq!(
+        (
+            StatusCode::OK,
+            "text/event-stream".to_string(),
+            "data: 1\n\ndata: 2\n\n".to_string()
+        ),
+        post(&addr, "/Counter/CountTo", "text/event-stream", "2").await
+    );
+    assert_eq!(
+        (
+            StatusCode::OK,
+            "application/x-ndjson".to_string(),
+            "1\n2\n3\n{\"error\":{\"code\":3,\"message\":\"Too far\"}}\n".to_string()
+        ),
+        post(&addr, "/Counter/CountTo", "application/x-ndjson", "5").await
+    );
+}
+
+#[tokio::test]
+async fn test_grpc_passthrough() {
+    let addr = run_gateway().await;
+    let mut client = counter_client::CounterClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+    assert_eq!(6, client.increment(5).await.unwrap().into_inner());
+}
+
+#[tokio::test]
+async fn test_request_too_large() {
+    let gateway = JsonGateway::new(counter_server::CounterServer::new(State)).max_request_size(8);
+    let addr = serve(gateway).await;
+    let (status, _, body) = post(&addr, "/Counter/Add", "application/json", "[1, 2]").await;
+    assert_eq!((StatusCode::OK, "3"), (status, body.as_str()));
+    let (status, _, body) = post(&addr, "/Counter/Add", "application/json", "[100, 200]").await;
+    assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, status);
+    assert_eq!(
+        r#"{"code":8,"message":"Request body is larger than 8 bytes"}"#,
+        body
+    );
+}
@@ -0,0 +1,278 @@
+#![cfg(feature = "jsonrpc")]
+
+use hyper::{body::to_bytes, header, Body, Client, Method, Request, StatusCode};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+use tonic_rpc::{jsonrpc::JsonRpc, tonic_rpc};
+
+#[tonic_rpc(json)]
+trait Counter {
+    fn increment(arg: i32) -> i32;
+
+    fn add(x: i32, y: i32) -> i32;
+
+    fn zero() -> i32;
+
+    #[server_streaming]
+    fn count_to(arg: i32) -> i32;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl counter_server::Counter for State {
+    type CountToStream = tokio_stream::Iter<std::vec::IntoIter<Result<i32, tonic::Status>>>;
+
+    async fn increment(
+        &self,
+        request: tonic::Request<i32>,
+    ) -> Result<tonic::Response<i32>, tonic::Status> {
+        request
+            .into_inner()
+            .checked_add(1)
+            .map(tonic::Response::new)
+            .ok_or_else(|| tonic::Status::out_of_range("Overflow"))
+    }
+
+    async fn add(
+        &self,
+        request: tonic::Request<(i32, i32)>,
+    ) -> Result<tonic::Response<i32>, tonic::Status> {
+        let (x, y) = request.into_inner();
+        Ok(tonic::Response::new(x - y))
+    }
+
+    async fn zero(
+        &self,
+        _request: tonic::Request<()>,
+    ) -> Result<tonic::Response<i32>, tonic::Status> {
+        Ok(tonic::Response::new(0))
+    }
+
+    async fn count_to(
+        &self,
+        request: tonic::Request<i32>,
+    ) -> Result<tonic::Response<Self::CountToStream>, tonic::Status> {
+        let counts: Vec<_> = (1..=request.into_inner()).map(Ok).collect();
+        Ok(tonic::Response::new(tokio_stream::iter(counts)))
+    }
+}
+
+async fn run_json_rpc() -> String {
+    serve(JsonRpc::new(counter_server::CounterServer::new(State))).await
+}
+
+async fn serve(json_rpc: JsonRpc<counter_server::CounterServer<State>>) -> String {
+    let listener = TcpListener::bind("[::1]:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        Server::builder()
+            .accept_http1(true)
+            .add_service(json_rpc)
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+    format!("http://{}/Counter/rpc", addr)
+}
+
+async fn post(addr: &str, body: &str) -> (StatusCode, String) {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(addr)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = Client::new().request(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body()).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+async fn call(addr: &str, request: Value) -> Value {
+    let (status, body) = post(addr, &request.to_string()).await;
+    assert_eq!(StatusCode::OK, status);
+    serde_json::from_str(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_positional_params() {
+    let addr = run_json_rpc().await;
+    assert_eq!(
+        json!({"jsonrpc": "2.0", "result": 6, "id": 1}),
+        call(
+            &addr,
+            json!({"jsonrpc": "2.0", "method": "increment", "params": [5], "id": 1})
+        )
+        .await
+    );
+    assert_eq!(
+        json!({"jsonrpc": "2.0", "result": 3, "id": "add"}),
+        call(
+            &addr,
+            json!({"jsonrpc": "2.0", "method": "add", "params": [5, 2], "id": "add"})
+        )
+        .await
+    );
+    assert_eq!(
+        json!({"jsonrpc": "2.0", "result": 0, "id": null}),
+        call(
+            &addr,
+            json!({"jsonrpc": "2.0", "method": "zero", "id": null})
+        )
+        .await
+    );
+}
+
+#[tokio::test]
+async fn test_named_params() {
+    let addr = run_json_rpc().await;
+    assert_eq!(
+        json!({"jsonrpc": "2.0", "result": 3, "id": 1}),
+        call(
+            &addr,
+            json!({"jsonrpc": "2.0", "method": "add", "params": {"y": 2, "x": 5}, "id": 1})
+        )
+        .await
+    );
+    assert_eq!(
+        json!({"jsonrpc": "2.0", "result": 6, "id": 2}),
+        call(
+            &addr,
+            json!({"jsonrpc": "2.0", "method": "increment", "params": {"arg": 5}, "id": 2})
+        )
+        .await
+    );
+
+    let response = call(
+        &addr,
+        json!({"jsonrpc": "2.0", "method": "add", "params": {"x": 5}, "id": 3}),
+    )
+    .await;
+    assert_eq!(-32602, response["error"]["code"]);
+    let response = call(
+        &addr,
+        json!({"jsonrpc": "2.0", "method": "add", "params": {"x": 5, "y": 2, "z": 1}, "id": 4}),
+    )
+    .await;
+    assert_eq!(-32602, response["error"]["code"]);
+}
+
+#[tokio::test]
+async fn test_batch() {
+    let addr = run_json_rpc().await;
+    assert_eq!(
+        json!([
+            {"jsonrpc": "2.0", "result": 2, "id": 1},
+            {"jsonrpc": "2.0", "error": {"code": -32601, "message": "Unknown method `missing`"}, "id": 3},
+        ]),
+        call(
+            &addr,
+            json!([
+                {"jsonrpc": "2.0", "method": "increment", "params": [1], "id": 1},
+                {"jsonrpc": "2.0", "method": "increment", "params": [2]},
+                {"jsonrpc": "2.0", "method": "missing", "id": 3},
+            ])
+        )
+        .await
+    );
+
+    // Notifications get no response.
+    let (status, body) = post(
+        &addr,
+        r#"[{"jsonrpc": "2.0", "method": "increment", "params": [1]}]"#,
+    )
+    .await;
+    assert_eq!(StatusCode::NO_CONTENT, status);
+    assert_eq!("", body);
+
+    let response = call(&addr, json!([])).await;
+    assert_eq!(-32600, response["error"]["code"]);
+}
+
+#[tokio::test]
+async fn test_errors() {
+    let addr = run_json_rpc().await;
+    assert_eq!(
+        json!({
+            "jsonrpc": "2.0",
+            "error": {"code": -32011, "message": "Overflow", "data": {"grpc_code": 11}},
+            "id": 1,
+        }),
+        call(
+            &addr,
+            json!({"jsonrpc": "2.0", "method": "increment", "params": [i32::MAX], "id": 1})
+        )
+        .await
+    );
+
+    // The codec rejects arguments of the wrong type.
+    let response = call(
+        &addr,
+        json!({"jsonrpc": "2.0", "method": "increment", "params": ["five"], "id": 2}),
+    )
+    .await;
+    assert_eq!(2, response["id"]);
+    assert!(response["error"]["code"].is_i64());
+
+    let response = call(
+        &addr,
+        json!({"jsonrpc": "2.0", "method": "add", "params": [1], "id": 3}),
+    )
+    .await;
+    assert_eq!(-32602, response["error"]["code"]);
+
+    let response = call(
+        &addr,
+        json!({"jsonrpc": "2.0", "method": "count_to", "params": [3], "id": 4}),
+    )
+    .await;
+    assert_eq!(-32601, response["error"]["code"]);
+
+    let response = call(&addr, json!({"jsonrpc": "1.0", "method": "zero", "id": 5})).await;
+    assert_eq!(
+        json!({"code": -32600, "message": "`jsonrpc` must be \"2.0\""}),
+        response["error"]
+    );
+    assert_eq!(5, response["id"]);
+
+    let (status, body) = post(&addr, "{").await;
+    assert_eq!(StatusCode::OK, status);
+    let response: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(-32700, response["error"]["code"]);
+    assert_eq!(Value::Null, response["id"]);
+}
+
+#[tokio::test]
+async fn test_grpc_passthrough() {
+    let addr = run_json_rpc().await;
+    let addr = addr.trim_end_matches("/Counter/rpc").to_string();
+    let mut client = counter_client::CounterClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+    let response = client.add((5, 2)).await.expect("Failed to send request");
+    assert_eq!(3, response.into_inner());
+}
+
+#[tokio::test]
+async fn test_request_too_large() {
+    let json_rpc = JsonRpc::new(counter_server::CounterServer::new(State)).max_request_size(56);
+    let addr = serve(json_rpc).await;
+    let request = json!({"jsonrpc": "2.0", "method": "add", "params": [5, 2], "id": 1});
+    assert_eq!(3, call(&addr, request).await["result"]);
+
+    let request = json!({"jsonrpc": "2.0", "method": "add", "params": [100, 200], "id": 1000});
+    let (status, body) = post(&addr, &request.to_string()).await;
+    assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, status);
+    assert_eq!(
+        json!({
+            "jsonrpc": "2.0",
+            "error": {"code": -32600, "message": "Request body is larger than 56 bytes"},
+            "id": null,
+        }),
+        serde_json::from_str::<Value>(&body).unwrap()
+    );
+}
@@ -0,0 +1,267 @@
+#![cfg(all(feature = "metrics", feature = "json"))]
+
+use std::sync::{Arc, OnceLock};
+
+use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+use tokio::sync::{Mutex, MutexGuard, Notify};
+use tonic_rpc::tonic_rpc;
+
+mod util;
+
+#[tonic_rpc(json)]
+trait Greeter {
+    fn greet(name: String) -> String;
+
+    #[server_streaming]
+    fn letters(name: String) -> char;
+
+    fn wait();
+}
+
+#[derive(Default)]
+struct State {
+    started: Arc<Notify>,
+    release: Arc<Notify>,
+}
+
+#[tonic::async_trait]
+impl greeter_server::Greeter for State {
+    type LettersStream = tokio_stream::Iter<std::vec::IntoIter<Result<char, tonic::Status>>>;
+
+    async fn greet(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<String>, tonic::Status> {
+        let name = request.into_inner();
+        if name.is_empty() {
+            return Err(tonic::Status::not_found("Nobody to greet"));
+        }
+        Ok(tonic::Response::new(format!("Hello {}", name)))
+    }
+
+    async fn letters(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<Self::LettersStream>, tonic::Status> {
+        let mut letters = Vec::new();
+        for c in request.into_inner().chars() {
+            letters.push(match c {
+                '!' => Err(tonic::Status::invalid_argument("Not a letter")),
+                c => Ok(c),
+            });
+        }
+        Ok(tonic::Response::new(tokio_stream::iter(letters)))
+    }
+
+    async fn wait(
+        &self,
+        _request: tonic::Request<()>,
+    ) -> Result<tonic::Response<()>, tonic::Status> {
+        self.started.notify_one();
+        self.release.notified().await;
+        Ok(tonic::Response::new(()))
+    }
+}
+
+/// The metrics recorded by all the tests, which call different methods.
+fn snapshotter() -> &'static Snapshotter {
+    static SNAPSHOTTER: OnceLock<Snapshotter> = OnceLock::new();
+    SNAPSHOTTER.get_or_init(|| {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        recorder.install().unwrap();
+        snapshotter
+    })
+}
+
+/// Runs the tests one at a time, as taking a snapshot drains the histograms of all of them.
+async fn record() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::const_new(());
+    snapshotter();
+    LOCK.lock().await
+}
+
+struct Metric {
+    name: String,
+    labels: Vec<(String, String)>,
+    value: DebugValue,
+}
+
+struct Snapshot(Vec<Metric>);
+
+impl Snapshot {
+    fn take() -> Self {
+        let metrics = snapshotter()
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let key = key.key();
+                let labels = key
+                    .labels()
+                    .map(|label| (label.key().to_string(), label.value().to_string()))
+                    .collect();
+                Metric {
+                    name: key.name().to_string(),
+                    labels,
+                    value,
+                }
+            })
+            .collect();
+        Snapshot(metrics)
+    }
+
+    /// The value of the metric `name` of `method` with `labels`.
+    fn get(&self, name: &str, method: &str, labels: &[(&str, &str)]) -> &DebugValue {
+        let expected = [("service", "Greeter"), ("method", method), ("codec", CODEC)];
+        self.0
+            .iter()
+            .find(|metric| {
+                metric.name == name
+                    && expected
+                        .iter()
+                        .chain(labels)
+                        .all(|(k, v)| metric.labels.contains(&(k.to_string(), v.to_string())))
+                    && metric.labels.len() == expected.len() + labels.len()
+            })
+            .map(|metric| &metric.value)
+            .unwrap_or_else(|| panic!("No metric {} {} {:?}", name, method, labels))
+    }
+
+    fn counter(&self, name: &str, method: &str, labels: &[(&str, &str)]) -> u64 {
+        match self.get(name, method, labels) {
+            DebugValue::Counter(count) => *count,
+            value => panic!("{} is not a counter: {:?}", name, value),
+        }
+    }
+
+    fn gauge(&self, name: &str, method: &str) -> f64 {
+        match self.get(name, method, &[]) {
+            DebugValue::Gauge(value) => value.into_inner(),
+            value => panic!("{} is not a gauge: {:?}", name, value),
+        }
+    }
+
+    fn histogram(&self, name: &str, method: &str) -> Vec<f64> {
+        match self.get(name, method, &[]) {
+            DebugValue::Histogram(values) => values.iter().map(|v| v.into_inner()).collect(),
+            value => panic!("{} is not a histogram: {:?}", name, value),
+        }
+    }
+}
+
+const CODEC: &str = "tonic_rpc::codec::JsonSerdeCodec";
+
+#[tokio::test]
+async fn test_unary() {
+    let _guard = record().await;
+    let addr = util::run_server(greeter_server::GreeterServer::new(State::default())).await;
+    let mut client = greeter_client::GreeterClient::connect(addr).await.unwrap();
+    client.greet("Ada".to_string()).await.unwrap();
+    let status = client.greet(String::new()).await.unwrap_err();
+    assert_eq!(tonic::Code::NotFound, status.code());
+
+    let snapshot = Snapshot::take();
+    for side in ["server", "client"] {
+        let name = |metric| format!("tonic_rpc_{}_{}", side, metric);
+        assert_eq!(2, snapshot.counter(&name("requests_total"), "Greet", &[]));
+        assert_eq!(
+            1,
+            snapshot.counter(&name("errors_total"), "Greet", &[("code", "NotFound")])
+        );
+        assert_eq!(0.0, snapshot.gauge(&name("in_flight"), "Greet"));
+        let latencies = snapshot.histogram(&name("latency_seconds"), "Greet");
+        assert_eq!(2, latencies.len(), "{}", side);
+        assert!(latencies.iter().all(|latency| *latency > 0.0));
+    }
+
+    // The requests are `"Ada"` and `""`, and the response `"Hello Ada"`, in JSON.
+    for (metric, bytes) in [
+        ("tonic_rpc_client_encoded_bytes_total", 7),
+        ("tonic_rpc_server_decoded_bytes_total", 7),
+        ("tonic_rpc_server_encoded_bytes_total", 11),
+        ("tonic_rpc_client_decoded_bytes_total", 11),
+    ] {
+        assert_eq!(bytes, snapshot.counter(metric, "Greet", &[]), "{}", metric);
+    }
+}
+
+#[tokio::test]
+async fn test_streaming() {
+    let _guard = record().await;
+    let addr = util::run_server(greeter_server::GreeterServer::new(State::default())).await;
+    let mut client = greeter_client::GreeterClient::connect(addr).await.unwrap();
+    let mut letters = client.letters("A!".to_string()).await.unwrap().into_inner();
+    // The client records the end of a stream once it has received the status after the
+    // messages, and the server once it has sent it.
+    let snapshot = Snapshot::take();
+    assert_eq!(1.0, snapshot.gauge("tonic_rpc_client_in_flight", "Letters"));
+    assert_eq!(Some('A'), letters.message().await.unwrap());
+    let status = letters.message().await.unwrap_err();
+    assert_eq!(tonic::Code::InvalidArgument, status.code());
+    let mut letters = client
+        .letters("Ada".to_string())
+        .await
+        .unwrap()
+        .into_inner();
+    while letters.message().await.unwrap().is_some() {}
+
+    let snapshot = Snapshot::take();
+    for side in ["server", "client"] {
+        let name = |metric| format!("tonic_rpc_{}_{}", side, metric);
+        assert_eq!(2, snapshot.counter(&name("requests_total"), "Letters", &[]));
+        assert_eq!(
+            1,
+            snapshot.counter(
+                &name("errors_total"),
+                "Letters",
+                &[("code", "InvalidArgument")]
+            )
+        );
+        assert_eq!(0.0, snapshot.gauge(&name("in_flight"), "Letters"));
+    }
+    // The server may have sent the first stream before the snapshot above, which drained its
+    // latency, but the client was still receiving it.
+    let latencies = snapshot.histogram("tonic_rpc_client_latency_seconds", "Letters");
+    assert_eq!(2, latencies.len());
+
+    // The requests are `"A!"` and `"Ada"`, and the responses `'A'`, `'A'`, `'d'` and `'a'`.
+    for (metric, bytes) in [
+        ("tonic_rpc_client_encoded_bytes_total", 9),
+        ("tonic_rpc_server_decoded_bytes_total", 9),
+        ("tonic_rpc_server_encoded_bytes_total", 12),
+        ("tonic_rpc_client_decoded_bytes_total", 12),
+    ] {
+        assert_eq!(
+            bytes,
+            snapshot.counter(metric, "Letters", &[]),
+            "{}",
+            metric
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_in_flight() {
+    let _guard = record().await;
+    let state = State::default();
+    let (started, release) = (state.started.clone(), state.release.clone());
+    let addr = util::run_server(greeter_server::GreeterServer::new(state)).await;
+    let mut client = greeter_client::GreeterClient::connect(addr).await.unwrap();
+
+    let call = tokio::spawn(async move { client.wait(()).await });
+    started.notified().await;
+    let snapshot = Snapshot::take();
+    for side in ["server", "client"] {
+        let metric = format!("tonic_rpc_{}_in_flight", side);
+        assert_eq!(1.0, snapshot.gauge(&metric, "Wait"));
+    }
+
+    release.notify_one();
+    call.await.unwrap().unwrap();
+    let snapshot = Snapshot::take();
+    for side in ["server", "client"] {
+        let metric = format!("tonic_rpc_{}_in_flight", side);
+        assert_eq!(0.0, snapshot.gauge(&metric, "Wait"));
+    }
+}
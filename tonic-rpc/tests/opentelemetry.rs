@@ -0,0 +1,232 @@
+#![cfg(all(feature = "opentelemetry", feature = "json"))]
+
+use opentelemetry::{
+    trace::{FutureExt, SpanId, TraceContextExt, TraceId, Tracer, TracerProvider as _},
+    Context,
+};
+use opentelemetry_sdk::{
+    export::trace::SpanData,
+    testing::trace::InMemorySpanExporter,
+    trace::{self, TracerProvider},
+};
+use tokio_stream::StreamExt;
+use tonic_rpc::tonic_rpc;
+
+mod util;
+
+#[tonic_rpc(json)]
+trait Greeter {
+    fn greet(name: String) -> String;
+
+    #[server_streaming]
+    fn letters(name: String) -> char;
+
+    #[client_streaming]
+    fn count(name: String) -> usize;
+}
+
+struct State {
+    tracer: trace::Tracer,
+}
+
+#[tonic::async_trait]
+impl greeter_server::Greeter for State {
+    type LettersStream =
+        std::pin::Pin<Box<dyn tokio_stream::Stream<Item = Result<char, tonic::Status>> + Send>>;
+
+    async fn greet(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<String>, tonic::Status> {
+        let name = request.into_inner();
+        let greeting = self.tracer.in_span("greet", |_| format!("Hello {}", name));
+        Ok(tonic::Response::new(greeting))
+    }
+
+    async fn letters(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<Self::LettersStream>, tonic::Status> {
+        // The letters are sent after this returns, each in a span of its own.
+        let tracer = self.tracer.clone();
+        let letters: Vec<_> = request.into_inner().chars().collect();
+        let letters = tokio_stream::iter(letters)
+            .map(move |letter| tracer.in_span(format!("letter {}", letter), |_| letter))
+            .map(Ok);
+        Ok(tonic::Response::new(Box::pin(letters)))
+    }
+
+    async fn count(
+        &self,
+        request: tonic::Request<tonic::Streaming<String>>,
+    ) -> Result<tonic::Response<usize>, tonic::Status> {
+        let mut names = request.into_inner();
+        let mut count = 0;
+        while let Some(name) = names.message().await? {
+            self.tracer
+                .in_span(format!("count {}", name), |_| count += 1);
+        }
+        Ok(tonic::Response::new(count))
+    }
+}
+
+struct Traces {
+    provider: TracerProvider,
+    exporter: InMemorySpanExporter,
+}
+
+impl Traces {
+    fn new() -> Self {
+        let exporter = InMemorySpanExporter::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        Traces { provider, exporter }
+    }
+
+    fn tracer(&self) -> trace::Tracer {
+        self.provider.tracer("tonic-rpc-test")
+    }
+
+    fn span(&self, name: &str) -> SpanData {
+        self.provider.force_flush();
+        let spans = self.exporter.get_finished_spans().unwrap();
+        spans
+            .into_iter()
+            .find(|span| span.name == name)
+            .unwrap_or_else(|| panic!("No span {}", name))
+    }
+}
+
+/// Asserts that `child` was created in the context of `parent`, on the other side of a call.
+fn assert_child(parent: &SpanData, child: &SpanData) {
+    assert_eq!(
+        parent.span_context.trace_id(),
+        child.span_context.trace_id(),
+        "{}",
+        child.name
+    );
+    assert_eq!(
+        parent.span_context.span_id(),
+        child.parent_span_id,
+        "{}",
+        child.name
+    );
+}
+
+async fn run(traces: &Traces) -> greeter_client::GreeterClient<tonic::transport::Channel> {
+    let state = State {
+        tracer: traces.tracer(),
+    };
+    let addr = util::run_server(greeter_server::GreeterServer::new(state)).await;
+    greeter_client::GreeterClient::connect(addr).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_unary() {
+    let traces = Traces::new();
+    let mut client = run(&traces).await;
+
+    let context = Context::current_with_span(traces.tracer().start("client"));
+    let response = client
+        .greet("Ada".to_string())
+        .with_context(context.clone())
+        .await
+        .unwrap();
+    assert_eq!("Hello Ada", response.into_inner());
+    context.span().end();
+
+    assert_child(&traces.span("client"), &traces.span("greet"));
+}
+
+#[tokio::test]
+async fn test_no_context() {
+    let traces = Traces::new();
+    let mut client = run(&traces).await;
+
+    client.greet("Ada".to_string()).await.unwrap();
+    assert_eq!(SpanId::INVALID, traces.span("greet").parent_span_id);
+}
+
+#[tokio::test]
+async fn test_streaming() {
+    let traces = Traces::new();
+    let mut client = run(&traces).await;
+
+    let context = Context::current_with_span(traces.tracer().start("client"));
+    let mut letters = client
+        .letters("Ada".to_string())
+        .with_context(context.clone())
+        .await
+        .unwrap()
+        .into_inner();
+    let mut received = String::new();
+    while let Some(letter) = letters.message().await.unwrap() {
+        received.push(letter);
+    }
+    assert_eq!("Ada", received);
+
+    let names = ["Ada", "Grace"].map(str::to_string);
+    let count = client
+        .count(tokio_stream::iter(names))
+        .with_context(context.clone())
+        .await
+        .unwrap();
+    assert_eq!(2, count.into_inner());
+    context.span().end();
+
+    let client = traces.span("client");
+    for name in [
+        "letter A",
+        "letter d",
+        "letter a",
+        "count Ada",
+        "count Grace",
+    ] {
+        assert_child(&client, &traces.span(name));
+    }
+}
+
+#[tokio::test]
+async fn test_traceparent_versions() {
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const SPAN_ID: &str = "00f067aa0ba902b7";
+    for (traceparent, valid) in [
+        (format!("00-{}-{}-01", TRACE_ID, SPAN_ID), true),
+        // Later versions may add fields after the flags.
+        (format!("01-{}-{}-01", TRACE_ID, SPAN_ID), true),
+        (
+            format!("cc-{}-{}-01-what-the-future-holds", TRACE_ID, SPAN_ID),
+            true,
+        ),
+        (format!("00-{}-{}-01-extra", TRACE_ID, SPAN_ID), false),
+        (format!("ff-{}-{}-01", TRACE_ID, SPAN_ID), false),
+        (format!("0-{}-{}-01", TRACE_ID, SPAN_ID), false),
+    ] {
+        let traces = Traces::new();
+        let mut client = run(&traces).await;
+        let mut request = tonic::Request::new("Ada".to_string());
+        request
+            .metadata_mut()
+            .insert("traceparent", traceparent.parse().unwrap());
+        client.greet(request).await.unwrap();
+
+        let greet = traces.span("greet");
+        if valid {
+            assert_eq!(
+                TraceId::from_hex(TRACE_ID).unwrap(),
+                greet.span_context.trace_id(),
+                "{}",
+                traceparent
+            );
+            assert_eq!(
+                SpanId::from_hex(SPAN_ID).unwrap(),
+                greet.parent_span_id,
+                "{}",
+                traceparent
+            );
+        } else {
+            assert_eq!(SpanId::INVALID, greet.parent_span_id, "{}", traceparent);
+        }
+    }
+}
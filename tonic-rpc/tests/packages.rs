@@ -0,0 +1,91 @@
+#![cfg(feature = "json")]
+
+use tonic::transport::NamedService;
+
+mod util;
+
+mod v1 {
+    #[tonic_rpc::tonic_rpc(json, package = "versioned.v1")]
+    pub trait Increment {
+        fn increment(arg: i32) -> i32;
+    }
+
+    pub struct State;
+
+    #[tonic::async_trait]
+    impl increment_server::Increment for State {
+        async fn increment(
+            &self,
+            request: tonic::Request<i32>,
+        ) -> Result<tonic::Response<i32>, tonic::Status> {
+            Ok(tonic::Response::new(request.into_inner() + 1))
+        }
+    }
+}
+
+mod v2 {
+    #[tonic_rpc::tonic_rpc(json, package = "versioned.v2")]
+    pub trait Increment {
+        fn increment(arg: i32, by: i32) -> i32;
+    }
+
+    pub struct State;
+
+    #[tonic::async_trait]
+    impl increment_server::Increment for State {
+        async fn increment(
+            &self,
+            request: tonic::Request<(i32, i32)>,
+        ) -> Result<tonic::Response<i32>, tonic::Status> {
+            let (arg, by) = request.into_inner();
+            Ok(tonic::Response::new(arg + by))
+        }
+    }
+}
+
+#[test]
+fn test_package_in_service_name() {
+    assert_eq!(
+        "versioned.v1.Increment",
+        <v1::increment_server::IncrementServer<v1::State> as NamedService>::NAME
+    );
+    assert_eq!(
+        "versioned.v2.Increment",
+        <v2::increment_server::IncrementServer<v2::State> as NamedService>::NAME
+    );
+}
+
+#[tokio::test]
+async fn test_versions_side_by_side() {
+    let listener = tokio::net::TcpListener::bind("[::1]:0").await.unwrap();
+    let addr = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(v1::increment_server::IncrementServer::new(v1::State))
+            .add_service(v2::increment_server::IncrementServer::new(v2::State))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+
+    let mut v1_client = v1::increment_client::IncrementClient::connect(addr.clone())
+        .await
+        .expect("Failed to connect");
+    let mut v2_client = v2::increment_client::IncrementClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    assert_eq!(6, v1_client.increment(5).await.unwrap().into_inner());
+    assert_eq!(15, v2_client.increment((5, 10)).await.unwrap().into_inner());
+}
+
+#[tokio::test]
+async fn test_package_mismatch_is_unimplemented() {
+    let addr = util::run_server(v1::increment_server::IncrementServer::new(v1::State)).await;
+    let mut client = v2::increment_client::IncrementClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    let status = client.increment((5, 10)).await.unwrap_err();
+    assert_eq!(tonic::Code::Unimplemented, status.code());
+}
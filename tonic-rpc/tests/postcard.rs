@@ -0,0 +1,40 @@
+#![cfg(feature = "postcard")]
+
+use tonic_rpc::tonic_rpc;
+
+mod util;
+
+#[tonic_rpc(postcard)]
+trait Math {
+    fn add(x: i32, y: i32) -> i32;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl math_server::Math for State {
+    async fn add(
+        &self,
+        request: tonic::Request<(i32, i32)>,
+    ) -> Result<tonic::Response<i32>, tonic::Status> {
+        let (x, y) = request.into_inner();
+        Ok(tonic::Response::new(x + y))
+    }
+}
+
+#[tokio::test]
+async fn test_postcard_codec() {
+    let addr = util::run_server(math_server::MathServer::new(State)).await;
+    let mut client = math_client::MathClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    assert_eq!(
+        77,
+        client
+            .add(tonic::Request::new((42_i32, 35_i32)))
+            .await
+            .expect("Failed to send request")
+            .into_inner()
+    );
+}
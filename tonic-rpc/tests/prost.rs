@@ -0,0 +1,162 @@
+#![cfg(feature = "prost")]
+
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic_rpc::tonic_rpc;
+
+mod util;
+
+#[derive(Clone, PartialEq, prost::Message, tonic_rpc::proto::ProtoMessage)]
+pub struct IncRequest {
+    #[prost(int32, tag = "1")]
+    pub num: i32,
+    /// Added to `num` instead of one, if set.
+    #[prost(message, optional, tag = "2")]
+    pub step: Option<Step>,
+}
+
+/// How much to add.
+#[derive(Clone, PartialEq, prost::Message, tonic_rpc::proto::ProtoMessage)]
+pub struct Step {
+    #[prost(int32, tag = "1")]
+    pub by: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message, tonic_rpc::proto::ProtoMessage)]
+pub struct IncResponse {
+    #[prost(int32, tag = "1")]
+    pub num: i32,
+    #[prost(string, repeated, tag = "2")]
+    pub steps: Vec<String>,
+}
+
+#[tonic_rpc(prost, package = "counter.v1")]
+trait Counter {
+    /// Add one to the number.
+    fn increment(arg: IncRequest) -> IncResponse;
+
+    /** Reset the "counter",
+    ignoring the requests. */
+    #[client_streaming]
+    fn reset(arg: IncRequest) -> ();
+
+    fn name(arg: ()) -> String;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl counter_server::Counter for State {
+    async fn increment(
+        &self,
+        request: tonic::Request<IncRequest>,
+    ) -> Result<tonic::Response<IncResponse>, tonic::Status> {
+        let request = request.into_inner();
+        let step = request.step.map_or(1, |step| step.by);
+        Ok(tonic::Response::new(IncResponse {
+            num: request.num + step,
+            steps: vec![step.to_string()],
+        }))
+    }
+
+    async fn reset(
+        &self,
+        _request: tonic::Request<tonic::Streaming<IncRequest>>,
+    ) -> Result<tonic::Response<()>, tonic::Status> {
+        Ok(tonic::Response::new(()))
+    }
+
+    async fn name(
+        &self,
+        _request: tonic::Request<()>,
+    ) -> Result<tonic::Response<String>, tonic::Status> {
+        Ok(tonic::Response::new("counter".to_string()))
+    }
+}
+
+#[test]
+fn test_generated_proto() {
+    assert_eq!(
+        "syntax = \"proto3\";
+
+package counter.v1;
+
+import \"google/protobuf/empty.proto\";
+import \"google/protobuf/wrappers.proto\";
+
+service Counter {
+  // Add one to the number.
+  rpc Increment(IncRequest) returns (IncResponse);
+  // Reset the \"counter\",
+  //    ignoring the requests.
+  rpc Reset(stream IncRequest) returns (google.protobuf.Empty);
+  rpc Name(google.protobuf.Empty) returns (google.protobuf.StringValue);
+}
+
+message IncRequest {
+  int32 num = 1;
+  // Added to `num` instead of one, if set.
+  Step step = 2;
+}
+
+// How much to add.
+message Step {
+  int32 by = 1;
+}
+
+message IncResponse {
+  int32 num = 1;
+  repeated string steps = 2;
+}
+",
+        counter_proto()
+    );
+}
+
+#[tokio::test]
+async fn test_prost_codec() {
+    let addr = util::run_server(counter_server::CounterServer::new(State)).await;
+    let mut client = counter_client::CounterClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    let response = client
+        .increment(IncRequest {
+            num: 40,
+            step: Some(Step { by: 2 }),
+        })
+        .await
+        .expect("Failed to send request");
+    assert_eq!(
+        IncResponse {
+            num: 42,
+            steps: vec!["2".to_string()],
+        },
+        response.into_inner()
+    );
+
+    let response = client.name(()).await.expect("Failed to send request");
+    assert_eq!("counter", response.into_inner());
+}
+
+/// Call the service the way a client generated from `counter_proto()` would.
+#[tokio::test]
+async fn test_plain_tonic_client() {
+    let addr = util::run_server(counter_server::CounterServer::new(State)).await;
+    let channel = tonic::transport::Endpoint::new(addr)
+        .unwrap()
+        .connect()
+        .await
+        .expect("Failed to connect");
+    let mut client = tonic::client::Grpc::new(channel);
+    client.ready().await.unwrap();
+
+    let response: tonic::Response<IncResponse> = client
+        .unary(
+            tonic::Request::new(IncRequest { num: 1, step: None }),
+            PathAndQuery::from_static("/counter.v1.Counter/Increment"),
+            tonic::codec::ProstCodec::default(),
+        )
+        .await
+        .expect("Failed to send request");
+    assert_eq!(2, response.into_inner().num);
+}
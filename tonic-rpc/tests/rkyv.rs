@@ -0,0 +1,109 @@
+#![cfg(feature = "rkyv")]
+
+use rkyv::{Archive, Deserialize, Serialize};
+use tonic_rpc::{codec::Archived, tonic_rpc};
+
+mod util;
+
+#[derive(Debug, PartialEq, Archive, Serialize, Deserialize)]
+#[archive(check_bytes)]
+#[archive_attr(derive(Debug))]
+pub struct Snapshot {
+    version: u64,
+    entries: Vec<(String, u32)>,
+}
+
+#[tonic_rpc(rkyv)]
+trait Snapshots {
+    fn snapshot(version: u64) -> Snapshot;
+
+    fn total(snapshot: Snapshot) -> u32;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl snapshots_server::Snapshots for State {
+    async fn snapshot(
+        &self,
+        request: tonic::Request<Archived<u64>>,
+    ) -> Result<tonic::Response<Archived<Snapshot>>, tonic::Status> {
+        let version = *request.get_ref().get();
+        let snapshot = Snapshot {
+            version,
+            entries: vec![("a".to_string(), 1), ("b".to_string(), 2)],
+        };
+        Ok(tonic::Response::new(Archived::new(&snapshot)?))
+    }
+
+    async fn total(
+        &self,
+        request: tonic::Request<Archived<Snapshot>>,
+    ) -> Result<tonic::Response<Archived<u32>>, tonic::Status> {
+        let total = request
+            .get_ref()
+            .get()
+            .entries
+            .iter()
+            .map(|entry| entry.1)
+            .sum();
+        Ok(tonic::Response::new(Archived::new(&total)?))
+    }
+}
+
+#[tokio::test]
+async fn test_rkyv_codec() {
+    let addr = util::run_server(snapshots_server::SnapshotsServer::new(State)).await;
+    let mut client = snapshots_client::SnapshotsClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    let snapshot = client
+        .snapshot(Archived::new(&7).unwrap())
+        .await
+        .expect("Failed to send request")
+        .into_inner();
+    assert_eq!(7, snapshot.get().version);
+    assert_eq!("b", snapshot.get().entries[1].0.as_str());
+
+    let snapshot = snapshot.deserialize();
+    assert_eq!(
+        Snapshot {
+            version: 7,
+            entries: vec![("a".to_string(), 1), ("b".to_string(), 2)],
+        },
+        snapshot
+    );
+
+    let total = client
+        .total(Archived::new(&snapshot).unwrap())
+        .await
+        .expect("Failed to send request")
+        .into_inner();
+    assert_eq!(3, *total.get());
+}
+
+#[tokio::test]
+async fn test_invalid_archive() {
+    use tonic::codegen::http::uri::PathAndQuery;
+
+    let addr = util::run_server(snapshots_server::SnapshotsServer::new(State)).await;
+    let channel = tonic::transport::Endpoint::new(addr)
+        .unwrap()
+        .connect()
+        .await
+        .expect("Failed to connect");
+    let mut client = tonic::client::Grpc::new(channel);
+    client.ready().await.unwrap();
+
+    // A `u64` is too short to be a valid archived `Snapshot`.
+    let status = client
+        .unary::<_, Archived<u32>, _>(
+            tonic::Request::new(Archived::new(&7_u64).unwrap()),
+            PathAndQuery::from_static("/Snapshots/Total"),
+            tonic_rpc::codec::RkyvCodec::default(),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(tonic::Code::Internal, status.code());
+}
@@ -0,0 +1,98 @@
+#![cfg(feature = "ron")]
+
+use serde::{Deserialize, Serialize};
+use tonic_rpc::{
+    codec::{RonSerdeCodec, SerdeCodec},
+    tonic_rpc,
+};
+
+mod util;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Meters(f64);
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Point(i32, i32);
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum Shape {
+    Empty,
+    Circle(Meters),
+    Line { from: Point, to: Point },
+}
+
+#[tonic_rpc(ron)]
+trait Shapes {
+    fn scale(shape: Shape, factor: i32) -> Shape;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl shapes_server::Shapes for State {
+    async fn scale(
+        &self,
+        request: tonic::Request<(Shape, i32)>,
+    ) -> Result<tonic::Response<Shape>, tonic::Status> {
+        let (shape, factor) = request.into_inner();
+        let scaled = match shape {
+            Shape::Empty => Shape::Empty,
+            Shape::Circle(Meters(r)) => Shape::Circle(Meters(r * factor as f64)),
+            Shape::Line { from, to } => Shape::Line {
+                from: Point(from.0 * factor, from.1 * factor),
+                to: Point(to.0 * factor, to.1 * factor),
+            },
+        };
+        Ok(tonic::Response::new(scaled))
+    }
+}
+
+#[test]
+fn test_readable_encoding() {
+    let mut bytes = Vec::new();
+    RonSerdeCodec
+        .write(
+            (
+                Shape::Line {
+                    from: Point(0, 1),
+                    to: Point(2, 3),
+                },
+                2,
+            ),
+            &mut bytes,
+        )
+        .unwrap();
+    assert_eq!(
+        "(Line(\n    from: Point(0, 1),\n    to: Point(2, 3),\n), 2)",
+        String::from_utf8(bytes).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_ron_codec() {
+    let addr = util::run_server(shapes_server::ShapesServer::new(State)).await;
+    let mut client = shapes_client::ShapesClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    for (shape, expected) in [
+        (Shape::Empty, Shape::Empty),
+        (Shape::Circle(Meters(1.5)), Shape::Circle(Meters(3.0))),
+        (
+            Shape::Line {
+                from: Point(0, 1),
+                to: Point(2, 3),
+            },
+            Shape::Line {
+                from: Point(0, 2),
+                to: Point(4, 6),
+            },
+        ),
+    ] {
+        let response = client
+            .scale((shape, 2))
+            .await
+            .expect("Failed to send request");
+        assert_eq!(expected, response.into_inner());
+    }
+}
@@ -0,0 +1,111 @@
+#![cfg(all(feature = "stdio", feature = "json"))]
+
+use tokio::io::{duplex, split};
+use tonic_rpc::{
+    tonic_rpc,
+    transport::stdio::{self, Pipe},
+};
+
+#[tonic_rpc(json)]
+trait Plugin {
+    fn transform(text: String) -> String;
+
+    #[server_streaming]
+    fn lines(text: String) -> String;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl plugin_server::Plugin for State {
+    type LinesStream = tokio_stream::Iter<std::vec::IntoIter<Result<String, tonic::Status>>>;
+
+    async fn transform(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<String>, tonic::Status> {
+        Ok(tonic::Response::new(request.into_inner().to_uppercase()))
+    }
+
+    async fn lines(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<Self::LinesStream>, tonic::Status> {
+        let lines: Vec<_> = request
+            .into_inner()
+            .lines()
+            .map(str::to_string)
+            .map(Ok)
+            .collect();
+        Ok(tonic::Response::new(tokio_stream::iter(lines)))
+    }
+}
+
+#[tokio::test]
+async fn test_pipe_transport() {
+    // Each end of the duplex stands for the stdio of one process.
+    let (host, plugin) = duplex(4096);
+    let (plugin_reader, plugin_writer) = split(plugin);
+    let server = tokio::spawn(stdio::serve_pipe(
+        plugin_server::PluginServer::new(State),
+        Pipe::new(plugin_reader, plugin_writer),
+    ));
+
+    let (host_reader, host_writer) = split(host);
+    let channel = stdio::connect(Pipe::new(host_reader, host_writer))
+        .await
+        .expect("Failed to connect");
+    let mut client = plugin_client::PluginClient::new(channel);
+
+    let response = client
+        .transform("plugin".to_string())
+        .await
+        .expect("Failed to send request");
+    assert_eq!("PLUGIN", response.into_inner());
+
+    let mut lines = client
+        .lines("a\nb\nc".to_string())
+        .await
+        .expect("Failed to send request")
+        .into_inner();
+    let mut received = Vec::new();
+    while let Some(line) = lines.message().await.unwrap() {
+        received.push(line);
+    }
+    assert_eq!(vec!["a", "b", "c"], received);
+
+    // The server stops once the connection is closed.
+    drop((lines, client));
+    server.await.unwrap().expect("Failed to serve");
+}
+
+#[tokio::test]
+async fn test_closed_pipe() {
+    let (host, plugin) = duplex(4096);
+    drop(plugin);
+    let (host_reader, host_writer) = split(host);
+    assert!(stdio::connect(Pipe::new(host_reader, host_writer))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_host_closes_pipe() {
+    let (host, plugin) = duplex(4096);
+    let (plugin_reader, plugin_writer) = split(plugin);
+    let server = tokio::spawn(stdio::serve_pipe(
+        plugin_server::PluginServer::new(State),
+        Pipe::new(plugin_reader, plugin_writer),
+    ));
+    drop(host);
+    server.await.unwrap().expect("Failed to serve");
+}
+
+#[tokio::test]
+async fn test_child_without_pipes() {
+    let mut child = tokio::process::Command::new("true")
+        .spawn()
+        .expect("Failed to spawn");
+    assert!(Pipe::child(&mut child).is_err());
+    child.wait().await.unwrap();
+}
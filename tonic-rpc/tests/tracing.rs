@@ -0,0 +1,202 @@
+#![cfg(all(feature = "tracing", feature = "json"))]
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use tonic_rpc::tonic_rpc;
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Event, Metadata, Subscriber,
+};
+use tracing_core::span::Current;
+
+mod util;
+
+#[tonic_rpc(json)]
+trait Greeter {
+    fn greet(name: String) -> String;
+
+    #[server_streaming]
+    fn letters(name: String) -> char;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl greeter_server::Greeter for State {
+    type LettersStream = tokio_stream::Iter<std::vec::IntoIter<Result<char, tonic::Status>>>;
+
+    async fn greet(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<String>, tonic::Status> {
+        let name = request.into_inner();
+        if name.is_empty() {
+            return Err(tonic::Status::not_found("Nobody to greet"));
+        }
+        Ok(tonic::Response::new(format!("Hello {}", name)))
+    }
+
+    async fn letters(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<Self::LettersStream>, tonic::Status> {
+        let mut letters = Vec::new();
+        for c in request.into_inner().chars() {
+            letters.push(match c {
+                '!' => Err(tonic::Status::invalid_argument("Not a letter")),
+                c => Ok(c),
+            });
+        }
+        Ok(tonic::Response::new(tokio_stream::iter(letters)))
+    }
+}
+
+#[derive(Debug)]
+struct SpanData {
+    metadata: &'static Metadata<'static>,
+    fields: HashMap<&'static str, String>,
+}
+
+/// Collects the spans and their fields, and tracks the spans entered on this thread.
+#[derive(Clone, Default)]
+struct Spans {
+    spans: Arc<Mutex<Vec<SpanData>>>,
+    entered: Arc<Mutex<Vec<Id>>>,
+}
+
+impl Spans {
+    fn find(&self, target: &str, name: &str) -> Vec<HashMap<&'static str, String>> {
+        self.spans
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|span| span.metadata.target() == target && span.metadata.name() == name)
+            .map(|span| span.fields.clone())
+            .collect()
+    }
+}
+
+struct Fields<'a>(&'a mut HashMap<&'static str, String>);
+
+impl Visit for Fields<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.insert(field.name(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for Spans {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let mut span = SpanData {
+            metadata: attributes.metadata(),
+            fields: HashMap::new(),
+        };
+        attributes.record(&mut Fields(&mut span.fields));
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(span);
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, id: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        let span = &mut spans[id.into_u64() as usize - 1];
+        values.record(&mut Fields(&mut span.fields));
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        self.entered.lock().unwrap().push(span.clone());
+    }
+
+    fn exit(&self, _span: &Id) {
+        self.entered.lock().unwrap().pop();
+    }
+
+    fn current_span(&self) -> Current {
+        match self.entered.lock().unwrap().last() {
+            Some(id) => {
+                let metadata = self.spans.lock().unwrap()[id.into_u64() as usize - 1].metadata;
+                Current::new(id.clone(), metadata)
+            }
+            None => Current::none(),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_spans() {
+    // The test runtime runs the server and the client on this thread.
+    let spans = Spans::default();
+    let _guard = tracing::subscriber::set_default(spans.clone());
+
+    let addr = util::run_server(greeter_server::GreeterServer::new(State)).await;
+    let mut client = greeter_client::GreeterClient::connect(addr).await.unwrap();
+    let response = client.greet("Ada".to_string()).await.unwrap();
+    assert_eq!("Hello Ada", response.into_inner());
+    let status = client.greet(String::new()).await.unwrap_err();
+    assert_eq!(tonic::Code::NotFound, status.code());
+
+    for target in ["tonic_rpc::client", "tonic_rpc::server"] {
+        let calls = spans.find(target, "Greeter/Greet");
+        assert_eq!(2, calls.len(), "{}: {:?}", target, calls);
+        for call in &calls {
+            assert_eq!("unary", call["kind"]);
+            assert!(call["codec"].ends_with("JsonSerdeCodec"), "{:?}", call);
+            assert!(call["latency_us"].parse::<u64>().is_ok(), "{:?}", call);
+        }
+        // The requests are `"Ada"` and `""` in JSON.
+        assert_eq!("5", calls[0]["request_size"], "{}", target);
+        assert_eq!("Ok", calls[0]["code"], "{}", target);
+        assert_eq!("2", calls[1]["request_size"], "{}", target);
+        assert_eq!("NotFound", calls[1]["code"], "{}", target);
+    }
+
+    let mut letters = client
+        .letters("Ada".to_string())
+        .await
+        .unwrap()
+        .into_inner();
+    while letters.message().await.unwrap().is_some() {}
+    for target in ["tonic_rpc::client", "tonic_rpc::server"] {
+        let calls = spans.find(target, "Greeter/Letters");
+        assert_eq!(1, calls.len(), "{}: {:?}", target, calls);
+        assert_eq!("server_streaming", calls[0]["kind"]);
+        assert_eq!("Ok", calls[0]["code"]);
+    }
+
+    // The server records the status of a stream once it's sent, after the messages, and the
+    // client once it's received.
+    let mut letters = client.letters("A!".to_string()).await.unwrap().into_inner();
+    let calls = spans.find("tonic_rpc::client", "Greeter/Letters");
+    assert!(!calls[1].contains_key("code"), "{:?}", calls);
+    assert_eq!(Some('A'), letters.message().await.unwrap());
+    let status = letters.message().await.unwrap_err();
+    assert_eq!(tonic::Code::InvalidArgument, status.code());
+    for target in ["tonic_rpc::client", "tonic_rpc::server"] {
+        let calls = spans.find(target, "Greeter/Letters");
+        assert_eq!(2, calls.len(), "{}: {:?}", target, calls);
+        assert_eq!("InvalidArgument", calls[1]["code"], "{}", target);
+        assert!(calls[1]["latency_us"].parse::<u64>().is_ok(), "{:?}", calls);
+    }
+
+    // A stream dropped before it ends is cancelled.
+    let letters = client.letters("Ada".to_string()).await.unwrap();
+    drop(letters);
+    let calls = spans.find("tonic_rpc::client", "Greeter/Letters");
+    assert_eq!("Cancelled", calls[2]["code"], "{:?}", calls);
+}
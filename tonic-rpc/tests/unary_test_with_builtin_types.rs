@@ -52,5 +52,5 @@ async fn test_math_with_builtins() {
     assert_eq!(77, response.into_inner());
     let request = (23.1, 0.01);
     let response = client.geq(request).await.expect("Failed to send request");
-    assert_eq!(true, response.into_inner());
+    assert!(response.into_inner());
 }
@@ -0,0 +1,118 @@
+#![cfg(all(unix, feature = "unix", feature = "json"))]
+
+use std::{
+    io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use tokio::sync::oneshot;
+use tonic::transport::Server;
+use tonic_rpc::{tonic_rpc, transport::unix::UnixSocket};
+
+#[tonic_rpc(json)]
+trait Sidecar {
+    fn ping(n: u32) -> u32;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl sidecar_server::Sidecar for State {
+    async fn ping(
+        &self,
+        request: tonic::Request<u32>,
+    ) -> Result<tonic::Response<u32>, tonic::Status> {
+        Ok(tonic::Response::new(request.into_inner() + 1))
+    }
+}
+
+fn socket_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("tonic-rpc-{}-{}.sock", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn router() -> tonic::transport::server::Router {
+    Server::builder().add_service(sidecar_server::SidecarServer::new(State))
+}
+
+async fn wait_for_socket(path: &Path) {
+    while !path.exists() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_unix_socket() {
+    let path = socket_path("serve");
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let server = tokio::spawn(
+        UnixSocket::new(&path)
+            .permissions(0o600)
+            .serve_with_shutdown(router(), async {
+                let _ = stop_rx.await;
+            }),
+    );
+    wait_for_socket(&path).await;
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(0o600, mode & 0o777);
+    // The private directory the socket was bound in is removed.
+    let private_prefix = format!(".{}.", path.file_name().unwrap().to_string_lossy());
+    assert!(!std::fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .any(|entry| entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with(&private_prefix)));
+
+    let mut client = sidecar_client::SidecarClient::connect_unix(&path)
+        .await
+        .expect("Failed to connect");
+    let response = client.ping(41).await.expect("Failed to send request");
+    assert_eq!(42, response.into_inner());
+
+    // The socket file is removed when the server stops.
+    stop_tx.send(()).unwrap();
+    drop(client);
+    server.await.unwrap().expect("Failed to serve");
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn test_stale_socket() {
+    let path = socket_path("stale");
+    // A server which exited without removing its socket.
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+
+    tokio::spawn(tonic_rpc::serve_unix(path.clone(), router()));
+    let mut client = loop {
+        match sidecar_client::SidecarClient::connect_unix(&path).await {
+            Ok(client) => break client,
+            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    };
+    let response = client.ping(1).await.expect("Failed to send request");
+    assert_eq!(2, response.into_inner());
+
+    // The socket of a running server isn't replaced.
+    let err = tonic_rpc::serve_unix(&path, router()).await.unwrap_err();
+    let err = err.downcast::<io::Error>().unwrap();
+    assert_eq!(io::ErrorKind::AddrInUse, err.kind());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_not_a_socket() {
+    let path = socket_path("file");
+    std::fs::write(&path, "data").unwrap();
+    let err = tonic_rpc::serve_unix(&path, router()).await.unwrap_err();
+    let err = err.downcast::<io::Error>().unwrap();
+    assert_eq!(io::ErrorKind::AlreadyExists, err.kind());
+    // The file is left untouched.
+    assert_eq!("data", std::fs::read_to_string(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,313 @@
+#![cfg(all(feature = "websocket", feature = "json"))]
+
+use std::time::Duration;
+
+use hyper::{body::to_bytes, header, Body, Client, Request, StatusCode};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::transport::Server;
+use tonic_rpc::{
+    tonic_rpc,
+    transport::websocket::{WebSocket, WebSocketChannel},
+};
+
+#[tonic_rpc(json)]
+trait Chat {
+    fn shout(text: String) -> String;
+
+    #[server_streaming]
+    fn letters(text: String) -> char;
+
+    #[client_streaming]
+    fn join(word: String) -> String;
+
+    #[server_streaming]
+    #[client_streaming]
+    fn echo(text: String) -> String;
+
+    fn sleep(millis: u64) -> u64;
+}
+
+struct State;
+
+#[tonic::async_trait]
+impl chat_server::Chat for State {
+    type LettersStream = tokio_stream::Iter<std::vec::IntoIter<Result<char, tonic::Status>>>;
+    type EchoStream = ReceiverStream<Result<String, tonic::Status>>;
+
+    async fn shout(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<String>, tonic::Status> {
+        let text = request.into_inner();
+        if text.is_empty() {
+            return Err(tonic::Status::invalid_argument("Nothing to shout"));
+        }
+        Ok(tonic::Response::new(text.to_uppercase()))
+    }
+
+    async fn letters(
+        &self,
+        request: tonic::Request<String>,
+    ) -> Result<tonic::Response<Self::LettersStream>, tonic::Status> {
+        let letters: Vec<_> = request.into_inner().chars().map(Ok).collect();
+        Ok(tonic::Response::new(tokio_stream::iter(letters)))
+    }
+
+    async fn join(
+        &self,
+        request: tonic::Request<tonic::Streaming<String>>,
+    ) -> Result<tonic::Response<String>, tonic::Status> {
+        let mut words = request.into_inner();
+        let mut joined = Vec::new();
+        while let Some(word) = words.message().await? {
+            joined.push(word);
+        }
+        Ok(tonic::Response::new(joined.join(" ")))
+    }
+
+    async fn echo(
+        &self,
+        request: tonic::Request<tonic::Streaming<String>>,
+    ) -> Result<tonic::Response<Self::EchoStream>, tonic::Status> {
+        let mut texts = request.into_inner();
+        let (tx, rx) = mpsc::channel(4);
+        tokio::spawn(async move {
+            while let Ok(Some(text)) = texts.message().await {
+                if tx.send(Ok(text.repeat(2))).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(tonic::Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn sleep(
+        &self,
+        request: tonic::Request<u64>,
+    ) -> Result<tonic::Response<u64>, tonic::Status> {
+        let millis = request.into_inner();
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        Ok(tonic::Response::new(millis))
+    }
+}
+
+/// Returns the address of the server.
+async fn run_websocket() -> String {
+    serve(WebSocket::new(chat_server::ChatServer::new(State))).await
+}
+
+/// Returns the address of the server.
+async fn serve(websocket: WebSocket<chat_server::ChatServer<State>>) -> String {
+    let listener = TcpListener::bind("[::1]:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        Server::builder()
+            .accept_http1(true)
+            .add_service(websocket)
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+    addr.to_string()
+}
+
+async fn connect(addr: &str) -> chat_client::ChatClient<WebSocketChannel> {
+    let channel = WebSocketChannel::connect(format!("ws://{}/Chat/ws", addr))
+        .await
+        .expect("Failed to connect");
+    chat_client::ChatClient::new(channel)
+}
+
+#[tokio::test]
+async fn test_unary() {
+    let addr = run_websocket().await;
+    let mut client = connect(&addr).await;
+    let response = client
+        .shout("hello".to_string())
+        .await
+        .expect("Failed to send request");
+    assert_eq!("HELLO", response.into_inner());
+
+    let status = client.shout(String::new()).await.unwrap_err();
+    assert_eq!(tonic::Code::InvalidArgument, status.code());
+    assert_eq!("Nothing to shout", status.message());
+}
+
+#[tokio::test]
+async fn test_streaming() {
+    let addr = run_websocket().await;
+    let mut client = connect(&addr).await;
+
+    let mut letters = client
+        .letters("abc".to_string())
+        .await
+        .expect("Failed to send request")
+        .into_inner();
+    let mut received = Vec::new();
+    while let Some(letter) = letters.message().await.unwrap() {
+        received.push(letter);
+    }
+    assert_eq!(vec!['a', 'b', 'c'], received);
+
+    let words = ["over", "a", "websocket"].map(str::to_string);
+    let response = client
+        .join(tokio_stream::iter(words))
+        .await
+        .expect("Failed to send request");
+    assert_eq!("over a websocket", response.into_inner());
+
+    let (tx, rx) = mpsc::channel(4);
+    let mut echoes = client
+        .echo(ReceiverStream::new(rx))
+        .await
+        .expect("Failed to send request")
+        .into_inner();
+    for text in ["ping", "pong"] {
+        tx.send(text.to_string()).await.unwrap();
+        assert_eq!(Some(text.repeat(2)), echoes.message().await.unwrap());
+    }
+    drop(tx);
+    assert_eq!(None, echoes.message().await.unwrap());
+}
+
+#[tokio::test]
+async fn test_multiplexing() {
+    let addr = run_websocket().await;
+    let client = connect(&addr).await;
+
+    // A slow call doesn't hold up the ones sent after it on the same connection.
+    let mut slow_client = client.clone();
+    let slow = tokio::spawn(async move { slow_client.sleep(200).await });
+    let mut fast_client = client.clone();
+    let fast = tokio::time::timeout(Duration::from_millis(100), fast_client.sleep(0))
+        .await
+        .expect("Call was held up")
+        .expect("Failed to send request");
+    assert_eq!(0, fast.into_inner());
+    assert_eq!(200, slow.await.unwrap().unwrap().into_inner());
+}
+
+#[tokio::test]
+async fn test_overflow() {
+    let addr = run_websocket().await;
+    let mut client = connect(&addr).await;
+
+    // A call which doesn't read its responses fails instead of holding up the connection.
+    let mut letters = client
+        .letters("a".repeat(100))
+        .await
+        .expect("Failed to send request")
+        .into_inner();
+    let response = tokio::time::timeout(Duration::from_millis(500), client.shout("hi".to_string()))
+        .await
+        .expect("Call was held up")
+        .expect("Failed to send request");
+    assert_eq!("HI", response.into_inner());
+    let status = loop {
+        match letters.message().await {
+            Ok(Some(_)) => continue,
+            Ok(None) => panic!("Call succeeded"),
+            Err(status) => break status,
+        }
+    };
+    assert_eq!(tonic::Code::ResourceExhausted, status.code());
+}
+
+#[tokio::test]
+async fn test_max_concurrent_streams() {
+    let websocket = WebSocket::new(chat_server::ChatServer::new(State)).max_concurrent_streams(1);
+    let addr = serve(websocket).await;
+    let client = connect(&addr).await;
+
+    let mut slow_client = client.clone();
+    let slow = tokio::spawn(async move { slow_client.sleep(200).await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let status = client.clone().sleep(0).await.unwrap_err();
+    assert_eq!(tonic::Code::ResourceExhausted, status.code());
+    assert_eq!(200, slow.await.unwrap().unwrap().into_inner());
+
+    // The stream is available again once the call completes.
+    assert_eq!(0, client.clone().sleep(0).await.unwrap().into_inner());
+}
+
+#[tokio::test]
+async fn test_async_trait() {
+    use chat_async::ChatAsync;
+
+    let addr = run_websocket().await;
+    let client = connect(&addr).await;
+    assert_eq!("TRAIT", client.shout("trait".to_string()).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_grpc_passthrough() {
+    let addr = run_websocket().await;
+    let mut client = chat_client::ChatClient::connect(format!("http://{}", addr))
+        .await
+        .expect("Failed to connect");
+    let response = client
+        .shout("grpc".to_string())
+        .await
+        .expect("Failed to send request");
+    assert_eq!("GRPC", response.into_inner());
+}
+
+#[tokio::test]
+async fn test_handshake() {
+    let addr = run_websocket().await;
+
+    // The example handshake of RFC 6455.
+    let mut stream = TcpStream::connect(&addr).await.unwrap();
+    let request = format!(
+        "GET /Chat/ws HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        addr
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        response.push(stream.read_u8().await.unwrap());
+    }
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 101"), "{}", response);
+    assert!(
+        response
+            .to_lowercase()
+            .contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo="),
+        "{}",
+        response
+    );
+    assert!(
+        response.contains("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="),
+        "{}",
+        response
+    );
+
+    // Frames sent by clients must be masked: an unmasked open frame closes the connection
+    // instead of starting a call.
+    let payload = [&[0, 0, 0, 0, 0][..], b"/Chat/Shout"].concat();
+    stream
+        .write_all(&[0x82, payload.len() as u8])
+        .await
+        .unwrap();
+    stream.write_all(&payload).await.unwrap();
+    let mut received = Vec::new();
+    stream.read_to_end(&mut received).await.unwrap();
+    assert_eq!(Some(&0x88), received.first(), "{:?}", received);
+
+    // Other HTTP/1.1 requests are rejected.
+    let request = Request::get(format!("http://{}/Chat/ws", addr))
+        .header(header::ACCEPT, "application/json")
+        .body(Body::empty())
+        .unwrap();
+    let response = Client::new().request(request).await.unwrap();
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let body = to_bytes(response.into_body()).await.unwrap();
+    assert_eq!("Expected a WebSocket upgrade", body);
+}
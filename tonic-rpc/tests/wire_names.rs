@@ -0,0 +1,95 @@
+#![cfg(feature = "json")]
+
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tonic::transport::NamedService;
+use tonic_rpc::tonic_rpc;
+
+mod util;
+
+#[tonic_rpc(json, name = "UserService")]
+trait Users {
+    #[rpc(name = "GetUser")]
+    fn lookup(id: u32) -> String;
+
+    #[server_streaming]
+    #[rpc(name = "ListUsers")]
+    fn all() -> String;
+}
+
+/// A service defined elsewhere whose default wire names match the overrides above.
+mod existing {
+    #[tonic_rpc::tonic_rpc(json)]
+    pub trait UserService {
+        fn get_user(id: u32) -> String;
+
+        #[server_streaming]
+        fn list_users() -> String;
+    }
+}
+
+const NAMES: [&str; 2] = ["alice", "bob"];
+
+struct State;
+
+#[tonic::async_trait]
+impl users_server::Users for State {
+    type AllStream = ReceiverStream<Result<String, tonic::Status>>;
+
+    async fn lookup(
+        &self,
+        request: tonic::Request<u32>,
+    ) -> Result<tonic::Response<String>, tonic::Status> {
+        NAMES
+            .get(request.into_inner() as usize)
+            .map(|name| tonic::Response::new(name.to_string()))
+            .ok_or_else(|| tonic::Status::not_found("No such user"))
+    }
+
+    async fn all(
+        &self,
+        _request: tonic::Request<()>,
+    ) -> Result<tonic::Response<Self::AllStream>, tonic::Status> {
+        let (tx, rx) = tokio::sync::mpsc::channel(NAMES.len());
+        for name in NAMES {
+            tx.send(Ok(name.to_string())).await.unwrap();
+        }
+        Ok(tonic::Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+#[test]
+fn test_service_name() {
+    assert_eq!(
+        "UserService",
+        <users_server::UsersServer<State> as NamedService>::NAME
+    );
+}
+
+#[tokio::test]
+async fn test_renamed_methods() {
+    let addr = util::run_server(users_server::UsersServer::new(State)).await;
+    let mut client = users_client::UsersClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    assert_eq!("bob", client.lookup(1).await.unwrap().into_inner());
+}
+
+#[tokio::test]
+async fn test_interop_with_existing_names() {
+    let addr = util::run_server(users_server::UsersServer::new(State)).await;
+    let mut client = existing::user_service_client::UserServiceClient::connect(addr)
+        .await
+        .expect("Failed to connect");
+
+    assert_eq!("alice", client.get_user(0).await.unwrap().into_inner());
+    let users: Vec<String> = client
+        .list_users(())
+        .await
+        .unwrap()
+        .into_inner()
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    assert_eq!(NAMES.to_vec(), users);
+}